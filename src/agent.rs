@@ -1,221 +1,146 @@
 //! AI Agent module using Rig and Ollama
 
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
 use anyhow::{Context, Result};
+use futures::StreamExt;
+use futures::stream::FuturesUnordered;
+
 use rig::{
+    OneOrMany,
     client::{CompletionClient, Nothing},
-    completion::Prompt,
+    completion::message::{
+        self, AssistantContent, ImageMediaType, Message, MimeType, ToolResultContent, UserContent,
+    },
     providers::ollama,
-    tool::server::ToolServer,
+    streaming::{StreamedAssistantContent, StreamingCompletion},
+    tool::server::{ToolServer, ToolServerHandle},
 };
+use serde::{Deserialize, Serialize};
 use serde_json::json;
 use tracing::debug;
 
-use crate::config::{Config, McpServerConfig};
-
-/// Simple MCP client for HTTP POST-based servers like pcli2-mcp
-struct SimpleMcpClient {
-    client: reqwest::Client,
-    url: String,
+use crate::approval::{self, ToolApprover};
+use crate::config::{self, Config, McpServerConfig};
+use crate::mcp::{self, Attachment, HealthState, McpClient};
+use crate::tool_selection::{self, ToolIndex};
+use crate::tools;
+
+/// Attachments from MCP tool results, waiting to be picked up with their result
+///
+/// Tools only return text, so each attachment is replaced by a summary that
+/// carries its id, and [`AttachmentStore::take_for`] finds it again by that id.
+#[derive(Debug, Clone, Default)]
+pub struct AttachmentStore {
+    next_id: Arc<AtomicU64>,
+    pending: Arc<Mutex<HashMap<u64, Attachment>>>,
 }
 
-impl Clone for SimpleMcpClient {
-    fn clone(&self) -> Self {
-        Self {
-            client: self.client.clone(),
-            url: self.url.clone(),
-        }
-    }
-}
-
-impl SimpleMcpClient {
-    fn new(url: String) -> Self {
-        Self {
-            client: reqwest::Client::new(),
-            url,
-        }
-    }
-
-    async fn initialize(&self) -> Result<()> {
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": 1,
-            "method": "initialize",
-            "params": {
-                "protocolVersion": "2024-11-05",
-                "capabilities": {},
-                "clientInfo": {
-                    "name": "pcli2-rig",
-                    "version": "0.1.0"
-                }
-            }
-        });
-
-        let response = self.client
-            .post(&self.url)
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send initialize request")?;
-
-        if !response.status().is_success() {
-            anyhow::bail!("Initialize failed with status: {}", response.status());
-        }
-
-        Ok(())
-    }
-
-    async fn list_tools(&self) -> Result<Vec<rmcp::model::Tool>> {
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": 2,
-            "method": "tools/list",
-            "params": {}
-        });
-
-        let response = self.client
-            .post(&self.url)
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send tools/list request")?;
-
-        if !response.status().is_success() {
-            anyhow::bail!("tools/list failed with status: {}", response.status());
-        }
-
-        let result: serde_json::Value = response.json().await?;
-        
-        // Parse the response to extract tools
-        if let Some(result_value) = result.get("result").and_then(|r| r.get("tools")) {
-            let tools: Vec<rmcp::model::Tool> = serde_json::from_value(result_value.clone())
-                .context("Failed to parse tools response")?;
-            Ok(tools)
-        } else {
-            Ok(Vec::new())
-        }
-    }
-
-    async fn call_tool(&self, name: &str, arguments: serde_json::Value) -> Result<String> {
-        let request = json!({
-            "jsonrpc": "2.0",
-            "id": 3,
-            "method": "tools/call",
-            "params": {
-                "name": name,
-                "arguments": arguments
-            }
-        });
-
-        let response = self.client
-            .post(&self.url)
-            .json(&request)
-            .send()
-            .await
-            .context("Failed to send tools/call request")?;
-
-        if !response.status().is_success() {
-            anyhow::bail!("tools/call failed with status: {}", response.status());
-        }
-
-        let result: serde_json::Value = response.json().await?;
-
-        // Parse the response to extract tool result
-        if let Some(result_value) = result.get("result") {
-            // Check if this is a thumbnail or image-related tool call
-            // and if the result contains base64 data
-            let result_str = serde_json::to_string_pretty(result_value)?;
-            
-            // Try to detect and convert base64 image data to proper MCP image content
-            if let Some(converted) = self.try_convert_to_image_content(name, result_value).await {
-                Ok(converted)
-            } else {
-                Ok(result_str)
-            }
-        } else if let Some(error) = result.get("error") {
-            anyhow::bail!("Tool call error: {}", error);
-        } else {
-            Ok("Tool executed successfully (no result)".to_string())
+impl AttachmentStore {
+    /// Keep an attachment and return its summary for the model
+    pub fn add(&self, attachment: Attachment) -> String {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        let summary = attachment.summary(id);
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.insert(id, attachment);
         }
+        summary
     }
 
-    /// Try to convert a tool result to MCP image content if it contains base64 image data
-    /// 
-    /// This is a workaround for MCP servers that return base64 as plain text instead of
-    /// proper MCP image content. Detects thumbnail and image-related tool calls.
-    async fn try_convert_to_image_content(&self, tool_name: &str, result_value: &serde_json::Value) -> Option<String> {
-        // Check if this is likely an image/thumbnail tool
-        let is_image_tool = tool_name.to_lowercase().contains("thumbnail") 
-            || tool_name.to_lowercase().contains("image");
-        
-        if !is_image_tool {
-            return None;
-        }
-
-        // Try to extract base64 data from the result
-        let base64_data = if let Some(text) = result_value.as_str() {
-            // Result is a plain string (likely base64)
-            Some(text.to_string())
-        } else if let Some(obj) = result_value.as_object() {
-            // Result might be an object with a "text" or "data" field
-            obj.get("text")
-                .or_else(|| obj.get("data"))
-                .and_then(|v| v.as_str())
-                .map(|s| s.to_string())
-        } else {
-            None
-        }?;
-
-        // Validate that it looks like base64 (contains only valid characters and is reasonably long)
-        if base64_data.is_empty() || !base64_data.chars().all(|c| c.is_alphanumeric() || c == '+' || c == '/' || c == '=') {
-            return None;
-        }
-
-        // Determine MIME type from base64 signature or default to PNG
-        let mime_type = if base64_data.starts_with("iVBORw0KGgo") {
-            "image/png"
-        } else if base64_data.starts_with("/9j/") {
-            "image/jpeg"
-        } else if base64_data.starts_with("R0lGOD") {
-            "image/gif"
-        } else if base64_data.starts_with("UklGR") {
-            "image/webp"
-        } else {
-            "image/png" // Default assumption for thumbnails
+    /// Remove and return the attachments summarized in a tool result, in order
+    pub fn take_for(&self, result: &str) -> Vec<Attachment> {
+        let Ok(mut pending) = self.pending.lock() else {
+            return Vec::new();
         };
-
-        // Create proper MCP image content structure
-        // This wraps the base64 data in the MCP protocol format
-        let image_content = json!({
-            "content": [{
-                "type": "image",
-                "data": base64_data,
-                "mimeType": mime_type
-            }]
-        });
-
-        Some(serde_json::to_string_pretty(&image_content).ok()?)
+        let mut found: Vec<(usize, u64)> = pending
+            .keys()
+            .filter_map(|id| {
+                result
+                    .find(&format!("[attachment {}:", id))
+                    .map(|position| (position, *id))
+            })
+            .collect();
+        found.sort();
+        found
+            .into_iter()
+            .filter_map(|(_, id)| pending.remove(&id))
+            .collect()
     }
 }
 
 /// A Rig tool that wraps an MCP tool
+///
+/// The model sees the tool under its namespaced name (`server__tool`); the
+/// server is called with the tool's own name.
 #[derive(Clone)]
 struct McpRigTool {
     definition: rmcp::model::Tool,
-    client: SimpleMcpClient,
-    #[allow(dead_code)]
-    server_name: String,
+    client: McpClient,
+    /// Name exposed to the model
+    name: String,
+    attachments: AttachmentStore,
 }
 
 impl McpRigTool {
-    fn new(definition: rmcp::model::Tool, client: SimpleMcpClient, server_name: String) -> Self {
+    fn new(
+        definition: rmcp::model::Tool,
+        client: McpClient,
+        name: String,
+        attachments: AttachmentStore,
+    ) -> Self {
         Self {
             definition,
             client,
-            server_name,
+            name,
+            attachments,
         }
     }
 }
 
+/// Name a tool is exposed under: `<prefix>__<tool>`, or the raw name with an empty prefix
+fn namespaced_tool_name(prefix: &str, tool: &str) -> String {
+    if prefix.is_empty() {
+        tool.to_string()
+    } else {
+        format!("{}__{}", prefix, tool)
+    }
+}
+
+/// A tool the agent can offer to the model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolInfo {
+    /// Name the tool is exposed under
+    pub name: String,
+    /// MCP server offering the tool (`None` for built-in tools)
+    pub server: Option<String>,
+    /// Whether the tool is advertised to the model in this session
+    pub enabled: bool,
+}
+
+/// A connected MCP server and the tools it offers
+struct McpConnection {
+    client: McpClient,
+    tools: Vec<rmcp::model::Tool>,
+    /// The server's config, for its tool prefix and filters
+    server: McpServerConfig,
+}
+
+/// Tools of a server that its `include_tools` and `exclude_tools` let through
+fn allowed_tools(server: &McpServerConfig, mut tools: Vec<rmcp::model::Tool>) -> Vec<rmcp::model::Tool> {
+    let discovered = tools.len();
+    tools.retain(|tool| server.tool_allowed(&tool.name));
+    if tools.len() < discovered {
+        debug!(
+            "Filtered out {} tools from MCP server '{}'",
+            discovered - tools.len(),
+            server.name
+        );
+    }
+    tools
+}
+
 #[derive(Debug)]
 struct McpToolError(String);
 
@@ -235,7 +160,7 @@ impl rig::tool::Tool for McpRigTool {
 
     async fn definition(&self, _prompt: String) -> rig::completion::ToolDefinition {
         rig::completion::ToolDefinition {
-            name: self.definition.name.to_string(),
+            name: self.name.clone(),
             description: self.definition.description.clone().unwrap_or_default().to_string(),
             parameters: self.definition.schema_as_json_value(),
         }
@@ -250,43 +175,220 @@ impl rig::tool::Tool for McpRigTool {
             .chars()
             .take(100)
             .collect::<String>();
-        tracing::info!("🔧 {}({})", self.definition.name, args_str);
+        tracing::info!("🔧 {}/{}({})", self.client.name(), self.definition.name, args_str);
+
+        // Fail fast with a clear reason instead of waiting on a server known to be down
+        let health = self.client.health();
+        if health.state == HealthState::Down {
+            return Err(McpToolError(format!(
+                "MCP server '{}' is down ({}); it is being reconnected, try again later",
+                self.client.name(),
+                health.last_error.unwrap_or_else(|| "not answering".to_string())
+            )));
+        }
         
-        self.client.call_tool(&self.definition.name, args)
+        let output = self.client.call_tool(&self.definition.name, args)
             .await
-            .map_err(|e| McpToolError(e.to_string()))
+            .map_err(|e| McpToolError(e.to_string()))?;
+
+        // isError results go back to the model as a tool error
+        let is_error = output.is_error;
+        let text = output.into_text(|attachment| self.attachments.add(attachment));
+        if is_error {
+            return Err(McpToolError(text));
+        }
+        Ok(text)
     }
 
     fn name(&self) -> String {
-        self.definition.name.to_string()
+        self.name.clone()
     }
 }
 
 /// Represents a chat message in the conversation
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ChatMessage {
     pub role: MessageRole,
     pub content: String,
+    /// Tools called by an assistant message
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tool_calls: Vec<ToolCallRequest>,
+    /// For tool results, the id of the call being answered
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tool_call_id: Option<String>,
+    /// Images attached by the user, or images and resources returned by a tool
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<Attachment>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+impl ChatMessage {
+    fn new(role: MessageRole, content: String) -> Self {
+        Self {
+            role,
+            content,
+            tool_calls: Vec::new(),
+            tool_call_id: None,
+            attachments: Vec::new(),
+        }
+    }
+
+    /// Convert to Rig messages (system messages are folded into the preamble instead)
+    ///
+    /// With `vision`, image attachments are sent as image input: on the user message
+    /// itself, or in a user message following a tool result (tool results can only
+    /// carry text). Other attachments of a user message, such as `@server:uri`
+    /// resources, are inlined into its text.
+    fn to_rig_messages(&self, vision: bool) -> Vec<Message> {
+        let images: Vec<&Attachment> = if vision {
+            self.attachments.iter().filter(|a| a.is_image()).collect()
+        } else {
+            Vec::new()
+        };
+        match self.role {
+            MessageRole::User => {
+                let mut text = self.content.clone();
+                for attachment in self.attachments.iter().filter(|a| !a.is_image()) {
+                    text.push_str("\n\n");
+                    text.push_str(&attachment.resource_text());
+                }
+                vec![image_message(&text, &images).unwrap_or_else(|| Message::user(&text))]
+            }
+            MessageRole::Assistant => {
+                let mut content = Vec::new();
+                if !self.content.is_empty() {
+                    content.push(AssistantContent::text(&self.content));
+                }
+                for call in &self.tool_calls {
+                    let arguments = serde_json::from_str(&call.arguments)
+                        .unwrap_or_else(|_| json!({}));
+                    content.push(AssistantContent::tool_call(
+                        &call.call_id,
+                        &call.tool_name,
+                        arguments,
+                    ));
+                }
+                OneOrMany::many(content)
+                    .ok()
+                    .map(|content| Message::Assistant { id: None, content })
+                    .into_iter()
+                    .collect()
+            }
+            MessageRole::System | MessageRole::Notice => Vec::new(),
+            MessageRole::ToolResult => {
+                let id = result_id(self.tool_call_id.as_deref().unwrap_or_default());
+                let text = tool_images_text(id);
+                std::iter::once(tool_result_message(id.to_string(), &self.content))
+                    .chain(image_message(&text, &images))
+                    .collect()
+            }
+        }
+    }
+}
+
+/// A tool result message for the call `id`
+fn tool_result_message(id: String, content: &str) -> Message {
+    Message::User {
+        content: OneOrMany::one(UserContent::tool_result(
+            id,
+            OneOrMany::one(ToolResultContent::text(content)),
+        )),
+    }
+}
+
+/// A user message with text and images, or `None` without images
+fn image_message(text: &str, images: &[&Attachment]) -> Option<Message> {
+    if images.is_empty() {
+        return None;
+    }
+    let content: Vec<_> = std::iter::once(UserContent::text(text))
+        .chain(images.iter().map(|image| {
+            UserContent::image_base64(
+                image.data.clone(),
+                ImageMediaType::from_mime_type(&image.mime_type),
+                None,
+            )
+        }))
+        .collect();
+    OneOrMany::many(content).ok().map(|content| Message::User { content })
+}
+
+/// Text of the user message carrying the images of tool call `id`
+fn tool_images_text(id: &str) -> String {
+    format!("Images returned by the tool call {}:", id)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum MessageRole {
     User,
     Assistant,
     #[allow(dead_code)]
     System,
     ToolResult,
+    /// Shown in the chat pane only (welcome banner, command output, errors);
+    /// never sent to the model, saved or exported
+    Notice,
 }
 
 /// Tool call request from the model
-#[allow(dead_code)]
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct ToolCallRequest {
     pub tool_name: String,
     pub arguments: String,
     pub call_id: String,
 }
 
+/// Incremental output of a streamed request
+#[derive(Debug, Clone)]
+pub enum StreamEvent {
+    /// Assistant text
+    Text(String),
+    /// The model called a tool
+    ToolCall(ToolCallRequest),
+    /// A tool call returned
+    ToolResult {
+        call_id: String,
+        content: String,
+        attachments: Vec<Attachment>,
+    },
+}
+
+/// Completion requests per prompt: one round of tool calls, then the answer
+const MAX_TURNS: usize = 2;
+
+/// Call id recorded for the `index`th tool call of a turn
+///
+/// Ollama does not assign call ids, so one is synthesized from the tool name and
+/// the index; concurrent calls of the same tool keep distinct ids.
+fn call_id_for(tool_call: &message::ToolCall, index: usize) -> String {
+    if tool_call.id.is_empty() {
+        format!("{}#{}", tool_call.function.name, index)
+    } else {
+        tool_call.id.clone()
+    }
+}
+
+/// Id sent with a tool result: Ollama matches results by tool name, so
+/// synthesized ids are sent as the tool name
+fn result_id(call_id: &str) -> &str {
+    match call_id.rsplit_once('#') {
+        Some((name, index)) if !index.is_empty() && index.bytes().all(|b| b.is_ascii_digit()) => name,
+        _ => call_id,
+    }
+}
+
+/// Call a tool on the tool server, turning errors into the result text
+async fn call_tool(handle: &ToolServerHandle, tool_call: &message::ToolCall) -> String {
+    let arguments = tool_call.function.arguments.to_string();
+    match handle.call_tool(&tool_call.function.name, &arguments).await {
+        Ok(result) => result,
+        Err(e) => {
+            tracing::warn!("Error while calling tool: {}", e);
+            e.to_string()
+        }
+    }
+}
+
 /// The AI agent
 pub struct Agent {
     client: ollama::Client,
@@ -295,91 +397,268 @@ pub struct Agent {
     chat_history: Vec<ChatMessage>,
     /// Connected MCP servers
     mcp_connected: Vec<String>,
+    /// Clients for the connected MCP servers with their tools, closed on shutdown
+    mcp_clients: Vec<McpConnection>,
+    /// Built-in and MCP tools, enabled or not
+    tools: Vec<ToolInfo>,
+    /// Tools switched off for this session, by exposed name
+    disabled_tools: HashSet<String>,
     /// Tool server handle for MCP tools
     tool_server_handle: Option<rig::tool::server::ToolServerHandle>,
+    /// Attachments returned by MCP tools, shared with the tools on the tool server
+    attachments: AttachmentStore,
+    /// Approval gate for tool calls (tools run unconfirmed when unset)
+    tool_approver: Option<ToolApprover>,
+    /// Whether the model accepts image input (`None` if unknown)
+    vision: Option<bool>,
+    /// Index for dynamic tool selection (all tools are advertised when unset)
+    tool_index: Option<ToolIndex>,
 }
 
 impl Agent {
     /// Create a new agent
     pub fn new(config: &Config) -> Result<Self> {
-        debug!("Creating Ollama client with host: {}", config.host);
+        let base_url = config.ollama_url();
+        debug!("Creating Ollama client with host: {}", base_url);
 
         // Create Ollama client
-        let client = ollama::Client::new(Nothing)
+        let client = ollama::Client::builder()
+            .api_key(Nothing)
+            .base_url(&base_url)
+            .build()
             .map_err(|e| anyhow::anyhow!("Failed to create Ollama client: {}", e))?;
 
+        let tool_index = config
+            .tool_selection
+            .enabled
+            .then(|| ToolIndex::new(&client, &config.tool_selection));
+
         Ok(Self {
             client,
             model_name: config.model.clone(),
-            preamble: Self::default_preamble(),
+            preamble: if tool_index.is_some() {
+                Self::tool_selection_preamble()
+            } else {
+                Self::default_preamble()
+            },
             chat_history: Vec::new(),
             mcp_connected: Vec::new(),
+            mcp_clients: Vec::new(),
+            tools: Vec::new(),
+            disabled_tools: HashSet::new(),
             tool_server_handle: None,
+            attachments: AttachmentStore::default(),
+            tool_approver: None,
+            vision: None,
+            tool_index,
         })
     }
 
-    /// Connect to MCP servers and discover tools
+    /// Connect to MCP servers, discover tools and start the tool server
+    ///
+    /// Built-in tools are always registered, so the tool server is started even
+    /// when no MCP server is configured or reachable.
     pub async fn connect_mcp_servers(&mut self, servers: &[McpServerConfig]) {
         debug!("Connecting to {} MCP servers", servers.len());
 
-        let mut tool_server = ToolServer::new();
-
         for server in servers {
             if !server.enabled {
                 continue;
             }
+            if let Err(e) = self.add_mcp_server(server).await {
+                tracing::warn!("Failed to connect to MCP server '{}': {}", server.name, e);
+            }
+        }
 
-            debug!(
-                "Connecting to MCP server: {} at {}",
-                server.name, server.url
-            );
+        self.rebuild_tool_server();
+    }
 
-            // Try to connect to the MCP server using simple HTTP client
-            match self.connect_mcp_server(&server.url, &server.name).await {
-                Ok((client, tools)) => {
-                    debug!("Connected to MCP server '{}': {} tools", server.name, tools.len());
-
-                    // Create custom Rig tools for each MCP tool
-                    for tool in &tools {
-                        debug!("Registering MCP tool: {} - {}", tool.name, tool.description.as_ref().unwrap_or(&"".into()));
-                        let mcp_tool = McpRigTool::new(
-                            tool.clone(),
-                            client.clone(),
-                            server.name.clone(),
-                        );
-                        tool_server = tool_server.tool(mcp_tool);
-                    }
+    /// Connect (or reconnect) one MCP server and rebuild the tool server with its tools
+    ///
+    /// An existing connection with the same name is closed first, so on failure the
+    /// server ends up disconnected. Returns the number of tools discovered.
+    #[cfg(test)]
+    pub async fn reconnect_mcp_server(&mut self, server: &McpServerConfig) -> Result<usize> {
+        self.remove_mcp_server(&server.name);
+        let result = self.add_mcp_server(server).await;
+        self.rebuild_tool_server();
+        result
+    }
+
+    /// Use a server connected with [`Agent::connect_mcp_server`], replacing any
+    /// connection with the same name, and rebuild the tool server with its tools
+    ///
+    /// Returns the number of tools kept.
+    pub fn replace_mcp_server(
+        &mut self,
+        server: &McpServerConfig,
+        client: McpClient,
+        tools: Vec<rmcp::model::Tool>,
+    ) -> usize {
+        self.remove_mcp_server(&server.name);
+        let count = self.keep_mcp_server(server, client, tools);
+        self.rebuild_tool_server();
+        count
+    }
+
+    /// Disconnect an MCP server and rebuild the tool server without its tools
+    ///
+    /// Returns whether the server was connected.
+    pub fn disconnect_mcp_server(&mut self, name: &str) -> bool {
+        let removed = self.remove_mcp_server(name);
+        if removed {
+            self.rebuild_tool_server();
+        }
+        removed
+    }
+
+    /// Connect to an MCP server and keep its client and tools
+    async fn add_mcp_server(&mut self, server: &McpServerConfig) -> Result<usize> {
+        debug!(
+            "Connecting to MCP server: {} at {}",
+            server.name, server.endpoint()
+        );
+        let (client, tools) = Self::connect_mcp_server(server).await?;
+        debug!("Connected to MCP server '{}': {} tools", server.name, tools.len());
+        Ok(self.keep_mcp_server(server, client, tools))
+    }
+
+    /// Keep the client and allowed tools of a connected MCP server
+    fn keep_mcp_server(&mut self, server: &McpServerConfig, client: McpClient, tools: Vec<rmcp::model::Tool>) -> usize {
+        let tools = allowed_tools(server, tools);
+        let count = tools.len();
+        self.mcp_connected.push(server.name.clone());
+        self.mcp_clients.push(McpConnection {
+            client,
+            tools,
+            server: server.clone(),
+        });
+        count
+    }
+
+    /// Replace the tools of a connected MCP server (e.g. after it reconnected) and
+    /// rebuild the tool server
+    ///
+    /// Returns the number of tools kept, or `None` if the server is not connected.
+    pub fn update_mcp_tools(&mut self, name: &str, tools: Vec<rmcp::model::Tool>) -> Option<usize> {
+        let connection = self.mcp_clients.iter_mut().find(|c| c.client.name() == name)?;
+        connection.tools = allowed_tools(&connection.server, tools);
+        let count = connection.tools.len();
+        self.rebuild_tool_server();
+        Some(count)
+    }
 
-                    self.mcp_connected.push(server.name.clone());
+    /// Forget the connection to an MCP server and close it in the background
+    fn remove_mcp_server(&mut self, name: &str) -> bool {
+        let Some(index) = self.mcp_clients.iter().position(|c| c.client.name() == name) else {
+            return false;
+        };
+        let connection = self.mcp_clients.remove(index);
+        self.mcp_connected.retain(|connected| connected != name);
+        connection.client.shutdown_in_background();
+        true
+    }
+
+    /// Start a new tool server with the built-in tools and the tools of every
+    /// connected MCP server, and update the preamble to match
+    ///
+    /// MCP tools are namespaced by server. A tool whose name is already taken (by a
+    /// built-in tool or a server connected earlier) is skipped with a warning.
+    /// Tools switched off for the session are left out of both.
+    /// Requests already running keep the previous tool server.
+    fn rebuild_tool_server(&mut self) {
+        let approver = self.tool_approver.as_ref();
+        let disabled = &self.disabled_tools;
+        let mut tool_server =
+            tools::register_builtin_tools(ToolServer::new(), approver, |name| !disabled.contains(name));
+        let mut mcp_tool_names: Vec<String> = Vec::new();
+        let mut mcp_tool_servers: HashMap<String, String> = HashMap::new();
+        self.tools = tools::BUILTIN_TOOL_NAMES
+            .iter()
+            .map(|name| ToolInfo {
+                name: name.to_string(),
+                server: None,
+                enabled: !disabled.contains(*name),
+            })
+            .collect();
+
+        // Create custom Rig tools for each MCP tool
+        for connection in &self.mcp_clients {
+            let server = connection.client.name();
+            for tool in &connection.tools {
+                let name = namespaced_tool_name(&connection.server.tool_prefix(), &tool.name);
+                if tools::BUILTIN_TOOL_NAMES.contains(&name.as_str()) {
+                    tracing::warn!("MCP tool '{}' from '{}' clashes with a built-in tool and is not available", name, server);
+                    continue;
                 }
-                Err(e) => {
-                    tracing::warn!("Failed to connect to MCP server '{}': {}", server.name, e);
+                if let Some(owner) = mcp_tool_servers.get(&name) {
+                    tracing::warn!(
+                        "MCP tool '{}' from '{}' clashes with the same tool from '{}' and is not available; set a different `prefix` for one of the servers",
+                        name, server, owner
+                    );
+                    continue;
+                }
+
+                mcp_tool_servers.insert(name.clone(), server.to_string());
+                let enabled = !disabled.contains(&name);
+                self.tools.push(ToolInfo {
+                    name: name.clone(),
+                    server: Some(server.to_string()),
+                    enabled,
+                });
+                if !enabled {
+                    continue;
                 }
+
+                debug!("Registering MCP tool: {} - {}", name, tool.description.as_ref().unwrap_or(&"".into()));
+                let mcp_tool = McpRigTool::new(
+                    tool.clone(),
+                    connection.client.clone(),
+                    name.clone(),
+                    self.attachments.clone(),
+                );
+                let raw_name = (name != tool.name.as_ref()).then(|| tool.name.to_string());
+                mcp_tool_names.push(name);
+                tool_server = approval::add_tool_as(tool_server, mcp_tool, raw_name, approver);
             }
         }
 
         // Start the tool server and get a handle
-        if !self.mcp_connected.is_empty() {
-            let handle = tool_server.run();
+        let handle = tool_server.run();
 
-            // Update preamble to mention MCP tools
-            let tool_defs = match handle.get_tool_defs(None).await {
-                Ok(defs) => defs,
-                Err(e) => {
-                    tracing::warn!("Failed to get tool definitions: {}", e);
-                    Vec::new()
-                }
-            };
+        if self.tool_index.is_some() {
+            // Each request advertises its own selection, so no tools are named here
+            self.preamble = Self::tool_selection_preamble();
+        } else if mcp_tool_names.is_empty() && self.disabled_tools.is_empty() {
+            self.preamble = Self::default_preamble();
+        } else if mcp_tool_names.is_empty() {
+            let builtin = self.builtin_tool_names();
+            let tools_str = if builtin.is_empty() { "none".to_string() } else { builtin.join(", ") };
+            self.preamble = format!(
+                r#"You are PCLI2-RIG, a helpful AI coding assistant running in a terminal TUI.
+
+You have access to these tools: {}
 
-            if !tool_defs.is_empty() {
-                let tool_names: Vec<&str> = tool_defs.iter().map(|t| t.name.as_str()).collect();
-                let tools_str = tool_names.join(", ");
-                tracing::debug!("Registered MCP tools: {}", tools_str);
-                self.preamble = format!(
-                    r#"You are PCLI2-RIG, a helpful AI coding assistant running in a terminal TUI.
+When using tools:
+1. Think carefully about what the user is asking
+2. Use the appropriate tool(s) to help
+3. Explain what you're doing and what the results mean
+
+Be concise but helpful. Use formatting like code blocks when appropriate.
+You are running on the user's local machine via Ollama."#,
+                tools_str
+            );
+        } else {
+            // Update preamble to mention MCP tools
+            let tools_str = mcp_tool_names.join(", ");
+            tracing::debug!("Registered MCP tools: {}", tools_str);
+            self.preamble = format!(
+                r#"You are PCLI2-RIG, a helpful AI coding assistant running in a terminal TUI.
 
 You have access to these MCP tools: {}
 
+You also have built-in tools: {}
+
 IMPORTANT: When the user asks about folders, assets, tenants, configuration, or any pcli2-related task, YOU MUST call the appropriate MCP tool directly. DO NOT just tell the user what command to run - actually execute the tool for them.
 
 For example:
@@ -395,36 +674,69 @@ When using tools:
 3. Present the results to the user in a clear format
 
 Be concise but helpful. You are running on the user's local machine via Ollama."#,
-                    tools_str
-                );
-            }
-
-            self.tool_server_handle = Some(handle);
+                tools_str,
+                self.builtin_tool_names().join(", ")
+            );
         }
+
+        self.tool_server_handle = Some(handle);
     }
 
-    /// Connect to a single MCP server using simple HTTP client
-    async fn connect_mcp_server(&self, url: &str, _name: &str) -> Result<(SimpleMcpClient, Vec<rmcp::model::Tool>)> {
-        let client = SimpleMcpClient::new(url.to_string());
+    /// Connect to a single MCP server (HTTP or stdio) and list its tools
+    ///
+    /// The client is created on a blocking thread, since resolving `token_cmd`
+    /// runs a command.
+    pub async fn connect_mcp_server(server: &McpServerConfig) -> Result<(McpClient, Vec<rmcp::model::Tool>)> {
+        let config = server.clone();
+        let client = tokio::task::spawn_blocking(move || McpClient::from_config(&config))
+            .await
+            .context("MCP client setup panicked")??;
         
         // Initialize the connection
         client.initialize().await?;
-        
+
+        // A server without the tools capability may reject tools/list
+        if !client.supports_tools() {
+            tracing::warn!(
+                "MCP server '{}' does not advertise the tools capability, no tools will be available from it",
+                server.name
+            );
+            return Ok((client, Vec::new()));
+        }
+
         // List available tools
         let tools = client.list_tools().await?;
         
         Ok((client, tools))
     }
 
+    /// System preamble with dynamic tool selection, which offers a different set
+    /// of tools with every request
+    fn tool_selection_preamble() -> String {
+        r#"You are PCLI2-RIG, a helpful AI coding assistant running in a terminal TUI.
+
+With each request you are given the tools most relevant to it, which may include built-in tools (files, shell commands, code search) and MCP tools (folders, assets, tenants, configuration and other pcli2 tasks). Only call tools you have been given.
+
+When a tool can do what the user asks, call it directly. DO NOT just tell the user what command to run - actually execute the tool for them.
+
+When using tools:
+1. Call the appropriate tool immediately
+2. Wait for the tool result
+3. Present the results to the user in a clear format
+
+Be concise but helpful. You are running on the user's local machine via Ollama."#
+            .to_string()
+    }
+
     /// Default system preamble
     fn default_preamble() -> String {
         r#"You are PCLI2-RIG, a helpful AI coding assistant running in a terminal TUI.
 
 You have access to tools that allow you to:
-- Read and write files
-- List directory contents  
-- Run shell commands
-- Search code with grep
+- Read and write files (read_file, write_file)
+- List directory contents (list_directory)
+- Run shell commands (run_command)
+- Search code with grep (search_code)
 
 When using tools:
 1. Think carefully about what the user is asking
@@ -437,27 +749,65 @@ You are running on the user's local machine via Ollama."#
     }
 
     /// Add a user message to the chat
+    #[cfg(test)]
     pub fn add_user_message(&mut self, content: String) {
-        self.chat_history.push(ChatMessage {
-            role: MessageRole::User,
-            content,
-        });
+        self.chat_history
+            .push(ChatMessage::new(MessageRole::User, content));
+    }
+
+    /// Add a user message with attached images
+    pub fn add_user_message_with_attachments(&mut self, content: String, attachments: Vec<Attachment>) {
+        let mut msg = ChatMessage::new(MessageRole::User, content);
+        msg.attachments = attachments;
+        self.chat_history.push(msg);
     }
 
     /// Add an assistant message to the chat
     pub fn add_assistant_message(&mut self, content: String) {
-        self.chat_history.push(ChatMessage {
-            role: MessageRole::Assistant,
-            content,
-        });
+        self.chat_history
+            .push(ChatMessage::new(MessageRole::Assistant, content));
     }
 
-    /// Add a tool result message
-    pub fn add_tool_result(&mut self, result: String) {
-        self.chat_history.push(ChatMessage {
-            role: MessageRole::ToolResult,
-            content: result,
-        });
+    /// Append streamed text to the last message (which must be the in-progress assistant reply)
+    pub fn append_to_last_message(&mut self, chunk: &str) {
+        if let Some(last) = self.chat_history.last_mut() {
+            last.content.push_str(chunk);
+        }
+    }
+
+    /// Add a notice for the user, e.g. command output (not part of the conversation)
+    pub fn add_notice(&mut self, content: String) {
+        self.chat_history
+            .push(ChatMessage::new(MessageRole::Notice, content));
+    }
+
+    /// Record streamed output of the current request in the chat history
+    ///
+    /// Text and tool calls extend the assistant reply at the end of the history (starting
+    /// one if needed); tool results are added as their own messages.
+    pub fn push_stream_event(&mut self, event: StreamEvent) {
+        let replying = self
+            .chat_history
+            .last()
+            .is_some_and(|msg| msg.role == MessageRole::Assistant);
+        if !replying && !matches!(event, StreamEvent::ToolResult { .. }) {
+            self.add_assistant_message(String::new());
+        }
+
+        match event {
+            StreamEvent::Text(text) => self.append_to_last_message(&text),
+            StreamEvent::ToolCall(call) => {
+                if let Some(last) = self.chat_history.last_mut() {
+                    last.tool_calls.push(call);
+                }
+            }
+            StreamEvent::ToolResult { call_id, content, attachments } => {
+                let mut msg = ChatMessage::new(MessageRole::ToolResult, content);
+                msg.tool_call_id = Some(call_id);
+                msg.attachments = attachments;
+                self.chat_history.push(msg);
+            }
+        }
     }
 
     /// Get the chat history
@@ -465,6 +815,11 @@ You are running on the user's local machine via Ollama."#
         &self.chat_history
     }
 
+    /// Replace the chat history
+    pub fn set_chat_history(&mut self, history: Vec<ChatMessage>) {
+        self.chat_history = history;
+    }
+
     /// Clear the chat history
     pub fn clear_history(&mut self) {
         self.chat_history.clear();
@@ -475,56 +830,176 @@ You are running on the user's local machine via Ollama."#
         &self.model_name
     }
 
+    /// Switch the model used for subsequent requests (chat history is kept)
+    ///
+    /// Its vision support is unknown until set again.
+    pub fn set_model_name(&mut self, model_name: String) {
+        self.model_name = model_name;
+        self.vision = None;
+    }
+
+    /// Whether the model accepts image input (`None` if unknown)
+    pub fn vision(&self) -> Option<bool> {
+        self.vision
+    }
+
+    /// Record whether the model accepts image input
+    pub fn set_vision(&mut self, vision: Option<bool>) {
+        self.vision = vision;
+    }
+
     /// Get connected MCP servers
     pub fn mcp_connected(&self) -> &[String] {
         &self.mcp_connected
     }
 
+    /// Clients of the connected MCP servers, in connection order
+    pub fn mcp_clients(&self) -> impl Iterator<Item = &McpClient> {
+        self.mcp_clients.iter().map(|connection| &connection.client)
+    }
+
+    /// Client for a connected MCP server
+    pub fn mcp_client(&self, name: &str) -> Option<&McpClient> {
+        self.mcp_clients
+            .iter()
+            .map(|connection| &connection.client)
+            .find(|client| client.name() == name)
+    }
+
+    /// MCP server offering a tool, by the tool's exposed name (`None` for built-in tools)
+    pub fn tool_server_name(&self, tool: &str) -> Option<&str> {
+        self.tools
+            .iter()
+            .find(|info| info.name == tool)
+            .and_then(|info| info.server.as_deref())
+    }
+
+    /// All built-in and MCP tools, with whether each is enabled
+    pub fn tools(&self) -> &[ToolInfo] {
+        &self.tools
+    }
+
+    /// Names `[permissions]` entries can match: every tool by the name it is
+    /// exposed under, and MCP tools also by their server's name
+    pub fn permission_names(&self) -> Vec<String> {
+        let raw_names = self
+            .mcp_clients
+            .iter()
+            .flat_map(|connection| connection.tools.iter().map(|tool| tool.name.to_string()));
+        self.tools.iter().map(|tool| tool.name.clone()).chain(raw_names).collect()
+    }
+
+    /// Names of the enabled built-in tools
+    fn builtin_tool_names(&self) -> Vec<&str> {
+        self.tools
+            .iter()
+            .filter(|info| info.server.is_none() && info.enabled)
+            .map(|info| info.name.as_str())
+            .collect()
+    }
+
+    /// Switch the tools matching a name or glob pattern on or off for this session
+    ///
+    /// Returns the number of tools matched. The tool server and preamble are
+    /// rebuilt when anything matched.
+    pub fn set_tools_enabled(&mut self, pattern: &str, enabled: bool) -> usize {
+        let names: Vec<String> = self
+            .tools
+            .iter()
+            .filter(|info| config::glob_match(pattern, &info.name))
+            .map(|info| info.name.clone())
+            .collect();
+        for name in &names {
+            if enabled {
+                self.disabled_tools.remove(name);
+            } else {
+                self.disabled_tools.insert(name.clone());
+            }
+        }
+        if !names.is_empty() {
+            self.rebuild_tool_server();
+        }
+        names.len()
+    }
+
     /// Get count of connected MCP servers
     pub fn mcp_server_count(&self) -> usize {
         self.mcp_connected.len()
     }
 
+    /// End the sessions of all connected MCP servers, giving up after
+    /// [`mcp::SHUTDOWN_TIMEOUT`]
+    pub async fn shutdown_mcp(&self) {
+        let shutdown = futures::future::join_all(self.mcp_clients.iter().map(|c| c.client.shutdown()));
+        if tokio::time::timeout(mcp::SHUTDOWN_TIMEOUT, shutdown).await.is_err() {
+            tracing::warn!("MCP servers did not close their sessions in time");
+        }
+    }
+
     /// Get tool server handle
-    #[allow(dead_code)]
     pub fn tool_server_handle(&self) -> Option<&rig::tool::server::ToolServerHandle> {
         self.tool_server_handle.as_ref()
     }
 
     /// Get preamble
-    #[allow(dead_code)]
     pub fn preamble(&self) -> &str {
         &self.preamble
     }
 
+    /// Set the approval gate used for tools registered by `connect_mcp_servers`
+    pub fn set_tool_approver(&mut self, approver: ToolApprover) {
+        self.tool_approver = Some(approver);
+    }
+
+    /// Get the tool approval gate
+    pub fn tool_approver(&self) -> Option<&ToolApprover> {
+        self.tool_approver.as_ref()
+    }
+
     /// Set tool server handle (for cloning agent state)
     pub fn set_tool_server_handle(&mut self, handle: rig::tool::server::ToolServerHandle) {
         self.tool_server_handle = Some(handle);
     }
 
-    /// Set preamble (for cloning agent state)
-    pub fn set_preamble(&mut self, preamble: String) {
-        self.preamble = preamble;
+    /// Get the dynamic tool selection index, if enabled
+    pub fn tool_index(&self) -> Option<&ToolIndex> {
+        self.tool_index.as_ref()
     }
 
-    /// Send a message and get a response (without adding user message to history)
-    pub async fn chat_without_history(&mut self, _user_message: String) -> Result<String> {
-        // Send request and get response
-        let response = self.send_request().await?;
+    /// Share a dynamic tool selection index (e.g. with an agent running a request)
+    pub fn set_tool_index(&mut self, tool_index: Option<ToolIndex>) {
+        self.tool_index = tool_index;
+    }
 
-        // Add assistant response to history
-        self.add_assistant_message(response.clone());
+    /// Get the attachment store shared with the MCP tools
+    pub fn attachment_store(&self) -> &AttachmentStore {
+        &self.attachments
+    }
 
-        Ok(response)
+    /// Set the attachment store (for cloning agent state)
+    pub fn set_attachment_store(&mut self, attachments: AttachmentStore) {
+        self.attachments = attachments;
     }
 
-    /// Send a message and get a response
-    pub async fn chat(&mut self, user_message: String) -> Result<String> {
-        // Add user message to history
-        self.add_user_message(user_message.clone());
+    /// Set preamble (for cloning agent state)
+    pub fn set_preamble(&mut self, preamble: String) {
+        self.preamble = preamble;
+    }
 
+    /// Send a message and get a response (without adding user message to history)
+    ///
+    /// Text, tool calls and tool results are passed to `on_event` as they stream in.
+    pub async fn chat_without_history<F, Fut>(
+        &mut self,
+        _user_message: String,
+        on_event: F,
+    ) -> Result<String>
+    where
+        F: FnMut(StreamEvent) -> Fut,
+        Fut: Future<Output = ()>,
+    {
         // Send request and get response
-        let response = self.send_request().await?;
+        let response = self.send_request(on_event).await?;
 
         // Add assistant response to history
         self.add_assistant_message(response.clone());
@@ -532,8 +1007,17 @@ You are running on the user's local machine via Ollama."#
         Ok(response)
     }
 
-    /// Send a request to the model
-    async fn send_request(&self) -> Result<String> {
+    /// Send the chat history to the model, streaming its output to `on_event`
+    ///
+    /// The last message is sent as the prompt and the rest as structured Rig history,
+    /// so role boundaries and tool call/result pairs reach Ollama intact. Returns the
+    /// full streamed text; text from separate turns (before and after a tool call) is
+    /// separated by a blank line.
+    async fn send_request<F, Fut>(&self, mut on_event: F) -> Result<String>
+    where
+        F: FnMut(StreamEvent) -> Fut,
+        Fut: Future<Output = ()>,
+    {
         debug!("Sending request to Ollama model: {}", self.model_name);
         debug!("Chat history has {} messages", self.chat_history.len());
         debug!("Tool server handle present: {}", self.tool_server_handle.is_some());
@@ -552,284 +1036,652 @@ You are running on the user's local machine via Ollama."#
             }
         }
 
-        // Build conversation history for prompt
-        let mut prompt_text = String::new();
-
-        // Add context from chat history
+        // System messages have no Rig message type, so they extend the preamble
+        let mut preamble = self.preamble.clone();
         for msg in &self.chat_history {
-            match msg.role {
-                MessageRole::User => {
-                    prompt_text.push_str(&format!("\n\nUser: {}", msg.content));
-                }
-                MessageRole::Assistant => {
-                    prompt_text.push_str(&format!("\n\nAssistant: {}", msg.content));
-                }
-                MessageRole::System => {
-                    prompt_text.push_str(&format!("\n\nSystem: {}", msg.content));
-                }
-                MessageRole::ToolResult => {
-                    prompt_text.push_str(&format!("\n\nTool Result: {}", msg.content));
-                }
+            if msg.role == MessageRole::System {
+                preamble.push_str("\n\n");
+                preamble.push_str(&msg.content);
             }
         }
 
-        debug!("Prompt text length: {} chars", prompt_text.len());
+        // Images are sent unless the model is known to lack vision support
+        let vision = self.vision != Some(false);
+        if !vision && self.chat_history.iter().flat_map(|m| &m.attachments).any(Attachment::is_image) {
+            tracing::warn!("{} has no vision support, images are not sent to it", self.model_name);
+        }
+
+        let mut history: Vec<Message> = self
+            .chat_history
+            .iter()
+            .flat_map(|msg| msg.to_rig_messages(vision))
+            .collect();
+        let mut prompt = history
+            .pop()
+            .context("Chat history is empty, nothing to send")?;
+
+        debug!("Sending prompt with {} history messages", history.len());
 
         // Build the agent with or without tools
-        let response = if let Some(tool_handle) = &self.tool_server_handle {
+        let builder = self.client.agent(&self.model_name).preamble(&preamble);
+        let agent = if let Some(tool_handle) = self.advertised_tools().await {
             debug!("Attaching tool server handle with {} MCP servers connected", self.mcp_connected.len());
-            debug!("Creating agent with model: {}", self.model_name);
-            let agent = self
-                .client
-                .agent(&self.model_name)
-                .preamble(&self.preamble)
-                .tool_server_handle(tool_handle.clone())
-                .build();
-
-            debug!("Sending prompt to agent with model: {}", self.model_name);
-            agent.prompt(prompt_text).await
+            builder.tool_server_handle(tool_handle).build()
         } else {
             debug!("Creating agent (no tools) with model: {}", self.model_name);
-            let agent = self
-                .client
-                .agent(&self.model_name)
-                .preamble(&self.preamble)
-                .build();
-
-            debug!("Sending prompt to agent with model: {}", self.model_name);
-            agent.prompt(prompt_text).await
-        }.map_err(|e| {
-            let error_str = e.to_string();
-            
-            // Provide specific error messages based on error type
-            if error_str.contains("MaxTurnError") {
-                anyhow::anyhow!(
-                    "The model got stuck in a repetitive loop. This happens when:\n\
-                     • A tool keeps failing and the model retries indefinitely\n\
-                     • The model's tool requests don't match what the tools expect\n\
-                     • The conversation history is in a confused state\n\n\
-                     To fix this:\n\
-                     1. Type /clear to reset the conversation\n\
-                     2. Try your request again with different wording\n\
-                     3. If using tools, check that the tool arguments are correct"
-                )
-            } else if error_str.contains("connection") || error_str.contains("connect") {
-                anyhow::anyhow!(
-                    "Cannot connect to Ollama: {}\n\n\
-                     Make sure Ollama is running (`ollama serve`) and \
-                     the model is pulled (`ollama pull {}`).",
-                    e,
-                    self.model_name
-                )
-            } else {
-                anyhow::anyhow!(
-                    "Ollama request failed: {}\n\n\
-                     Make sure Ollama is running (`ollama serve`) and \
-                     the model is pulled (`ollama pull {}`).",
-                    e,
-                    self.model_name
-                )
+            builder.build()
+        };
+
+        debug!("Streaming chat to agent with model: {}", self.model_name);
+        let mut response = String::new();
+
+        for _ in 0..MAX_TURNS {
+            let mut stream = agent
+                .stream_completion(prompt.clone(), history.clone())
+                .await
+                .map_err(|e| self.request_error(e))?
+                .stream()
+                .await
+                .map_err(|e| self.request_error(e))?;
+            history.push(prompt);
+
+            let mut turn_text = String::new();
+            let mut tool_calls = Vec::new();
+            while let Some(item) = stream.next().await {
+                match item.map_err(|e| self.request_error(e))? {
+                    StreamedAssistantContent::Text(text) => {
+                        if text.text.is_empty() {
+                            continue;
+                        }
+                        if turn_text.is_empty() && !response.is_empty() {
+                            response.push_str("\n\n");
+                        }
+                        turn_text.push_str(&text.text);
+                        response.push_str(&text.text);
+                        on_event(StreamEvent::Text(text.text)).await;
+                    }
+                    StreamedAssistantContent::ToolCall { tool_call, .. } => {
+                        debug!("Model requested tool: {}", tool_call.function.name);
+                        on_event(StreamEvent::ToolCall(ToolCallRequest {
+                            tool_name: tool_call.function.name.clone(),
+                            arguments: tool_call.function.arguments.to_string(),
+                            call_id: call_id_for(&tool_call, tool_calls.len()),
+                        }))
+                        .await;
+                        tool_calls.push(tool_call);
+                    }
+                    _ => {}
+                }
             }
-        })?;
 
-        debug!("Received response: {} chars", response.len());
+            if tool_calls.is_empty() {
+                debug!("Received response: {} chars", response.len());
+                return Ok(response);
+            }
 
-        Ok(response)
-    }
-}
+            let mut content = Vec::new();
+            if !turn_text.is_empty() {
+                content.push(AssistantContent::text(&turn_text));
+            }
+            content.extend(tool_calls.iter().cloned().map(AssistantContent::ToolCall));
+            history.push(Message::Assistant {
+                id: stream.message_id.clone(),
+                content: OneOrMany::many(content).context("Assistant turn has no content")?,
+            });
+
+            // Run the turn's tool calls concurrently; MCP servers apply their own limits
+            let results = self.run_tool_calls(&tool_calls, &mut on_event).await;
+            for (index, (tool_call, (result, attachments))) in tool_calls.iter().zip(results).enumerate() {
+                let id = result_id(&call_id_for(tool_call, index)).to_string();
+                let images: Vec<&Attachment> = if vision {
+                    attachments.iter().filter(|a| a.is_image()).collect()
+                } else {
+                    Vec::new()
+                };
+                let text = tool_images_text(&id);
+                history.push(tool_result_message(id, &result));
+                history.extend(image_message(&text, &images));
+            }
+            prompt = history.pop().context("Chat history is empty")?;
+        }
 
-/// Execute a tool call
-pub async fn execute_tool_call(tool_name: &str, arguments: &str) -> Result<String> {
-    debug!("Executing tool: {} with args: {}", tool_name, arguments);
+        Err(self.request_error(format!("MaxTurnError: reached {} turns", MAX_TURNS)))
+    }
 
-    let args: serde_json::Value =
-        serde_json::from_str(arguments).context("Failed to parse tool arguments")?;
+    /// Tool server handle advertising the tools for this request
+    ///
+    /// With dynamic tool selection only the `top_k` tools closest to the latest user
+    /// message are advertised, for every turn of the request; if selection fails all
+    /// tools are. Tool calls always go to the full tool server.
+    async fn advertised_tools(&self) -> Option<ToolServerHandle> {
+        let handle = self.tool_server_handle.as_ref()?;
+        let (Some(index), Some(message)) = (
+            &self.tool_index,
+            self.chat_history.iter().rev().find(|msg| msg.role == MessageRole::User),
+        ) else {
+            return Some(handle.clone());
+        };
 
-    let result = match tool_name {
-        "read_file" => {
-            let path = args["path"].as_str().context("Missing 'path' argument")?;
-            let contents = std::fs::read_to_string(path).context("Failed to read file")?;
-            format!("Contents of {}:\n\n{}", path, contents)
-        }
-        "write_file" => {
-            let path = args["path"].as_str().context("Missing 'path' argument")?;
-            let content = args["content"]
-                .as_str()
-                .context("Missing 'content' argument")?;
-            std::fs::write(path, content).context("Failed to write file")?;
-            format!("Successfully wrote {} bytes to {}", content.len(), path)
-        }
-        "list_directory" => {
-            let path = args["path"].as_str().context("Missing 'path' argument")?;
-            let entries = std::fs::read_dir(path).context("Failed to read directory")?;
-
-            let mut result = String::new();
-            for entry in entries {
-                let entry = entry.context("Failed to read directory entry")?;
-                let name = entry.file_name();
-                let name_str = name.to_string_lossy();
-                let is_dir = entry.path().is_dir();
-                if is_dir {
-                    result.push_str(&format!("📁 {}/\n", name_str));
-                } else {
-                    result.push_str(&format!("📄 {}\n", name_str));
-                }
+        let defs = match handle.get_tool_defs(None).await {
+            Ok(defs) if defs.len() > index.top_k() => defs,
+            Ok(defs) => {
+                debug!("Advertising all {} tools, no selection needed", defs.len());
+                return Some(handle.clone());
             }
-            format!("Contents of {}:\n\n{}", path, result)
-        }
-        "run_command" => {
-            let command = args["command"]
-                .as_str()
-                .context("Missing 'command' argument")?;
-            let output = std::process::Command::new("bash")
-                .arg("-c")
-                .arg(command)
-                .output()
-                .context("Failed to run command")?;
-
-            let mut result = String::new();
-            result.push_str(&format!("Command: {}\n\n", command));
-            if !output.stdout.is_empty() {
-                result.push_str("STDOUT:\n");
-                result.push_str(&String::from_utf8_lossy(&output.stdout));
-                result.push('\n');
+            Err(e) => {
+                tracing::warn!("Failed to get tool definitions for tool selection: {}", e);
+                return Some(handle.clone());
+            }
+        };
+        match index.select(&message.content, &defs).await {
+            Ok(names) => {
+                tracing::info!("🧭 Selected {} of {} tools: {}", names.len(), defs.len(), names.join(", "));
+                let selected = defs.into_iter().filter(|def| names.contains(&def.name)).collect();
+                Some(tool_selection::selected_tool_server(handle, selected))
             }
-            if !output.stderr.is_empty() {
-                result.push_str("STDERR:\n");
-                result.push_str(&String::from_utf8_lossy(&output.stderr));
-                result.push('\n');
+            Err(e) => {
+                tracing::warn!("Tool selection failed, advertising all tools: {:#}", e);
+                Some(handle.clone())
             }
-            result.push_str(&format!(
-                "Exit code: {}",
-                output.status.code().unwrap_or(-1)
-            ));
-            result
         }
-        "search_code" => {
-            let pattern = args["pattern"]
-                .as_str()
-                .context("Missing 'pattern' argument")?;
-            let mut cmd = std::process::Command::new("grep");
-            cmd.arg("-rn").arg("--color=never").arg(pattern).arg(".");
-
-            if let Some(glob) = args["glob"].as_str() {
-                cmd.arg("--include").arg(glob);
-            }
+    }
 
-            let output = cmd.output().context("Failed to run grep")?;
+    /// Run tool calls concurrently, emitting each result as it arrives
+    ///
+    /// Returns the results and their attachments in the order of the calls. Errors
+    /// become results, so the model can see what went wrong.
+    async fn run_tool_calls<F, Fut>(
+        &self,
+        tool_calls: &[message::ToolCall],
+        on_event: &mut F,
+    ) -> Vec<(String, Vec<Attachment>)>
+    where
+        F: FnMut(StreamEvent) -> Fut,
+        Fut: Future<Output = ()>,
+    {
+        let Some(handle) = &self.tool_server_handle else {
+            return vec![("No tools are available".to_string(), Vec::new()); tool_calls.len()];
+        };
 
-            if output.stdout.is_empty() {
-                "No matches found.".to_string()
-            } else {
-                format!(
-                    "Search results for '{}':\n\n{}",
-                    pattern,
-                    String::from_utf8_lossy(&output.stdout)
-                )
-            }
+        let mut pending: FuturesUnordered<_> = tool_calls
+            .iter()
+            .enumerate()
+            .map(|(index, tool_call)| async move {
+                (index, call_tool(handle, tool_call).await)
+            })
+            .collect();
+
+        let mut results = vec![(String::new(), Vec::new()); tool_calls.len()];
+        while let Some((index, result)) = pending.next().await {
+            let content = ToolResultContent::from_tool_output(result.clone())
+                .into_iter()
+                .map(|content| match content {
+                    ToolResultContent::Text(text) => text.text,
+                    ToolResultContent::Image(_) => "[image]".to_string(),
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+            let attachments = self.attachments.take_for(&result);
+            on_event(StreamEvent::ToolResult {
+                call_id: call_id_for(&tool_calls[index], index),
+                content,
+                attachments: attachments.clone(),
+            })
+            .await;
+            results[index] = (result, attachments);
         }
-        _ => {
-            return Err(anyhow::anyhow!("Unknown tool: {}", tool_name));
-        }
-    };
+        results
+    }
 
-    Ok(result)
+    /// Turn a request error into a user-facing explanation
+    fn request_error(&self, e: impl std::fmt::Display) -> anyhow::Error {
+        let error_str = e.to_string();
+
+        // Provide specific error messages based on error type
+        if error_str.contains("MaxTurnError") {
+            anyhow::anyhow!(
+                "The model got stuck in a repetitive loop. This happens when:\n\
+                 • A tool keeps failing and the model retries indefinitely\n\
+                 • The model's tool requests don't match what the tools expect\n\
+                 • The conversation history is in a confused state\n\n\
+                 To fix this:\n\
+                 1. Type /clear to reset the conversation\n\
+                 2. Try your request again with different wording\n\
+                 3. If using tools, check that the tool arguments are correct"
+            )
+        } else if error_str.contains("connection") || error_str.contains("connect") {
+            anyhow::anyhow!(
+                "Cannot connect to Ollama: {}\n\n\
+                 Make sure Ollama is running (`ollama serve`) and \
+                 the model is pulled (`ollama pull {}`).",
+                e,
+                self.model_name
+            )
+        } else {
+            anyhow::anyhow!(
+                "Ollama request failed: {}\n\n\
+                 Make sure Ollama is running (`ollama serve`) and \
+                 the model is pulled (`ollama pull {}`).",
+                e,
+                self.model_name
+            )
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_http;
     use serde_json::json;
 
+    #[test]
+    fn test_attachments_are_taken_with_their_result() {
+        let store = AttachmentStore::default();
+        let image = Attachment {
+            mime_type: "image/png".to_string(),
+            data: "AAAA".to_string(),
+            uri: None,
+        };
+        let first = store.add(image.clone());
+        let second = store.add(image.clone());
+        assert_eq!(first, "[attachment 1: image/png, 3 bytes, shown to the user]");
+
+        assert_eq!(store.take_for(&format!("Thumbnail:\n{}", second)), vec![image.clone()]);
+        assert!(store.take_for(&second).is_empty());
+        assert_eq!(store.take_for(&first), vec![image]);
+    }
+
+    #[test]
+    fn test_images_are_sent_only_with_vision() {
+        let image = Attachment {
+            mime_type: "image/jpeg".to_string(),
+            data: "/9j/AAAA".to_string(),
+            uri: None,
+        };
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.add_user_message_with_attachments("What is this part?".to_string(), vec![image.clone()]);
+        agent.push_stream_event(StreamEvent::ToolResult {
+            call_id: "get_thumbnail".to_string(),
+            content: "[attachment 1: image/jpeg, 6 bytes, shown to the user]".to_string(),
+            attachments: vec![image],
+        });
+
+        let messages: Vec<_> = agent
+            .chat_history()
+            .iter()
+            .flat_map(|msg| msg.to_rig_messages(true))
+            .collect();
+        assert_eq!(messages.len(), 3);
+        let Message::User { content } = &messages[0] else {
+            panic!("expected a user message");
+        };
+        let Some(UserContent::Image(image)) = content.iter().nth(1) else {
+            panic!("expected an image");
+        };
+        assert_eq!(image.data, message::DocumentSourceKind::Base64("/9j/AAAA".to_string()));
+        assert_eq!(image.media_type, Some(ImageMediaType::JPEG));
+        let Message::User { content } = &messages[2] else {
+            panic!("expected the tool's images");
+        };
+        assert_eq!(content.first(), UserContent::text("Images returned by the tool call get_thumbnail:"));
+
+        let messages: Vec<_> = agent
+            .chat_history()
+            .iter()
+            .flat_map(|msg| msg.to_rig_messages(false))
+            .collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[0], Message::user("What is this part?"));
+    }
+
+    #[test]
+    fn test_history_maps_to_rig_messages() {
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.add_notice("Type /help for available commands".to_string());
+        agent.add_user_message("List the files".to_string());
+        agent.push_stream_event(StreamEvent::Text("Let me look.".to_string()));
+        agent.push_stream_event(StreamEvent::ToolCall(ToolCallRequest {
+            tool_name: "list_directory".to_string(),
+            arguments: r#"{"path":"."}"#.to_string(),
+            call_id: "list_directory".to_string(),
+        }));
+        agent.push_stream_event(StreamEvent::ToolResult {
+            call_id: "list_directory".to_string(),
+            content: "Cargo.toml".to_string(),
+            attachments: Vec::new(),
+        });
+        agent.push_stream_event(StreamEvent::Text("There is one file.".to_string()));
+
+        let roles: Vec<_> = agent.chat_history().iter().map(|m| m.role.clone()).collect();
+        assert_eq!(
+            roles,
+            vec![
+                MessageRole::Notice,
+                MessageRole::User,
+                MessageRole::Assistant,
+                MessageRole::ToolResult,
+                MessageRole::Assistant
+            ]
+        );
+
+        let messages: Vec<_> = agent
+            .chat_history()
+            .iter()
+            .flat_map(|msg| msg.to_rig_messages(true))
+            .collect();
+        // The notice is not sent
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0], Message::user("List the files"));
+
+        let Message::Assistant { content, .. } = &messages[1] else {
+            panic!("expected an assistant message");
+        };
+        assert_eq!(content.len(), 2);
+        let Some(AssistantContent::ToolCall(call)) = content.iter().nth(1) else {
+            panic!("expected a tool call");
+        };
+        assert_eq!(call.id, "list_directory");
+        assert_eq!(call.function.arguments, json!({"path": "."}));
+
+        let Message::User { content } = &messages[2] else {
+            panic!("expected a tool result");
+        };
+        let UserContent::ToolResult(result) = content.first() else {
+            panic!("expected a tool result");
+        };
+        assert_eq!(result.id, call.id);
+    }
+
+    /// Streamed reply with two text chunks
+    const HELLO_REPLY: &str = concat!(
+        r#"{"model":"stub","created_at":"2026-01-01T00:00:00Z","message":{"role":"assistant","content":"Hello from "},"done":false}"#,
+        "\n",
+        r#"{"model":"stub","created_at":"2026-01-01T00:00:00Z","message":{"role":"assistant","content":"the stub"},"done":false}"#,
+        "\n",
+        r#"{"model":"stub","created_at":"2026-01-01T00:00:00Z","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop"}"#,
+        "\n"
+    );
+
+    /// Serve canned Ollama `/api/chat` replies, one per request, and return the requests received
+    async fn stub_ollama(replies: Vec<&'static str>) -> (String, tokio::task::JoinHandle<Vec<String>>) {
+        let responses = replies
+            .into_iter()
+            .map(|body| test_http::ok("application/x-ndjson", body))
+            .collect();
+        let (addr, server) = test_http::serve_in_order(responses).await;
+        let requests = tokio::spawn(async move {
+            server.await.unwrap().iter().map(ToString::to_string).collect()
+        });
+        (format!("http://{}/", addr), requests)
+    }
+
+    /// A tool that takes a while and records how many calls overlap
+    #[derive(Default)]
+    struct SlowTool {
+        in_flight: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+        max_in_flight: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    impl rig::tool::Tool for SlowTool {
+        const NAME: &'static str = "slow";
+        type Error = McpToolError;
+        type Args = serde_json::Value;
+        type Output = String;
+
+        async fn definition(&self, _prompt: String) -> rig::completion::ToolDefinition {
+            rig::completion::ToolDefinition {
+                name: Self::NAME.to_string(),
+                description: "Slow tool".to_string(),
+                parameters: json!({"type": "object"}),
+            }
+        }
+
+        async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+            use std::sync::atomic::Ordering;
+            let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+            tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+            Ok(format!("done {}", args["n"]))
+        }
+    }
+
+    /// A stdio MCP server named `name` offering one tool, `tool`
+    #[cfg(unix)]
+    fn stdio_mcp_stub(name: &str, tool: &str) -> McpServerConfig {
+        let script = r#"
+while read -r line; do
+  id=$(echo "$line" | sed 's/.*"id":\([0-9]*\).*/\1/')
+  case "$line" in
+    *'"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"protocolVersion":"2025-06-18","capabilities":{"tools":{}},"serverInfo":{"name":"stub","version":"1"}}}' ;;
+    *'"tools/list"'*)
+      echo '{"jsonrpc":"2.0","id":'"$id"',"result":{"tools":[{"name":"'"$TOOL"'","inputSchema":{"type":"object"}}]}}' ;;
+  esac
+done
+"#;
+        let mut server = McpServerConfig::stdio(name.to_string(), "sh".to_string(), vec!["-c".to_string(), script.to_string()]);
+        server.env.insert("TOOL".to_string(), tool.to_string());
+        server
+    }
+
+    /// Names of the tools on the agent's tool server
+    async fn tool_names(agent: &Agent) -> Vec<String> {
+        let defs = agent.tool_server_handle().unwrap().get_tool_defs(None).await.unwrap();
+        defs.into_iter().map(|def| def.name).collect()
+    }
+
+    #[cfg(unix)]
     #[tokio::test]
-    async fn test_detects_thumbnail_tool_name() {
-        let client = SimpleMcpClient::new("http://localhost:9999".to_string());
-        
-        // Simulate a base64 PNG image (1x1 pixel transparent PNG)
-        let base64_png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
-        let result_value = json!(base64_png);
-        
-        let converted = client.try_convert_to_image_content("pcli2_asset_thumbnail", &result_value).await;
-        
-        assert!(converted.is_some());
-        let converted_str = converted.unwrap();
-        assert!(converted_str.contains("\"type\": \"image\""));
-        assert!(converted_str.contains("\"mimeType\": \"image/png\""));
-        assert!(converted_str.contains(base64_png));
+    async fn test_mcp_servers_are_managed_live() {
+        let server = stdio_mcp_stub("pcli2", "folder_list");
+
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.connect_mcp_servers(&[]).await;
+        assert_eq!(tool_names(&agent).await.len(), tools::BUILTIN_TOOL_NAMES.len());
+
+        assert_eq!(agent.reconnect_mcp_server(&server).await.unwrap(), 1);
+        assert_eq!(agent.reconnect_mcp_server(&server).await.unwrap(), 1);
+        assert_eq!(agent.mcp_connected(), ["pcli2"]);
+        assert!(tool_names(&agent).await.contains(&"pcli2__folder_list".to_string()));
+        assert!(agent.preamble().contains("pcli2__folder_list"));
+
+        assert!(agent.disconnect_mcp_server("pcli2"));
+        assert!(!agent.disconnect_mcp_server("pcli2"));
+        assert_eq!(agent.mcp_server_count(), 0);
+        assert_eq!(tool_names(&agent).await.len(), tools::BUILTIN_TOOL_NAMES.len());
+        assert!(!agent.preamble().contains("pcli2__folder_list"));
+
+        let unreachable = McpServerConfig::stdio("broken".to_string(), "/nonexistent/mcp-server".to_string(), Vec::new());
+        assert!(agent.reconnect_mcp_server(&unreachable).await.is_err());
+        assert_eq!(agent.mcp_server_count(), 0);
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn test_detects_jpeg_mime_type() {
-        let client = SimpleMcpClient::new("http://localhost:9999".to_string());
-        
-        // Simulate a base64 JPEG image (starts with /9j/)
-        let base64_jpeg = "/9j/4AAQSkZJRgABAQEASABIAAD/2wBDAAgGBgcGBQgHBwcJCQgKDBQNDAsLDBkSEw8UHRofHh0aHBwgJC4nICIsIxwcKDcpLDAxNDQ0Hyc5PTgyPC4zNDL/2wBDAQkJCQwLDBgNDRgyIRwhMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjL/wAARCAABAAEDASIAAhEBAxEB/8QAFQABAQAAAAAAAAAAAAAAAAAAAAn/xAAUEAEAAAAAAAAAAAAAAAAAAAAA/8QAFQEBAQAAAAAAAAAAAAAAAAAAAAX/xAAUEQEAAAAAAAAAAAAAAAAAAAAA/9oADAMBEQCEAwEPwAB//9k=";
-        let result_value = json!(base64_jpeg);
-        
-        let converted = client.try_convert_to_image_content("pcli2_asset_thumbnail", &result_value).await;
-        
-        assert!(converted.is_some());
-        let converted_str = converted.unwrap();
-        assert!(converted_str.contains("\"mimeType\": \"image/jpeg\""));
+    async fn test_mcp_tools_are_namespaced_by_server() {
+        let prod = stdio_mcp_stub("prod", "search");
+        let mut staging = stdio_mcp_stub("staging", "search");
+        let mut raw = stdio_mcp_stub("raw", "search");
+        raw.prefix = Some(String::new());
+        let mut clash = stdio_mcp_stub("clash", "search");
+        clash.prefix = Some("prod".to_string());
+
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.connect_mcp_servers(&[prod, staging.clone(), raw, clash]).await;
+        let names = tool_names(&agent).await;
+        let mcp_names: Vec<_> = names.iter().filter(|name| name.ends_with("search")).collect();
+        assert_eq!(mcp_names.len(), 3);
+        assert!(names.contains(&"prod__search".to_string()));
+        assert!(names.contains(&"search".to_string()));
+        // The second `prod__search` is skipped rather than shadowing the first
+        assert_eq!(agent.tool_server_name("prod__search"), Some("prod"));
+        assert_eq!(agent.tool_server_name("staging__search"), Some("staging"));
+        assert_eq!(agent.tool_server_name("read_file"), None);
+
+        // A tool named like a built-in one is skipped
+        staging.prefix = Some(String::new());
+        staging.env.insert("TOOL".to_string(), "read_file".to_string());
+        agent.reconnect_mcp_server(&staging).await.unwrap();
+        assert_eq!(agent.tool_server_name("read_file"), None);
+        agent.shutdown_mcp().await;
     }
 
+    #[cfg(unix)]
     #[tokio::test]
-    async fn test_rejects_non_image_tool() {
-        let client = SimpleMcpClient::new("http://localhost:9999".to_string());
-        
-        let base64_data = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
-        let result_value = json!(base64_data);
-        
-        // Tool name doesn't contain 'thumbnail' or 'image'
-        let converted = client.try_convert_to_image_content("pcli2_folder_list", &result_value).await;
-        
-        assert!(converted.is_none());
+    async fn test_tools_are_filtered_and_toggled() {
+        let mut excluded = stdio_mcp_stub("excluded", "asset_delete");
+        excluded.exclude_tools = vec!["asset_*".to_string()];
+        let mut included = stdio_mcp_stub("included", "folder_list");
+        included.include_tools = vec!["folder_*".to_string()];
+
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.connect_mcp_servers(&[excluded, included]).await;
+        assert_eq!(agent.mcp_connected(), ["excluded", "included"]);
+        assert!(!agent.tools().iter().any(|tool| tool.name.contains("asset_delete")));
+        assert!(tool_names(&agent).await.contains(&"included__folder_list".to_string()));
+
+        assert_eq!(agent.set_tools_enabled("included__*", false), 1);
+        assert_eq!(agent.set_tools_enabled("run_command", false), 1);
+        assert_eq!(agent.set_tools_enabled("nothing_*", false), 0);
+        let names = tool_names(&agent).await;
+        assert!(!names.contains(&"included__folder_list".to_string()));
+        assert!(!names.contains(&"run_command".to_string()));
+        assert_eq!(names.len(), tools::BUILTIN_TOOL_NAMES.len() - 1);
+        assert!(!agent.preamble().contains("run_command"));
+        assert!(!agent.preamble().contains("folder_list"));
+        let disabled: Vec<_> = agent.tools().iter().filter(|tool| !tool.enabled).map(|tool| tool.name.as_str()).collect();
+        assert_eq!(disabled, ["run_command", "included__folder_list"]);
+
+        // Switched-off tools stay off when their server reconnects
+        let mut included = stdio_mcp_stub("included", "folder_list");
+        included.include_tools = vec!["folder_*".to_string()];
+        agent.reconnect_mcp_server(&included).await.unwrap();
+        assert!(!tool_names(&agent).await.contains(&"included__folder_list".to_string()));
+
+        assert_eq!(agent.set_tools_enabled("*", true), tools::BUILTIN_TOOL_NAMES.len() + 1);
+        assert!(agent.preamble().contains("included__folder_list"));
+        assert!(agent.preamble().contains("run_command"));
+        agent.shutdown_mcp().await;
     }
 
     #[tokio::test]
-    async fn test_rejects_invalid_base64() {
-        let client = SimpleMcpClient::new("http://localhost:9999".to_string());
-        
-        // Invalid base64 (contains invalid characters)
-        let invalid_base64 = "This is not valid base64!!!";
-        let result_value = json!(invalid_base64);
-        
-        let converted = client.try_convert_to_image_content("pcli2_asset_thumbnail", &result_value).await;
-        
-        assert!(converted.is_none());
+    async fn test_tool_selection_advertises_top_k_tools() {
+        // Built-in tools in registration order: read_file, write_file, list_directory,
+        // run_command, search_code; the query is closest to read_file, then list_directory
+        const TOOL_EMBEDDINGS: &str = r#"{"model":"stub","embeddings":[[1,0],[0,1],[1,1],[-1,0],[0,-1]]}"#;
+        const QUERY_EMBEDDING: &str = r#"{"model":"stub","embeddings":[[1,0.1]]}"#;
+        let (host, server) =
+            stub_ollama(vec![TOOL_EMBEDDINGS, QUERY_EMBEDDING, HELLO_REPLY, QUERY_EMBEDDING, HELLO_REPLY]).await;
+        let mut config = Config {
+            host,
+            ..Config::default()
+        };
+        config.tool_selection.enabled = true;
+        config.tool_selection.top_k = 2;
+
+        let mut agent = Agent::new(&config).unwrap();
+        agent.connect_mcp_servers(&[]).await;
+        agent.add_user_message("Show me main.rs".to_string());
+        agent.chat_without_history(String::new(), |_| async {}).await.unwrap();
+
+        // A request sharing the index only embeds the new message
+        let mut request_agent = Agent::new(&config).unwrap();
+        request_agent.set_tool_server_handle(agent.tool_server_handle().unwrap().clone());
+        request_agent.set_tool_index(agent.tool_index().cloned());
+        request_agent.add_user_message("And lib.rs?".to_string());
+        request_agent.chat_without_history(String::new(), |_| async {}).await.unwrap();
+
+        let requests = server.await.unwrap();
+        assert!(requests[0].starts_with("POST /api/embed"));
+        assert!(requests[0].contains("read_file: Read the contents"));
+        assert!(requests[1].contains("Show me main.rs"));
+        assert!(requests[3].starts_with("POST /api/embed"));
+        for chat in [&requests[2], &requests[4]] {
+            assert!(chat.starts_with("POST /api/chat"));
+            assert!(chat.contains(r#""name":"read_file""#));
+            assert!(chat.contains(r#""name":"list_directory""#));
+            assert!(!chat.contains(r#""name":"run_command""#));
+            // The preamble does not list the tools left out either
+            assert!(!chat.contains("run_command"));
+        }
     }
 
     #[tokio::test]
-    async fn test_handles_object_result_with_data_field() {
-        let client = SimpleMcpClient::new("http://localhost:9999".to_string());
-        
-        let base64_png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
-        let result_value = json!({
-            "data": base64_png,
-            "other_field": "value"
-        });
-        
-        let converted = client.try_convert_to_image_content("pcli2_asset_thumbnail", &result_value).await;
-        
-        assert!(converted.is_some());
-        let converted_str = converted.unwrap();
-        assert!(converted_str.contains("\"type\": \"image\""));
-        assert!(converted_str.contains(base64_png));
+    async fn test_requests_go_to_configured_host() {
+        let (host, server) = stub_ollama(vec![HELLO_REPLY]).await;
+        let config = Config {
+            host,
+            ..Config::default()
+        };
+        let mut agent = Agent::new(&config).unwrap();
+        agent.add_user_message("Hi".to_string());
+
+        let mut chunks = Vec::new();
+        let response = agent
+            .chat_without_history("Hi".to_string(), |event| {
+                if let StreamEvent::Text(text) = event {
+                    chunks.push(text);
+                }
+                async {}
+            })
+            .await
+            .unwrap();
+
+        assert!(server.await.unwrap()[0].starts_with("POST /api/chat HTTP/1.1"));
+        assert_eq!(response, "Hello from the stub");
+        assert_eq!(chunks, vec!["Hello from ", "the stub"]);
     }
 
     #[tokio::test]
-    async fn test_handles_object_result_with_text_field() {
-        let client = SimpleMcpClient::new("http://localhost:9999".to_string());
-        
-        let base64_png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
-        let result_value = json!({
-            "text": base64_png
-        });
-        
-        let converted = client.try_convert_to_image_content("pcli2_image_get", &result_value).await;
-        
-        assert!(converted.is_some());
-        let converted_str = converted.unwrap();
-        assert!(converted_str.contains("\"type\": \"image\""));
+    async fn test_parallel_tool_calls_run_concurrently() {
+        let tool_calls = concat!(
+            r#"{"model":"stub","created_at":"2026-01-01T00:00:00Z","message":{"role":"assistant","content":"","tool_calls":["#,
+            r#"{"function":{"name":"slow","arguments":{"n":1}}},{"function":{"name":"slow","arguments":{"n":2}}}]},"done":false}"#,
+            "\n",
+            r#"{"model":"stub","created_at":"2026-01-01T00:00:00Z","message":{"role":"assistant","content":""},"done":true,"done_reason":"stop"}"#,
+            "\n"
+        );
+        let (host, server) = stub_ollama(vec![tool_calls, HELLO_REPLY]).await;
+        let config = Config {
+            host,
+            ..Config::default()
+        };
+        let tool = SlowTool::default();
+        let max_in_flight = tool.max_in_flight.clone();
+        let mut agent = Agent::new(&config).unwrap();
+        agent.set_tool_server_handle(ToolServer::new().tool(tool).run());
+        agent.add_user_message("Run both".to_string());
+
+        let mut events = Vec::new();
+        let response = agent
+            .chat_without_history("Run both".to_string(), |event| {
+                events.push(event);
+                async {}
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(response, "Hello from the stub");
+        assert_eq!(max_in_flight.load(std::sync::atomic::Ordering::SeqCst), 2);
+        let results: Vec<_> = events
+            .iter()
+            .filter_map(|event| match event {
+                StreamEvent::ToolResult { call_id, .. } => Some(call_id.as_str()),
+                _ => None,
+            })
+            .collect();
+        // Calls of the same tool without ids get distinct synthesized ids
+        assert_eq!(results.len(), 2);
+        assert!(results.contains(&"slow#0") && results.contains(&"slow#1"));
+
+        // The second request carries both calls and both results, in call order,
+        // with results matched to Ollama by tool name
+        let requests = server.await.unwrap();
+        let second = &requests[1];
+        let first_result = second.find("done 1").unwrap();
+        assert!(second[first_result..].contains("done 2"));
+        assert!(!second.contains("slow#"));
     }
 }
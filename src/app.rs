@@ -1,15 +1,23 @@
 //! Main application state and logic
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use crossterm::event::{KeyEvent, KeyModifiers};
 use ratatui::Frame;
+use rmcp::model::{GetPromptResult, PromptMessageRole};
 use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 use tracing::debug;
 
-use crate::agent::{self, Agent};
-use crate::config::Config;
+use crate::agent::{Agent, MessageRole, StreamEvent};
+use crate::approval::{ApprovalDecision, ApprovalRequest, ToolApprover};
+use crate::config::{Config, McpServerConfig, ToolPolicy};
+use crate::export;
+use crate::health::{self, HealthCheck};
+use crate::images::{self, GraphicsProtocol, ImageRenderer};
+use crate::mcp::{self, Attachment, HealthState, McpClient};
+use crate::models::{self, ModelInfo};
+use crate::session::{self, Session, SessionInfo, SessionStore};
 use crate::tui::Tui;
 use crate::ui;
 
@@ -20,19 +28,135 @@ pub static LOG_BUFFER: once_cell::sync::Lazy<Arc<Mutex<Vec<String>>>> =
 /// Number of CPU samples to keep for sparkline
 const CPU_HISTORY_SIZE: usize = 20;
 
+/// Time allowed for connecting to an MCP server from `/mcp`
+const MCP_CONNECT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
+
+/// Time allowed for reading mentioned resources or getting a prompt
+const MCP_REQUEST_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Expand a leading `~/` to the home directory
+fn expand_home(path: &str) -> std::path::PathBuf {
+    match path.strip_prefix("~/").zip(dirs::home_dir()) {
+        Some((rest, home)) => home.join(rest),
+        None => std::path::PathBuf::from(path),
+    }
+}
+
+/// Read resources mentioned as `@server:uri`, from their servers' clients
+async fn read_mentions(mentions: Vec<(McpClient, String)>) -> Result<Vec<Attachment>> {
+    let mut attachments = Vec::new();
+    for (client, uri) in mentions {
+        let contents = client
+            .read_resource(&uri)
+            .await
+            .with_context(|| format!("Could not read @{}:{}", client.name(), uri))?;
+        tracing::info!("Inlined resource {} from {}", uri, client.name());
+        attachments.extend(contents.into_iter().map(Attachment::from));
+    }
+    Ok(attachments)
+}
+
+/// List the resources and resource templates of `clients`, for `/mcp resources`
+async fn resource_listing(clients: Vec<McpClient>) -> Result<String> {
+    let mut msg = String::new();
+    for client in clients {
+        msg.push_str(&format!("**{}**\n", client.name()));
+        match client.list_resources().await {
+            Ok(resources) if resources.is_empty() => msg.push_str("  (no resources)\n"),
+            Ok(resources) => {
+                for resource in resources {
+                    msg.push_str(&format!("  `@{}:{}` {}", client.name(), resource.uri, resource.name));
+                    if let Some(description) = &resource.description {
+                        msg.push_str(&format!(" - {}", description));
+                    }
+                    msg.push('\n');
+                }
+            }
+            Err(e) => msg.push_str(&format!("  ⚠ {:#}\n", e)),
+        }
+        if let Ok(templates) = client.list_resource_templates().await {
+            for template in templates {
+                msg.push_str(&format!(
+                    "  `@{}:{}` {} (template)\n",
+                    client.name(),
+                    template.uri_template,
+                    template.name
+                ));
+            }
+        }
+        msg.push('\n');
+    }
+    if msg.is_empty() {
+        msg = "No connected MCP server offers resources.".to_string();
+    } else {
+        msg.push_str("Mention a resource as `@server:uri` to inline it into your message.");
+    }
+    Ok(msg)
+}
+
+/// List the prompts of `clients`, for `/mcp prompts`
+async fn prompt_listing(clients: Vec<McpClient>) -> Result<String> {
+    let mut msg = String::new();
+    for client in clients {
+        msg.push_str(&format!("**{}**\n", client.name()));
+        match client.list_prompts().await {
+            Ok(prompts) if prompts.is_empty() => msg.push_str("  (no prompts)\n"),
+            Ok(prompts) => {
+                for prompt in prompts {
+                    msg.push_str(&format!("  `/{}:{}`", client.name(), prompt.name));
+                    if let Some(description) = &prompt.description {
+                        msg.push_str(&format!(" - {}", description));
+                    }
+                    msg.push_str(&format!("\n    {}\n", mcp::prompt_usage(&prompt)));
+                }
+            }
+            Err(e) => msg.push_str(&format!("  ⚠ {:#}\n", e)),
+        }
+        msg.push('\n');
+    }
+    if msg.is_empty() {
+        msg = "No connected MCP server offers prompts.".to_string();
+    } else {
+        msg.push_str("Run a prompt as `/server:prompt name=value ...`.");
+    }
+    Ok(msg)
+}
+
 /// Messages for the app loop
 #[derive(Debug)]
 pub enum AppMessage {
-    Response(Result<String>),
+    /// Partial output streamed from the model, tagged with its request id
+    Chunk(u64, StreamEvent),
+    /// Final result of a request, tagged with its request id
+    Response(u64, Result<String>),
+    /// Installed models, fetched for the model picker
+    Models(Result<Vec<ModelInfo>>),
+    /// Installed models, fetched to check the model named in `/model <name>`
+    ModelLookup(String, Result<Vec<ModelInfo>>),
+    /// Capabilities of a model, fetched when it becomes active
+    Capabilities(String, Result<Option<Vec<String>>>),
+    /// Tools of an MCP server the health monitor reconnected
+    McpReconnected(String, Vec<rmcp::model::Tool>),
+    /// Tools of an MCP server, listed again after it announced a change
+    McpToolsChanged(String, Result<Vec<rmcp::model::Tool>>),
+    /// An MCP server connected from `/mcp add`, `enable` or `reconnect`, with its tools
+    McpConnected(Box<McpServerConfig>, Result<(McpClient, Vec<rmcp::model::Tool>)>),
+    /// Resources mentioned in a message, read before it is sent, tagged with the
+    /// request id
+    Mentions(u64, String, Result<Vec<Attachment>>),
+    /// An MCP prompt run as `/server:prompt`, tagged with the request id
+    Prompt(u64, String, Result<GetPromptResult>),
+    /// Output of `/mcp resources` or `/mcp prompts`, tagged with the request id
+    McpListing(u64, Result<String>),
 }
 
-/// Pending tool call awaiting confirmation
-#[derive(Debug, Clone)]
-pub struct PendingToolCall {
-    pub tool_name: String,
-    pub arguments: String,
-    #[allow(dead_code)]
-    pub call_id: String,
+/// State of the `/model` picker
+#[derive(Debug)]
+pub struct ModelPicker {
+    /// Installed models
+    pub models: Vec<ModelInfo>,
+    /// Index of the highlighted model
+    pub selected: usize,
 }
 
 /// Application state
@@ -54,8 +178,14 @@ pub struct App {
     is_thinking: bool,
     /// When thinking started (for animation)
     thinking_start: std::time::Instant,
+    /// Whether the last chat message is an assistant reply still being streamed
+    streaming: bool,
+    /// Id of the latest request; output of earlier requests is dropped
+    request_id: u64,
     /// Pending tool call awaiting confirmation
-    pending_tool_call: Option<PendingToolCall>,
+    pending_tool_call: Option<ApprovalRequest>,
+    /// Tool calls waiting behind the one currently shown for confirmation
+    queued_tool_calls: Vec<ApprovalRequest>,
     /// Log buffer for displaying in UI
     logs: Vec<String>,
     /// Max log lines to keep
@@ -74,6 +204,14 @@ pub struct App {
     show_help: bool,
     /// Scroll offset for help modal
     help_scroll_offset: usize,
+    /// Model picker, when open
+    model_picker: Option<ModelPicker>,
+    /// Whether the "enable YOLO mode?" confirmation is shown
+    confirm_yolo: bool,
+    /// The current session (saved after every response)
+    session: SessionInfo,
+    /// Where sessions are saved (`None` if there is no data dir)
+    sessions: Option<SessionStore>,
     /// Whether mouse capture is enabled (for click/scroll vs text selection)
     mouse_enabled: bool,
     /// CPU usage history for sparkline (percentage values 0-100)
@@ -90,14 +228,22 @@ pub struct App {
     history_original: String,
     /// Horizontal scroll offset for input (when text exceeds width)
     input_hscroll_offset: usize,
+    /// Draws image attachments in the chat
+    images: ImageRenderer,
+    /// Images from `/attach`, sent with the next message
+    pending_attachments: Vec<Attachment>,
+    /// Health monitor tasks of the connected MCP servers
+    health_monitors: Vec<(McpClient, CancellationToken)>,
 }
 
 impl App {
     /// Create a new application
     pub fn new(config: Config) -> Self {
         let agent = Agent::new(&config).expect("Failed to create agent");
+        let session = SessionInfo::new(&config.model);
         let mut sys = sysinfo::System::new();
         sys.refresh_cpu_usage();
+        let images = ImageRenderer::new(config.graphics.unwrap_or_else(GraphicsProtocol::detect));
 
         Self {
             agent,
@@ -108,7 +254,10 @@ impl App {
             status: "Ready".to_string(),
             is_thinking: false,
             thinking_start: std::time::Instant::now(),
+            streaming: false,
+            request_id: 0,
             pending_tool_call: None,
+            queued_tool_calls: Vec::new(),
             logs: Vec::new(),
             max_logs: 100,
             scroll_offset: 0,
@@ -118,6 +267,10 @@ impl App {
             message_queue: Vec::new(),
             show_help: false,
             help_scroll_offset: 0,
+            model_picker: None,
+            confirm_yolo: false,
+            session,
+            sessions: SessionStore::default_location(),
             mouse_enabled: false,
             cpu_history: Vec::new(),
             sys,
@@ -126,6 +279,9 @@ impl App {
             history_index: 0,
             history_original: String::new(),
             input_hscroll_offset: 0,
+            images,
+            pending_attachments: Vec::new(),
+            health_monitors: Vec::new(),
         }
     }
 
@@ -136,19 +292,36 @@ impl App {
         // Create channel for async responses
         let (tx, mut rx) = mpsc::channel::<AppMessage>(32);
 
-        // Add welcome banner as first message in chat history
-        self.add_welcome_banner();
+        // Create channel for tool calls awaiting confirmation; every tool registered
+        // below is gated by this approver
+        let (approval_tx, mut approval_rx) = mpsc::channel::<ApprovalRequest>(32);
+        self.agent.set_tool_approver(ToolApprover::new(
+            approval_tx,
+            self.config.yolo,
+            self.config.permissions.clone(),
+        ));
+
+        // Add welcome banner as first message in chat history (unless resuming)
+        if self.agent.chat_history().is_empty() {
+            self.add_welcome_banner();
+        } else {
+            self.status = format!("Resumed session {}", self.session.label());
+        }
 
-        // Connect to MCP servers
+        // Connect to MCP servers and start the tool server (built-in tools are
+        // registered even when no MCP servers are configured)
         let mcp_servers = self.config.mcp_servers.clone();
         if !mcp_servers.is_empty() {
             self.status = "Connecting to MCP servers...".to_string();
-            // Connect to MCP servers asynchronously
-            self.agent.connect_mcp_servers(&mcp_servers).await;
+        }
+        self.agent.connect_mcp_servers(&mcp_servers).await;
+        if !mcp_servers.is_empty() {
             let connected_count = self.agent.mcp_server_count();
             self.status = format!("Ready | {} MCP server(s) connected", connected_count);
             debug!("Connected to {} MCP servers", connected_count);
         }
+        self.warn_unmatched_permissions();
+        self.fetch_capabilities(&tx);
 
         // Timer for spinner animation (500ms interval)
         let mut spinner_timer = tokio::time::interval(std::time::Duration::from_millis(500));
@@ -161,8 +334,12 @@ impl App {
 
         // Main event loop
         loop {
+            // Keep a health monitor running for every connected MCP server
+            self.monitor_mcp_servers(&tx);
+
             // Draw the UI
             tui.draw(|frame| self.render(frame))?;
+            tui.paint_images(&mut self.images)?;
 
             // Handle events and messages
             tokio::select! {
@@ -176,6 +353,10 @@ impl App {
                 Some(msg) = rx.recv() => {
                     self.handle_response(msg, &tx).await?;
                 }
+                // Handle tool calls awaiting confirmation
+                Some(request) = approval_rx.recv() => {
+                    self.request_tool_approval(request);
+                }
                 // Timer for spinner animation
                 _ = spinner_timer.tick() => {
                     // Force redraw when thinking to animate spinner
@@ -186,6 +367,7 @@ impl App {
                 // Timer for syncing logs from shared buffer
                 _ = log_timer.tick() => {
                     self.sync_logs();
+                    self.refresh_changed_mcp_tools(&tx);
                 }
                 // Timer for CPU sampling
                 _ = cpu_timer.tick() => {
@@ -199,9 +381,90 @@ impl App {
             }
         }
 
+        self.save_session();
+        for (_, cancel) in self.health_monitors.drain(..) {
+            cancel.cancel();
+        }
+        self.agent.shutdown_mcp().await;
+
         Ok(())
     }
 
+    /// Start health monitors for newly connected MCP servers and stop those of
+    /// servers that were disconnected or replaced
+    fn monitor_mcp_servers(&mut self, tx: &mpsc::Sender<AppMessage>) {
+        let clients: Vec<&McpClient> = self.agent.mcp_clients().collect();
+        self.health_monitors.retain(|(client, cancel)| {
+            let connected = clients.iter().any(|c| c.same_connection(client));
+            if !connected {
+                cancel.cancel();
+            }
+            connected
+        });
+
+        for client in clients {
+            if self.health_monitors.iter().any(|(c, _)| c.same_connection(client)) {
+                continue;
+            }
+            debug!("Starting health monitor for MCP server '{}'", client.name());
+            let cancel = CancellationToken::new();
+            tokio::spawn(health::monitor(
+                client.clone(),
+                HealthCheck::default(),
+                tx.clone(),
+                cancel.clone(),
+            ));
+            self.health_monitors.push((client.clone(), cancel));
+        }
+    }
+
+    /// List the tools again of MCP servers that announced a tool list change
+    fn refresh_changed_mcp_tools(&self, tx: &mpsc::Sender<AppMessage>) {
+        for client in self.agent.mcp_clients().filter(|client| client.take_tools_changed()) {
+            let client = client.clone();
+            let tx = tx.clone();
+            tokio::spawn(async move {
+                let result = client.list_tools().await;
+                let _ = tx.send(AppMessage::McpToolsChanged(client.name().to_string(), result)).await;
+            });
+        }
+    }
+
+    /// Continue a saved session
+    pub fn resume(&mut self, session: Session) {
+        tracing::info!(
+            "Resuming session {} ({} messages)",
+            session.info.label(),
+            session.messages.len()
+        );
+        self.session = session.info;
+        self.agent.set_chat_history(session.messages);
+    }
+
+    /// Save the current session (sessions without a user message are skipped)
+    fn save_session(&mut self) {
+        if self.sessions.is_none() || !session::has_conversation(self.agent.chat_history()) {
+            return;
+        }
+        match self.write_session() {
+            Ok(path) => debug!("Saved session to {}", path.display()),
+            Err(e) => tracing::error!("Failed to save session: {:#}", e),
+        }
+    }
+
+    /// Write the current session to the session store
+    fn write_session(&mut self) -> Result<std::path::PathBuf> {
+        let store = self
+            .sessions
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("Sessions cannot be saved: no data directory on this system"))?;
+
+        self.session.updated_at = chrono::Utc::now();
+        self.session.model = self.agent.model_name().to_string();
+        self.session.mcp_servers = self.agent.mcp_connected().to_vec();
+        store.save(&self.session, self.agent.chat_history())
+    }
+
     /// Add the welcome banner to chat history
     fn add_welcome_banner(&mut self) {
         let banner = r#"
@@ -211,7 +474,7 @@ Type /help for available commands · Type /quit to exit
 "#
         .to_string();
 
-        self.agent.add_assistant_message(banner);
+        self.agent.add_notice(banner);
     }
 
     /// Handle an event
@@ -228,14 +491,23 @@ Type /help for available commands · Type /quit to exit
             if let crossterm::event::Event::Key(key) = event {
                 match key.code {
                     KeyCode::Char('y') | KeyCode::Char('Y') | KeyCode::Enter => {
-                        // Confirm tool execution
-                        self.execute_pending_tool().await?;
+                        // Confirm tool execution (this call only)
+                        self.resolve_pending_tool(ApprovalDecision::Approve);
+                        return Ok(());
+                    }
+                    KeyCode::Char('s') | KeyCode::Char('S') => {
+                        // Allow this tool for the rest of the session
+                        self.resolve_pending_tool(ApprovalDecision::ApproveForSession);
+                        return Ok(());
+                    }
+                    KeyCode::Char('a') | KeyCode::Char('A') => {
+                        // Always allow this tool (persisted to config)
+                        self.resolve_pending_tool(ApprovalDecision::AlwaysApprove);
                         return Ok(());
                     }
                     KeyCode::Char('n') | KeyCode::Char('N') | KeyCode::Esc => {
-                        // Cancel tool execution
-                        self.pending_tool_call = None;
-                        self.status = "Tool execution cancelled".to_string();
+                        // Decline tool execution
+                        self.resolve_pending_tool(ApprovalDecision::Decline);
                         return Ok(());
                     }
                     _ => {}
@@ -261,12 +533,10 @@ Type /help for available commands · Type /quit to exit
                 }
                 self.handle_key_event(key, tx).await?;
             }
-            crossterm::event::Event::Mouse(mouse) => {
-                // Only handle mouse events if mouse is enabled
-                if self.mouse_enabled {
-                    let area = tui.area();
-                    self.handle_mouse(mouse, area);
-                }
+            // Only handle mouse events if mouse is enabled
+            crossterm::event::Event::Mouse(mouse) if self.mouse_enabled => {
+                let area = tui.area();
+                self.handle_mouse(mouse, area);
             }
             crossterm::event::Event::Resize(_, _) => {
                 // Terminal was resized
@@ -285,6 +555,23 @@ Type /help for available commands · Type /quit to exit
     ) -> Result<()> {
         use crossterm::event::{KeyCode, KeyModifiers};
 
+        // Handle YOLO confirmation
+        if self.confirm_yolo {
+            self.confirm_yolo = false;
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                self.set_yolo(true);
+            } else {
+                self.status = "YOLO mode not enabled".to_string();
+            }
+            return Ok(());
+        }
+
+        // Handle model picker
+        if self.model_picker.is_some() {
+            self.handle_model_picker_key(key, tx);
+            return Ok(());
+        }
+
         // Handle help modal
         if self.show_help {
             match key.code {
@@ -315,10 +602,8 @@ Type /help for available commands · Type /quit to exit
 
         match key.code {
             // Cancel in-flight request (Esc)
-            KeyCode::Esc => {
-                if self.is_thinking {
-                    self.cancel_request();
-                }
+            KeyCode::Esc if self.is_thinking => {
+                self.cancel_request();
             }
 
             // Quit
@@ -333,33 +618,25 @@ Type /help for available commands · Type /quit to exit
             }
 
             // Enter - send message (only when input is focused)
-            KeyCode::Enter => {
-                if self.focus_pane == 1 && !self.input.trim().is_empty() {
-                    self.send_message(tx).await?;
-                }
+            KeyCode::Enter if self.focus_pane == 1 && !self.input.trim().is_empty() => {
+                self.send_message(tx).await?;
             }
 
             // Text input (only when input pane is focused)
-            KeyCode::Char(c) => {
-                if self.focus_pane == 1 {
-                    self.input.insert(self.cursor_pos, c);
-                    self.cursor_pos += 1;
-                }
+            KeyCode::Char(c) if self.focus_pane == 1 => {
+                self.input.insert(self.cursor_pos, c);
+                self.cursor_pos += 1;
             }
 
             // Backspace (only when input pane is focused)
-            KeyCode::Backspace => {
-                if self.focus_pane == 1 && self.cursor_pos > 0 {
-                    self.input.remove(self.cursor_pos - 1);
-                    self.cursor_pos -= 1;
-                }
+            KeyCode::Backspace if self.focus_pane == 1 && self.cursor_pos > 0 => {
+                self.input.remove(self.cursor_pos - 1);
+                self.cursor_pos -= 1;
             }
 
             // Delete (only when input pane is focused)
-            KeyCode::Delete => {
-                if self.focus_pane == 1 && self.cursor_pos < self.input.len() {
-                    self.input.remove(self.cursor_pos);
-                }
+            KeyCode::Delete if self.focus_pane == 1 && self.cursor_pos < self.input.len() => {
+                self.input.remove(self.cursor_pos);
             }
 
             // Arrow keys for cursor navigation (only when input pane is focused)
@@ -464,7 +741,7 @@ Type /help for available commands · Type /quit to exit
 
         // Check for internal commands
         if input.trim().starts_with('/') {
-            self.handle_command(&input).await?;
+            self.handle_command(&input, tx).await?;
             return Ok(());
         }
 
@@ -481,13 +758,82 @@ Type /help for available commands · Type /quit to exit
             return Ok(());
         }
 
-        // Add user message to history immediately
-        self.agent.add_user_message(input.clone());
+        // Inline `@server:uri` resources, read in the background; mentions of unknown
+        // servers are left alone, since they may be plain text
+        let mentions: Vec<_> = mcp::resource_mentions(&input)
+            .into_iter()
+            .filter_map(|(server, uri)| {
+                let client = self.agent.mcp_client(server)?.clone();
+                Some((client, uri.to_string()))
+            })
+            .collect();
+        if !mentions.is_empty() {
+            let message = input.clone();
+            self.start_mcp_request(
+                format!("Reading {} resource(s)...", mentions.len()),
+                read_mentions(mentions),
+                move |request_id, result| AppMessage::Mentions(request_id, message, result),
+                tx,
+            );
+            return Ok(());
+        }
+
+        self.send_with_attachments(input, Vec::new(), tx);
+        Ok(())
+    }
+
+    /// Add the user message to history, with any attached images, and send it
+    fn send_with_attachments(&mut self, input: String, mut attachments: Vec<Attachment>, tx: &mpsc::Sender<AppMessage>) {
+        attachments.splice(0..0, std::mem::take(&mut self.pending_attachments));
+        self.agent
+            .add_user_message_with_attachments(input.clone(), attachments);
+        self.start_request(input, tx);
+    }
+
+    /// Run an MCP request in the background, as a request that Esc cancels
+    ///
+    /// Messages typed meanwhile are queued; `message` reports the result.
+    fn start_mcp_request<T, F>(
+        &mut self,
+        status: String,
+        request: F,
+        message: impl FnOnce(u64, Result<T>) -> AppMessage + Send + 'static,
+        tx: &mpsc::Sender<AppMessage>,
+    ) where
+        T: Send + 'static,
+        F: std::future::Future<Output = Result<T>> + Send + 'static,
+    {
+        self.status = status;
+        self.is_thinking = true;
+        self.thinking_start = std::time::Instant::now();
+        self.request_id += 1;
+        let request_id = self.request_id;
+        let cancel_token = CancellationToken::new();
+        self.cancel_token = Some(cancel_token.clone());
+
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = tokio::select! {
+                result = tokio::time::timeout(MCP_REQUEST_TIMEOUT, request) => {
+                    result.unwrap_or_else(|_| Err(anyhow::anyhow!(
+                        "MCP server did not answer within {} seconds",
+                        MCP_REQUEST_TIMEOUT.as_secs()
+                    )))
+                }
+                _ = cancel_token.cancelled() => return,
+            };
+            let _ = tx.send(message(request_id, result)).await;
+        });
+    }
 
+    /// Send the chat history to the model in the background
+    fn start_request(&mut self, input: String, tx: &mpsc::Sender<AppMessage>) {
         // Set thinking status
         self.status = "Thinking...".to_string();
         self.is_thinking = true;
         self.thinking_start = std::time::Instant::now();
+        self.request_id += 1;
+        let request_id = self.request_id;
 
         // Create cancellation token for this request
         let cancel_token = CancellationToken::new();
@@ -501,53 +847,48 @@ Type /help for available commands · Type /quit to exit
         let tx = tx.clone();
 
         // Clone agent state for the spawned task
-        let model_name = self.agent.model_name().to_string();
+        let mut config = self.config.clone();
+        config.model = self.agent.model_name().to_string();
         let preamble = self.agent.preamble().to_string();
         let tool_server_handle = self.agent.tool_server_handle().cloned();
-        let mut chat_history = Vec::new();
-        for msg in self.agent.chat_history() {
-            chat_history.push((msg.role.clone(), msg.content.clone()));
-        }
+        let attachments = self.agent.attachment_store().clone();
+        let vision = self.agent.vision();
+        let tool_index = self.agent.tool_index().cloned();
+        let chat_history = self.agent.chat_history().to_vec();
 
         tokio::spawn(async move {
-            // Create agent with correct model
-            use crate::config::Config;
-            let config = Config {
-                model: model_name,
-                host: "http://localhost:11434".to_string(),
-                yolo: false,
-                mcp_servers: vec![],
+            // Create agent with the current model and the configured Ollama host
+            let mut agent = match Agent::new(&config) {
+                Ok(agent) => agent,
+                Err(e) => {
+                    let _ = tx.send(AppMessage::Response(request_id, Err(e))).await;
+                    return;
+                }
             };
-            let mut agent = Agent::new(&config).expect("Failed to create agent");
 
             // Restore agent state
             agent.set_preamble(preamble);
             if let Some(handle) = tool_server_handle {
                 agent.set_tool_server_handle(handle);
             }
+            agent.set_attachment_store(attachments);
+            agent.set_vision(vision);
+            agent.set_tool_index(tool_index);
 
             // Restore chat history
-            for (role, content) in chat_history {
-                match role {
-                    crate::agent::MessageRole::User => {
-                        agent.add_user_message(content);
-                    }
-                    crate::agent::MessageRole::Assistant => {
-                        agent.add_assistant_message(content);
-                    }
-                    crate::agent::MessageRole::ToolResult => {
-                        agent.add_tool_result(content);
-                    }
-                    _ => {}
-                }
-            }
+            agent.set_chat_history(chat_history);
 
             // Add timeout and cancellation support
             let result = tokio::select! {
-                // Normal request with timeout
+                // Normal request with timeout, streaming output back as it arrives
                 result = tokio::time::timeout(
                     std::time::Duration::from_secs(600),
-                    agent.chat_without_history(input_clone),
+                    agent.chat_without_history(input_clone, |event| {
+                        let tx = tx.clone();
+                        async move {
+                            let _ = tx.send(AppMessage::Chunk(request_id, event)).await;
+                        }
+                    }),
                 ) => {
                     result.unwrap_or(Err(anyhow::anyhow!("Request timed out after 10 minutes")))
                 }
@@ -557,12 +898,70 @@ Type /help for available commands · Type /quit to exit
                 }
             };
 
-            if let Err(e) = tx.send(AppMessage::Response(result)).await {
+            if let Err(e) = tx.send(AppMessage::Response(request_id, result)).await {
                 tracing::error!("Failed to send response: {}", e);
             }
         });
+    }
 
-        Ok(())
+    /// Connected MCP servers that pass `filter`, e.g. a capability check
+    fn mcp_clients_with(&self, filter: impl Fn(&McpClient) -> bool) -> Vec<McpClient> {
+        self.agent
+            .mcp_connected()
+            .iter()
+            .filter_map(|name| self.agent.mcp_client(name))
+            .filter(|client| filter(client))
+            .cloned()
+            .collect()
+    }
+
+    /// Run an MCP prompt as `/server:prompt [arguments]` in the background: its
+    /// messages are added to the chat and sent to the model when it arrives
+    fn run_prompt(&mut self, server: &str, name: &str, line: &str, tx: &mpsc::Sender<AppMessage>) {
+        if self.is_thinking {
+            self.status = "Wait for the response (or press Esc) before running a prompt".to_string();
+            return;
+        }
+        let Some(client) = self.agent.mcp_client(server).cloned() else {
+            return;
+        };
+        let (server, name, line) = (server.to_string(), name.to_string(), line.to_string());
+        let label = format!("{} from {}", name, server);
+        let request = async move {
+            let prompts = client.list_prompts().await?;
+            let prompt = prompts
+                .into_iter()
+                .find(|prompt| prompt.name == name)
+                .with_context(|| format!("{} has no prompt named {}. See /mcp prompts.", server, name))?;
+            let arguments = mcp::prompt_arguments(&prompt, &line)?;
+            client.get_prompt(&prompt.name, &arguments).await
+        };
+        self.start_mcp_request(
+            format!("Getting prompt {}...", label),
+            request,
+            move |request_id, result| AppMessage::Prompt(request_id, label, result),
+            tx,
+        );
+    }
+
+    /// Add the messages of a prompt to the chat and send them to the model
+    fn apply_prompt(&mut self, label: &str, result: GetPromptResult, tx: &mpsc::Sender<AppMessage>) {
+        tracing::info!("Running prompt {}", label);
+        for message in result.messages {
+            let (text, attachment) = mcp::prompt_content(message.content);
+            match message.role {
+                PromptMessageRole::User => self
+                    .agent
+                    .add_user_message_with_attachments(text, attachment.into_iter().collect()),
+                PromptMessageRole::Assistant => self.agent.add_assistant_message(text),
+            }
+        }
+        if let Some(last) = self.agent.chat_history().last()
+            && last.role == MessageRole::User
+        {
+            self.start_request(last.content.clone(), tx);
+        }
+        self.reset_scroll();
     }
 
     /// Handle the response from the async task
@@ -572,14 +971,38 @@ Type /help for available commands · Type /quit to exit
         tx: &mpsc::Sender<AppMessage>,
     ) -> Result<()> {
         match msg {
-            AppMessage::Response(Ok(response)) => {
+            AppMessage::Chunk(request_id, event) => {
+                // Ignore chunks that arrive after the request was cancelled
+                if request_id != self.request_id || !self.is_thinking {
+                    return Ok(());
+                }
+                if !self.streaming {
+                    self.streaming = true;
+                    self.status = "Responding...".to_string();
+                }
+                self.agent.push_stream_event(event);
+                return Ok(());
+            }
+            AppMessage::Response(request_id, _) if request_id != self.request_id => {
+                // A request cancelled before the current one was started
+                debug!("Discarding reply of earlier request {}", request_id);
+                return Ok(());
+            }
+            AppMessage::Response(_, _) if !self.is_thinking => {
+                // Cancelled: `cancel_request` already recorded the outcome
+                debug!("Discarding reply of cancelled request");
+            }
+            AppMessage::Response(_, Ok(response)) => {
                 self.is_thinking = false;
                 self.cancel_token = None;
                 debug!("Received response: {} chars", response.len());
-                if response.trim().is_empty() {
+                if std::mem::take(&mut self.streaming) {
+                    // The reply is already in the chat history, built from the streamed chunks
+                    self.status = "✓ Ready".to_string();
+                } else if response.trim().is_empty() {
                     // Empty response - report as error
                     self.status = "⚠ Empty response from model".to_string();
-                    self.agent.add_assistant_message("⚠ The model returned an empty response. This may indicate a problem with the model or the request.".to_string());
+                    self.agent.add_notice("⚠ The model returned an empty response. This may indicate a problem with the model or the request.".to_string());
                     tracing::warn!("Received empty response from model");
                 } else {
                     self.status = "✓ Ready".to_string();
@@ -588,9 +1011,162 @@ Type /help for available commands · Type /quit to exit
                 }
                 debug!("Agent response: {}", response);
             }
-            AppMessage::Response(Err(e)) => {
+            AppMessage::Models(Ok(models)) => {
+                if models.is_empty() {
+                    self.status = "No models installed".to_string();
+                    self.agent.add_notice(
+                        "No models are installed. Pull one with `ollama pull <model>`.".to_string(),
+                    );
+                } else {
+                    let selected = models
+                        .iter()
+                        .position(|m| m.name == self.agent.model_name())
+                        .unwrap_or(0);
+                    self.status = "Select a model (↑/↓, Enter, Esc)".to_string();
+                    self.model_picker = Some(ModelPicker { models, selected });
+                }
+                return Ok(());
+            }
+            AppMessage::Capabilities(model, result) => {
+                // A reply for a model that is no longer active is stale
+                if model == self.agent.model_name() {
+                    match result {
+                        Ok(capabilities) => {
+                            let vision = capabilities.map(|caps| caps.iter().any(|c| c == "vision"));
+                            debug!("Model {} vision support: {:?}", model, vision);
+                            self.agent.set_vision(vision);
+                        }
+                        Err(e) => debug!("Could not get capabilities of {}: {:#}", model, e),
+                    }
+                }
+                return Ok(());
+            }
+            AppMessage::McpReconnected(name, tools) => {
+                if let Some(count) = self.agent.update_mcp_tools(&name, tools) {
+                    self.status = format!("✓ Reconnected to {} ({} tools)", name, count);
+                }
+                return Ok(());
+            }
+            AppMessage::McpToolsChanged(name, Ok(tools)) => {
+                if let Some(count) = self.agent.update_mcp_tools(&name, tools) {
+                    tracing::info!("🔄 Tools of MCP server '{}' changed: {} tools", name, count);
+                }
+                return Ok(());
+            }
+            AppMessage::McpConnected(server, result) => {
+                // The server may have been removed or disabled while connecting
+                let wanted = self.config.mcp_servers.iter().any(|s| s.name == server.name && s.enabled);
+                match result {
+                    Ok((client, tools)) if wanted => {
+                        let count = self.agent.replace_mcp_server(&server, client, tools);
+                        self.report_mcp_connection(&server.name, Ok(count));
+                    }
+                    Ok((client, _)) => client.shutdown_in_background(),
+                    Err(e) if wanted => {
+                        // As with a failed reconnect at startup, the old connection is gone too
+                        self.agent.disconnect_mcp_server(&server.name);
+                        self.report_mcp_connection(&server.name, Err(e));
+                    }
+                    Err(e) => debug!("Dropped failed connection to MCP server '{}': {:#}", server.name, e),
+                }
+                return Ok(());
+            }
+            AppMessage::McpToolsChanged(name, Err(e)) => {
+                tracing::warn!("Failed to list the changed tools of MCP server '{}': {:#}", name, e);
+                return Ok(());
+            }
+            AppMessage::ModelLookup(name, Ok(models)) => {
+                match models::find_model(&models, &name) {
+                    Some(info) => self.switch_model(info.name.clone(), Some(info), tx),
+                    None => {
+                        self.status = format!("✗ Unknown model: {}", name);
+                        let installed: Vec<String> = models.iter().map(|m| format!("`{}`", m.name)).collect();
+                        self.agent.add_notice(format!(
+                            "Model `{}` is not installed. Pull it with `ollama pull {}`{}",
+                            name,
+                            name,
+                            if installed.is_empty() {
+                                ".".to_string()
+                            } else {
+                                format!(", or pick one of: {}.", installed.join(", "))
+                            }
+                        ));
+                    }
+                }
+                return Ok(());
+            }
+            AppMessage::ModelLookup(name, Err(e)) => {
+                self.status = "✗ Error: could not list models".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** could not check model `{}`: {:#}", name, e));
+                return Ok(());
+            }
+            AppMessage::Models(Err(e)) => {
+                self.status = "✗ Error: could not list models".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+                return Ok(());
+            }
+            AppMessage::Mentions(request_id, ..)
+            | AppMessage::Prompt(request_id, ..)
+            | AppMessage::McpListing(request_id, _)
+                if request_id != self.request_id || !self.is_thinking =>
+            {
+                debug!("Discarding MCP reply of cancelled request {}", request_id);
+                return Ok(());
+            }
+            AppMessage::Mentions(_, input, Ok(resources)) => {
+                self.is_thinking = false;
+                self.cancel_token = None;
+                self.send_with_attachments(input, resources, tx);
+            }
+            AppMessage::Mentions(_, input, Err(e)) => {
+                // The message is not sent; it goes back to the input with anything queued
+                self.is_thinking = false;
+                self.cancel_token = None;
+                self.status = "✗ Error: could not read resource".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+                let mut parts = vec![input];
+                parts.append(&mut self.message_queue);
+                if !self.input.is_empty() {
+                    parts.push(std::mem::take(&mut self.input));
+                }
+                self.input = parts.join("\n\n");
+                self.cursor_pos = self.input.len();
+                return Ok(());
+            }
+            AppMessage::Prompt(_, label, Ok(result)) => {
+                self.is_thinking = false;
+                self.cancel_token = None;
+                self.apply_prompt(&label, result, tx);
+            }
+            AppMessage::Prompt(_, _, Err(e)) => {
+                self.is_thinking = false;
+                self.cancel_token = None;
+                self.status = "✗ Error: could not run prompt".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+            AppMessage::McpListing(_, Ok(listing)) => {
+                self.is_thinking = false;
+                self.cancel_token = None;
+                self.status = "✓ Ready".to_string();
+                self.agent.add_notice(listing);
+                self.reset_scroll();
+            }
+            AppMessage::McpListing(_, Err(e)) => {
+                self.is_thinking = false;
+                self.cancel_token = None;
+                self.status = "✗ Error: could not list MCP servers".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+            AppMessage::Response(_, Err(e)) => {
                 self.is_thinking = false;
                 self.cancel_token = None;
+                // Keep any partial reply streamed before the error
+                self.streaming = false;
                 
                 // Clean up repetitive error messages
                 let error_msg = e.to_string();
@@ -606,16 +1182,18 @@ Type /help for available commands · Type /quit to exit
                 
                 self.status = format!("✗ Error: {}", clean_error);
                 self.agent
-                    .add_assistant_message(format!("⚠ **Error:** {}", clean_error));
+                    .add_notice(format!("⚠ **Error:** {}", clean_error));
                 tracing::error!("Received error: {}", e);
             }
         }
 
+        self.save_session();
+
         // Reset scroll to bottom to show new message
         self.reset_scroll();
 
-        // Check if there are queued messages to send
-        if !self.message_queue.is_empty() {
+        // Check if there are queued messages to send (a prompt may have started a request)
+        if !self.is_thinking && !self.message_queue.is_empty() {
             // Concatenate all queued messages with newlines
             let combined = self.message_queue.join("\n\n");
             self.message_queue.clear();
@@ -629,7 +1207,7 @@ Type /help for available commands · Type /quit to exit
     }
 
     /// Handle internal commands
-    async fn handle_command(&mut self, input: &str) -> Result<()> {
+    async fn handle_command(&mut self, input: &str, tx: &mpsc::Sender<AppMessage>) -> Result<()> {
         let parts: Vec<&str> = input.split_whitespace().collect();
         let command = parts.first().map(|s| s.to_lowercase()).unwrap_or_default();
         let args: Vec<&str> = parts.iter().skip(1).copied().collect();
@@ -646,46 +1224,127 @@ Type /help for available commands · Type /quit to exit
                 self.agent.clear_history();
                 self.status = "Chat history cleared".to_string();
                 self.agent
-                    .add_assistant_message("Chat history has been cleared.".to_string());
+                    .add_notice("Chat history has been cleared.".to_string());
             }
             "/model" => {
-                if args.is_empty() {
-                    self.agent.add_assistant_message(format!(
-                        "Current model: {}",
-                        self.agent.model_name()
-                    ));
+                if let Some(name) = args.first() {
+                    self.look_up_model(name.to_string(), tx);
                 } else {
-                    self.status = format!("Model changed to: {}", args[0]);
-                    self.agent.add_assistant_message(format!(
-                        "Model setting updated to '{}' (requires restart to apply)",
-                        args[0]
-                    ));
+                    self.open_model_picker(tx);
                 }
             }
             "/history" | "/hist" => {
                 let count = self.agent.chat_history().len();
                 self.agent
-                    .add_assistant_message(format!("Chat history contains {} messages.", count));
+                    .add_notice(format!("Chat history contains {} messages.", count));
             }
             "/status" => {
-                self.agent.add_assistant_message(format!(
-                    "Status: {}\nModel: {}\nMessages: {}",
+                self.agent.add_notice(format!(
+                    "Status: {}\nModel: {}\nMessages: {}\nImages: {:?}",
                     self.status,
                     self.agent.model_name(),
-                    self.agent.chat_history().len()
+                    self.agent.chat_history().len(),
+                    self.images.protocol()
                 ));
             }
+            "/export" => match args.first() {
+                Some(_) => self.export_chat(input.trim()["/export".len()..].trim()),
+                None => {
+                    self.agent.add_notice(
+                        "Usage: /export <path>. The extension picks the format: .md, .json or .html."
+                            .to_string(),
+                    );
+                }
+            },
+            "/save-image" => match args.first().and_then(|n| n.parse::<usize>().ok()) {
+                Some(number) => self.save_image(number, args.get(1).copied()),
+                None => {
+                    self.agent.add_notice(
+                        "Usage: /save-image <n> [path]. Images are numbered in the chat, e.g. 🖼 [1]."
+                            .to_string(),
+                    );
+                }
+            },
+            "/attach" => match args.first() {
+                Some(_) => self.attach_image(input.trim()["/attach".len()..].trim()),
+                None => {
+                    self.agent.add_notice(
+                        "Usage: /attach <path>. The image is sent with your next message.".to_string(),
+                    );
+                }
+            },
+            "/sessions" => {
+                self.list_sessions();
+            }
+            "/load" => match args.first() {
+                _ if self.is_thinking => {
+                    self.status = "Wait for the response (or press Esc) before loading".to_string();
+                }
+                Some(id) => self.load_session(id, tx),
+                None => {
+                    self.agent
+                        .add_notice("Usage: /load <id>. Use /sessions to list them.".to_string());
+                }
+            },
+            "/save" => {
+                if !args.is_empty() {
+                    self.session.name = Some(args.join(" "));
+                }
+                match self.write_session() {
+                    Ok(path) => {
+                        self.status = format!("✓ Saved session {}", self.session.label());
+                        tracing::info!("Saved session to {}", path.display());
+                    }
+                    Err(e) => {
+                        self.status = "✗ Error: could not save session".to_string();
+                        self.agent
+                            .add_notice(format!("⚠ **Error:** {:#}", e));
+                    }
+                }
+            }
             "/yolo" => {
-                self.status = "YOLO mode toggled (feature pending)".to_string();
-                self.agent.add_assistant_message(
-                    "YOLO mode toggle requested. This feature is coming soon!".to_string(),
-                );
+                let enable = match args.first().map(|a| a.to_lowercase()).as_deref() {
+                    None => !self.config.yolo,
+                    Some("on") => true,
+                    Some("off") => false,
+                    Some(other) => {
+                        self.agent.add_notice(format!(
+                            "Unknown YOLO option: {}. Usage: /yolo [on|off]",
+                            other
+                        ));
+                        return Ok(());
+                    }
+                };
+                if enable && !self.config.yolo {
+                    // Ask first: YOLO mode lets tools modify files and data unchecked
+                    self.confirm_yolo = true;
+                    self.status = "Enable YOLO mode? (y/N)".to_string();
+                } else {
+                    self.set_yolo(enable);
+                }
             }
             "/mcp" | "/mcp-servers" => {
-                self.handle_mcp_command(&args).await?;
+                self.handle_mcp_command(&args, tx).await?;
+            }
+            "/tools" => match (args.first().map(|a| a.to_lowercase()).as_deref(), args.get(1)) {
+                (None, _) => self.list_tools(),
+                (Some("on"), Some(pattern)) => self.set_tools_enabled(pattern, true),
+                (Some("off"), Some(pattern)) => self.set_tools_enabled(pattern, false),
+                _ => {
+                    self.agent.add_notice(
+                        "Usage: /tools [on|off <name|glob>], e.g. /tools off pcli2__asset_*".to_string(),
+                    );
+                }
+            },
+            // MCP prompts run as /server:prompt
+            _ if let Some((server, name)) = parts[0][1..].split_once(':')
+                && self.agent.mcp_client(server).is_some() =>
+            {
+                let line = input.trim()[parts[0].len()..].trim();
+                self.run_prompt(server, name, line, tx);
             }
             _ => {
-                self.agent.add_assistant_message(format!(
+                self.agent.add_notice(format!(
                     "Unknown command: {}. Type /help for available commands.",
                     command
                 ));
@@ -696,7 +1355,7 @@ Type /help for available commands · Type /quit to exit
     }
 
     /// Handle MCP commands
-    async fn handle_mcp_command(&mut self, args: &[&str]) -> Result<()> {
+    async fn handle_mcp_command(&mut self, args: &[&str], tx: &mpsc::Sender<AppMessage>) -> Result<()> {
         if args.is_empty() {
             // Show MCP status
             let connected = self.agent.mcp_connected();
@@ -719,13 +1378,19 @@ Type /help for available commands · Type /quit to exit
                 msg.push_str("\nCommands:\n");
                 msg.push_str("  /mcp list     - List all configured servers\n");
                 msg.push_str("  /mcp tools    - Show available MCP tools\n");
-                msg.push_str("  /mcp add <name> <url> - Add a new server\n");
+                msg.push_str("  /mcp resources - Show resources (mention as @server:uri)\n");
+                msg.push_str("  /mcp prompts  - Show prompts (run as /server:prompt)\n");
+                msg.push_str("  /mcp add <name> <url|command> - Add and connect a server\n");
+                msg.push_str("  /mcp remove|enable|disable <name> - Change a server\n");
+                msg.push_str("  /mcp reconnect [name] - Reconnect one or all servers\n");
+                msg.push_str("  /mcp health   - Show server health\n");
+                msg.push_str("  /mcp save     - Save the server list to the config file\n");
             } else {
                 msg.push_str("\nNo MCP servers configured.\n");
                 msg.push_str("Add servers by editing ~/.config/pcli2-rig/config.toml\n");
             }
 
-            self.agent.add_assistant_message(msg);
+            self.agent.add_notice(msg);
             return Ok(());
         }
 
@@ -734,9 +1399,23 @@ Type /help for available commands · Type /quit to exit
                 let mut msg = String::from("Configured MCP servers:\n");
                 for server in &self.config.mcp_servers {
                     let status = if server.enabled { "✓" } else { "✗" };
-                    msg.push_str(&format!("  {} {} ({})\n", status, server.name, server.url));
+                    msg.push_str(&format!("  {} {} ({})\n", status, server.name, server.endpoint()));
+                    if let Some(info) = self.agent.mcp_client(&server.name).and_then(|c| c.server_info()) {
+                        let capabilities = crate::mcp::capability_names(&info.capabilities);
+                        msg.push_str(&format!(
+                            "      {} {} · protocol {} · {}\n",
+                            info.server_info.name,
+                            info.server_info.version,
+                            info.protocol_version,
+                            if capabilities.is_empty() {
+                                "no capabilities".to_string()
+                            } else {
+                                capabilities.join(", ")
+                            }
+                        ));
+                    }
                 }
-                self.agent.add_assistant_message(msg);
+                self.agent.add_notice(msg);
             }
             "tools" => {
                 // Try to get actual tool definitions from ToolServer
@@ -744,49 +1423,135 @@ Type /help for available commands · Type /quit to exit
                     match handle.get_tool_defs(None).await {
                         Ok(tool_defs) => {
                             if tool_defs.is_empty() {
-                                self.agent.add_assistant_message(
+                                self.agent.add_notice(
                                     "No MCP tools available.".to_string(),
                                 );
                             } else {
+                                // Group by server: built-in tools first, then servers in connection order
+                                let mut groups: Vec<(&str, Vec<_>)> = vec![("Built-in", Vec::new())];
+                                groups.extend(self.agent.mcp_connected().iter().map(|name| (name.as_str(), Vec::new())));
+                                for tool in &tool_defs {
+                                    let group = self.agent.tool_server_name(&tool.name).unwrap_or("Built-in");
+                                    if let Some((_, tools)) = groups.iter_mut().find(|(name, _)| *name == group) {
+                                        tools.push(tool);
+                                    }
+                                }
+
                                 let mut msg = String::new();
-                                msg.push_str(&format!("**Available MCP tools** ({} total):\n\n", tool_defs.len()));
-                                for (i, tool) in tool_defs.iter().enumerate() {
-                                    msg.push_str(&format!("**{}.** `{}`\n", i + 1, tool.name));
-                                    msg.push_str(&format!("    {}\n\n", tool.description));
+                                msg.push_str(&format!("**Available tools** ({} total):\n\n", tool_defs.len()));
+                                for (group, mut tools) in groups {
+                                    if tools.is_empty() {
+                                        continue;
+                                    }
+                                    tools.sort_by(|a, b| a.name.cmp(&b.name));
+                                    msg.push_str(&format!("**{}** ({} tools)\n", group, tools.len()));
+                                    for tool in tools {
+                                        msg.push_str(&format!("  `{}`\n", tool.name));
+                                        msg.push_str(&format!("    {}\n", tool.description));
+                                    }
+                                    msg.push('\n');
                                 }
-                                self.agent.add_assistant_message(msg);
+                                self.agent.add_notice(msg);
                             }
                         }
                         Err(e) => {
-                            self.agent.add_assistant_message(
+                            self.agent.add_notice(
                                 format!("Failed to get tool definitions: {}", e),
                             );
                         }
                     }
                 } else {
-                    self.agent.add_assistant_message(
+                    self.agent.add_notice(
                         "No MCP server connected. Configure with --setup-mcp or --mcp-remote.".to_string(),
                     );
                 }
             }
+            "health" => {
+                let clients: Vec<McpClient> = self.agent.mcp_clients().cloned().collect();
+                if clients.is_empty() {
+                    self.agent.add_notice("No MCP servers connected.".to_string());
+                    return Ok(());
+                }
+                let mut msg = String::from("**MCP server health**\n\n");
+                for client in clients {
+                    let health = client.health();
+                    let icon = match health.state {
+                        HealthState::Up => "✓",
+                        HealthState::Degraded => "⚠",
+                        HealthState::Down => "✗",
+                    };
+                    msg.push_str(&format!("  {} {} - {}", icon, client.name(), health.state.label()));
+                    if let Some(latency) = health.latency {
+                        msg.push_str(&format!(" · ping {} ms", latency.as_millis()));
+                    }
+                    match health.checked_at {
+                        Some(checked_at) => msg.push_str(&format!(" · checked {}\n", checked_at.format("%H:%M:%S"))),
+                        None => msg.push_str(" · not checked yet\n"),
+                    }
+                    if health.failures > 0 {
+                        msg.push_str(&format!("      {} failed check(s)\n", health.failures));
+                    }
+                    if let Some(error) = &health.last_error {
+                        msg.push_str(&format!("      last error: {}\n", error));
+                    }
+                    if health.reconnects > 0 {
+                        msg.push_str(&format!("      reconnected {} time(s)\n", health.reconnects));
+                    }
+                }
+                msg.push_str(&format!(
+                    "\nServers are pinged every {}s; a server that is down is reconnected with backoff.",
+                    HealthCheck::default().interval.as_secs()
+                ));
+                self.agent.add_notice(msg);
+            }
+            "resources" | "prompts" => {
+                if self.is_thinking {
+                    self.status = "Wait for the response (or press Esc) before listing".to_string();
+                } else if args[0] == "resources" {
+                    let clients = self.mcp_clients_with(McpClient::supports_resources);
+                    self.start_mcp_request(
+                        "Listing MCP resources...".to_string(),
+                        resource_listing(clients),
+                        AppMessage::McpListing,
+                        tx,
+                    );
+                } else {
+                    let clients = self.mcp_clients_with(McpClient::supports_prompts);
+                    self.start_mcp_request(
+                        "Listing MCP prompts...".to_string(),
+                        prompt_listing(clients),
+                        AppMessage::McpListing,
+                        tx,
+                    );
+                }
+            }
             "add" => {
                 if args.len() < 3 {
-                    self.agent
-                        .add_assistant_message("Usage: /mcp add <name> <url>".to_string());
+                    self.agent.add_notice(
+                        "Usage: /mcp add <name> <url> or /mcp add <name> <command> [args...]".to_string(),
+                    );
                 } else {
-                    self.agent.add_assistant_message(format!(
-                        "To add MCP server '{}', edit ~/.config/pcli2-rig/config.toml\n\
-                         Add this section:\n\
-                         [[mcp_servers]]\n\
-                         name = \"{}\"\n\
-                         url = \"{}\"\n\
-                         enabled = true",
-                        args[1], args[1], args[2]
-                    ));
+                    let (name, endpoint) = (args[1].to_string(), args[2].to_string());
+                    let server = if endpoint.starts_with("http://") || endpoint.starts_with("https://") {
+                        McpServerConfig::http(name, endpoint)
+                    } else {
+                        let command_args = args[3..].iter().map(|arg| arg.to_string()).collect();
+                        McpServerConfig::stdio(name, endpoint, command_args)
+                    };
+                    self.add_mcp_server(server, tx);
                 }
             }
+            "remove" | "enable" | "disable" if args.len() < 2 => {
+                self.agent
+                    .add_notice(format!("Usage: /mcp {} <name>", args[0]));
+            }
+            "remove" => self.remove_mcp_server(args[1]),
+            "enable" => self.set_mcp_server_enabled(args[1], true, tx),
+            "disable" => self.set_mcp_server_enabled(args[1], false, tx),
+            "reconnect" => self.reconnect_mcp_servers(args.get(1).copied(), tx),
+            "save" => self.save_mcp_servers(),
             _ => {
-                self.agent.add_assistant_message(format!(
+                self.agent.add_notice(format!(
                     "Unknown MCP command: {}. Type /mcp for help.",
                     args[0]
                 ));
@@ -796,45 +1561,481 @@ Type /help for available commands · Type /quit to exit
         Ok(())
     }
 
-    /// Execute the pending tool call
-    async fn execute_pending_tool(&mut self) -> Result<()> {
+    /// Show saved sessions in the chat
+    fn list_sessions(&mut self) {
+        let sessions = match self.sessions.as_ref().map(SessionStore::list).transpose() {
+            Ok(sessions) => sessions.unwrap_or_default(),
+            Err(e) => {
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+                return;
+            }
+        };
+        if sessions.is_empty() {
+            self.agent
+                .add_notice("No saved sessions yet.".to_string());
+            return;
+        }
+
+        let mut msg = format!("**Saved sessions** ({} total):\n\n", sessions.len());
+        for summary in sessions.iter().take(20) {
+            let current = if summary.info.id == self.session.id { " ← current" } else { "" };
+            msg.push_str(&format!(
+                "- `{}`{} · {} messages · {} · {}{}\n",
+                summary.info.id,
+                summary
+                    .info
+                    .name
+                    .as_ref()
+                    .map(|n| format!(" **{}**", n))
+                    .unwrap_or_default(),
+                summary.message_count,
+                summary.info.model,
+                summary
+                    .info
+                    .updated_at
+                    .with_timezone(&chrono::Local)
+                    .format("%Y-%m-%d %H:%M"),
+                current
+            ));
+        }
+        msg.push_str("\nLoad one with `/load <id>`.");
+        self.agent.add_notice(msg);
+    }
+
+    /// Write the chat history to a file (format from the extension)
+    fn export_chat(&mut self, path: &str) {
+        let path = expand_home(path);
+        let messages = self.agent.chat_history();
+        match export::write_file(&path, None, &self.session, messages) {
+            Ok(format) => {
+                self.status = format!("✓ Exported {} messages to {}", messages.len(), path.display());
+                tracing::info!("Exported chat as {:?} to {}", format, path.display());
+            }
+            Err(e) => {
+                self.status = "✗ Error: export failed".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+        }
+    }
+
+    /// Write image `number` from the chat to disk
+    fn save_image(&mut self, number: usize, path: Option<&str>) {
+        let path = path.map(expand_home);
+        let history = self.agent.chat_history();
+        let attachments = images::image_attachments(history);
+        let Some(attachment) = number.checked_sub(1).and_then(|i| attachments.get(i)) else {
+            self.status = format!("✗ No image {} in this chat ({} images)", number, attachments.len());
+            return;
+        };
+        match images::save_image(attachment, number, path.as_deref()) {
+            Ok(path) => {
+                self.status = format!("✓ Saved image {} to {}", number, path.display());
+                tracing::info!("Saved image {} to {}", number, path.display());
+            }
+            Err(e) => {
+                self.status = "✗ Error: could not save image".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+        }
+    }
+
+    /// List every tool by server, with whether it is on for this session
+    fn list_tools(&mut self) {
+        let tools = self.agent.tools();
+        let enabled = tools.iter().filter(|tool| tool.enabled).count();
+        let mut msg = format!("**Tools** ({} of {} on):\n\n", enabled, tools.len());
+        let groups = std::iter::once(None).chain(self.agent.mcp_connected().iter().map(|name| Some(name.as_str())));
+        for group in groups {
+            let mut names: Vec<_> = tools.iter().filter(|tool| tool.server.as_deref() == group).collect();
+            if names.is_empty() {
+                continue;
+            }
+            names.sort_by(|a, b| a.name.cmp(&b.name));
+            msg.push_str(&format!("**{}**\n", group.unwrap_or("Built-in")));
+            for tool in names {
+                let switch = if tool.enabled { "on " } else { "off" };
+                msg.push_str(&format!("  [{}] `{}`\n", switch, tool.name));
+            }
+            msg.push('\n');
+        }
+        msg.push_str("Switch tools with /tools on|off <name|glob>.");
+        self.agent.add_notice(msg);
+    }
+
+    /// Switch the tools matching a name or glob on or off for this session
+    fn set_tools_enabled(&mut self, pattern: &str, enabled: bool) {
+        let count = self.agent.set_tools_enabled(pattern, enabled);
+        let state = if enabled { "on" } else { "off" };
+        if count == 0 {
+            self.status = format!("✗ No tools match {}", pattern);
+        } else {
+            self.status = format!("✓ Switched {} tool(s) {}", count, state);
+            tracing::info!("Switched {} tool(s) matching '{}' {}", count, pattern, state);
+        }
+    }
+
+    /// Attach an image file to the next message
+    fn attach_image(&mut self, path: &str) {
+        let path = expand_home(path);
+        match images::load_image(&path) {
+            Ok(attachment) => {
+                tracing::info!("Attached {} ({})", path.display(), attachment.label());
+                self.status = format!("✓ Attached {} ({})", path.display(), attachment.label());
+                self.pending_attachments.push(attachment);
+                if self.agent.vision() == Some(false) {
+                    self.agent.add_notice(format!(
+                        "⚠ `{}` has no vision support, so it will not see the image. Switch to a vision model such as `llava` or `qwen2.5vl` with /model.",
+                        self.agent.model_name()
+                    ));
+                }
+            }
+            Err(e) => {
+                self.status = "✗ Error: could not attach image".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+        }
+    }
+
+    /// Look up whether the active model accepts images, in the background
+    fn fetch_capabilities(&self, tx: &mpsc::Sender<AppMessage>) {
+        let base_url = self.config.ollama_url();
+        let model = self.agent.model_name().to_string();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = models::model_capabilities(&base_url, &model).await;
+            let _ = tx.send(AppMessage::Capabilities(model, result)).await;
+        });
+    }
+
+    /// Save the current session and switch to a saved one
+    fn load_session(&mut self, query: &str, tx: &mpsc::Sender<AppMessage>) {
+        let Some(store) = self.sessions.clone() else {
+            return;
+        };
+        match store.load(query) {
+            Ok(session) => {
+                self.save_session();
+                let label = session.info.label().to_string();
+                let count = session.messages.len();
+                if session.info.model != self.agent.model_name() {
+                    self.config.model = session.info.model.clone();
+                    self.agent.set_model_name(session.info.model.clone());
+                    self.fetch_capabilities(tx);
+                }
+                self.resume(session);
+                self.reset_scroll();
+                self.status = format!("✓ Loaded session {} ({} messages)", label, count);
+            }
+            Err(e) => {
+                self.status = "✗ Error: could not load session".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+        }
+    }
+
+    /// Warn about `[permissions]` entries that match no registered tool, since a
+    /// misspelled `deny` would silently not apply
+    fn warn_unmatched_permissions(&self) {
+        let names = self.agent.permission_names();
+        for pattern in self.config.permissions.unmatched_patterns(&names) {
+            tracing::warn!("Permission entry '{}' matches no registered tool", pattern);
+        }
+    }
+
+    /// Turn YOLO mode on or off for the running session
+    fn set_yolo(&mut self, yolo: bool) {
+        let changed = self.config.yolo != yolo;
+        self.config.yolo = yolo;
+        if let Some(approver) = self.agent.tool_approver() {
+            approver.set_yolo(yolo);
+        }
+
+        if yolo {
+            self.status = "YOLO mode on: tool calls run without confirmation".to_string();
+        } else {
+            self.status = "✓ YOLO mode off: tool calls need confirmation".to_string();
+        }
+        if changed {
+            tracing::warn!("YOLO mode {}", if yolo { "enabled" } else { "disabled" });
+        }
+    }
+
+    /// Fetch installed models in the background; the picker opens when they arrive
+    fn open_model_picker(&mut self, tx: &mpsc::Sender<AppMessage>) {
+        self.status = "Loading models...".to_string();
+        let base_url = self.config.ollama_url();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = models::list_models(&base_url).await;
+            let _ = tx.send(AppMessage::Models(result)).await;
+        });
+    }
+
+    /// Check that a model named with `/model <name>` is installed, then switch to it
+    fn look_up_model(&mut self, name: String, tx: &mpsc::Sender<AppMessage>) {
+        self.status = format!("Looking up {}...", name);
+        let base_url = self.config.ollama_url();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = models::list_models(&base_url).await;
+            let _ = tx.send(AppMessage::ModelLookup(name, result)).await;
+        });
+    }
+
+    /// Use a different model for the next request, keeping the chat history
+    fn switch_model(&mut self, name: String, info: Option<&ModelInfo>, tx: &mpsc::Sender<AppMessage>) {
+        if name == self.agent.model_name() {
+            self.status = format!("Already using {}", name);
+            return;
+        }
+
+        tracing::info!("Switching model to {}", name);
+        let mut msg = format!("Switched to model `{}`. Chat history is kept.", name);
+        if info.and_then(ModelInfo::supports_tools) == Some(false) {
+            msg.push_str(&format!(
+                "\n\n⚠ `{}` does not support tool calling, so tools will not work with it.",
+                name
+            ));
+        }
+        self.agent.add_notice(msg);
+        self.status = format!("✓ Model: {}", name);
+        self.config.model = name.clone();
+        self.agent.set_model_name(name);
+        match info {
+            Some(info) => self.agent.set_vision(info.supports_vision()),
+            None => self.fetch_capabilities(tx),
+        }
+    }
+
+    /// Handle a key press while the model picker is open
+    fn handle_model_picker_key(&mut self, key: KeyEvent, tx: &mpsc::Sender<AppMessage>) {
+        use crossterm::event::KeyCode;
+
+        let Some(picker) = self.model_picker.as_mut() else {
+            return;
+        };
+        match key.code {
+            KeyCode::Up | KeyCode::Char('k') => {
+                picker.selected = picker.selected.saturating_sub(1);
+            }
+            KeyCode::Down | KeyCode::Char('j') => {
+                picker.selected = (picker.selected + 1).min(picker.models.len().saturating_sub(1));
+            }
+            KeyCode::Enter => {
+                if let Some(picker) = self.model_picker.take() {
+                    let info = picker.models[picker.selected].clone();
+                    self.switch_model(info.name.clone(), Some(&info), tx);
+                }
+            }
+            KeyCode::Esc | KeyCode::Char('q') => {
+                self.model_picker = None;
+                self.status = "Ready".to_string();
+            }
+            _ => {}
+        }
+    }
+
+    /// Show a tool call for confirmation, or queue it behind the one already shown
+    fn request_tool_approval(&mut self, request: ApprovalRequest) {
+        debug!("Tool call awaiting approval: {}", request.tool_name);
+        if self.pending_tool_call.is_some() {
+            self.queued_tool_calls.push(request);
+        } else {
+            self.status = format!("Approve {}? (Y/n)", request.tool_name);
+            self.pending_tool_call = Some(request);
+        }
+    }
+
+    /// Answer the pending tool call and show the next queued one, if any
+    fn resolve_pending_tool(&mut self, decision: ApprovalDecision) {
         if let Some(pending) = self.pending_tool_call.take() {
-            self.status = format!("Executing {}...", pending.tool_name);
-
-            match agent::execute_tool_call(&pending.tool_name, &pending.arguments).await {
-                Ok(result) => {
-                    self.agent.add_tool_result(result.clone());
-                    self.status = "Tool executed successfully".to_string();
-
-                    // Send the tool result back to get the agent's response
-                    let follow_up = format!("The tool returned:\n{}", result);
-                    match self.agent.chat(follow_up).await {
-                        Ok(response) => {
-                            debug!("Agent follow-up response: {}", response);
-                        }
-                        Err(e) => {
-                            self.status = format!("Error in follow-up: {}", e);
-                        }
-                    }
+            self.status = match decision {
+                ApprovalDecision::Approve => format!("Executing {}...", pending.tool_name),
+                ApprovalDecision::ApproveForSession => {
+                    format!("Executing {} (allowed for this session)...", pending.tool_name)
                 }
-                Err(e) => {
-                    // Clean up repetitive error messages
-                    let error_msg = e.to_string();
-                    let clean_error = if error_msg.contains("Tool call error:") {
-                        error_msg.split("Tool call error:").last().unwrap_or(&error_msg).trim().to_string()
-                    } else if error_msg.contains("ToolCallError:") {
-                        error_msg.split("ToolCallError:").last().unwrap_or(&error_msg).trim().to_string()
-                    } else {
-                        error_msg
-                    };
-                    
-                    self.status = format!("Tool execution failed: {}", clean_error);
-                    self.agent.add_tool_result(format!("Error: {}", clean_error));
+                ApprovalDecision::AlwaysApprove => {
+                    self.persist_tool_policy(&pending.tool_name, ToolPolicy::Allow);
+                    format!("Executing {} (always allowed)...", pending.tool_name)
                 }
+                ApprovalDecision::Decline => format!("Declined {}", pending.tool_name),
+            };
+            pending.respond(decision);
+        }
+
+        if !self.queued_tool_calls.is_empty() {
+            let next = self.queued_tool_calls.remove(0);
+            self.request_tool_approval(next);
+        }
+    }
+
+    /// Add an MCP server to the config file and connect to it
+    fn add_mcp_server(&mut self, server: McpServerConfig, tx: &mpsc::Sender<AppMessage>) {
+        if self.config.mcp_servers.iter().any(|s| s.name == server.name) {
+            self.agent.add_notice(format!(
+                "MCP server '{}' already exists. Remove it first with /mcp remove {}.",
+                server.name, server.name
+            ));
+            return;
+        }
+
+        self.config.mcp_servers.push(server.clone());
+        self.persist_mcp_servers(|servers| {
+            servers.retain(|s| s.name != server.name);
+            servers.push(server.clone());
+        });
+        self.status = format!("Connecting to {}...", server.name);
+        self.connect_mcp_server(server, tx);
+    }
+
+    /// Connect to an MCP server in the background; the connection replaces the
+    /// current one when [`AppMessage::McpConnected`] arrives
+    fn connect_mcp_server(&self, server: McpServerConfig, tx: &mpsc::Sender<AppMessage>) {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let result = tokio::time::timeout(MCP_CONNECT_TIMEOUT, Agent::connect_mcp_server(&server))
+                .await
+                .unwrap_or_else(|_| {
+                    Err(anyhow::anyhow!(
+                        "no answer within {} seconds",
+                        MCP_CONNECT_TIMEOUT.as_secs()
+                    ))
+                });
+            let _ = tx.send(AppMessage::McpConnected(Box::new(server), result)).await;
+        });
+    }
+
+    /// Disconnect an MCP server and remove it from the config file
+    fn remove_mcp_server(&mut self, name: &str) {
+        if !self.config.mcp_servers.iter().any(|s| s.name == name) {
+            self.agent
+                .add_notice(format!("No MCP server named '{}'. See /mcp list.", name));
+            return;
+        }
+
+        self.config.mcp_servers.retain(|s| s.name != name);
+        self.persist_mcp_servers(|servers| servers.retain(|s| s.name != name));
+        self.agent.disconnect_mcp_server(name);
+        tracing::info!("Removed MCP server '{}'", name);
+        self.status = format!("✓ Removed MCP server {}", name);
+        self.agent
+            .add_notice(format!("Removed MCP server '{}' and its tools.", name));
+    }
+
+    /// Enable (and connect) or disable (and disconnect) an MCP server
+    fn set_mcp_server_enabled(&mut self, name: &str, enabled: bool, tx: &mpsc::Sender<AppMessage>) {
+        let Some(server) = self.config.mcp_servers.iter_mut().find(|s| s.name == name) else {
+            self.agent
+                .add_notice(format!("No MCP server named '{}'. See /mcp list.", name));
+            return;
+        };
+        server.enabled = enabled;
+        let server = server.clone();
+        self.persist_mcp_servers(|servers| {
+            if let Some(saved) = servers.iter_mut().find(|s| s.name == name) {
+                saved.enabled = enabled;
             }
+        });
+
+        if enabled {
+            self.status = format!("Connecting to {}...", name);
+            self.connect_mcp_server(server, tx);
+        } else {
+            self.agent.disconnect_mcp_server(name);
+            tracing::info!("Disabled MCP server '{}'", name);
+            self.status = format!("✓ Disabled MCP server {}", name);
+            self.agent
+                .add_notice(format!("Disabled MCP server '{}'. Its tools are no longer available.", name));
         }
+    }
 
-        Ok(())
+    /// Reconnect one enabled MCP server, or all of them
+    fn reconnect_mcp_servers(&mut self, name: Option<&str>, tx: &mpsc::Sender<AppMessage>) {
+        let servers: Vec<McpServerConfig> = self
+            .config
+            .mcp_servers
+            .iter()
+            .filter(|s| s.enabled && name.is_none_or(|name| s.name == name))
+            .cloned()
+            .collect();
+        if servers.is_empty() {
+            self.agent.add_notice(match name {
+                Some(name) => format!("No enabled MCP server named '{}'. See /mcp list.", name),
+                None => "No MCP servers are enabled.".to_string(),
+            });
+            return;
+        }
+
+        self.status = match &servers[..] {
+            [server] => format!("Reconnecting to {}...", server.name),
+            _ => format!("Reconnecting to {} MCP servers...", servers.len()),
+        };
+        for server in servers {
+            self.connect_mcp_server(server, tx);
+        }
+    }
+
+    /// Report the outcome of connecting to an MCP server in the chat
+    fn report_mcp_connection(&mut self, name: &str, result: Result<usize>) {
+        match result {
+            Ok(count) => {
+                tracing::info!("Connected to MCP server '{}' ({} tools)", name, count);
+                self.status = format!("✓ Connected to {}", name);
+                self.agent
+                    .add_notice(format!("✓ Connected to MCP server '{}': {} tools.", name, count));
+            }
+            Err(e) => {
+                tracing::warn!("Failed to connect to MCP server '{}': {:#}", name, e);
+                self.status = format!("✗ Could not connect to {}", name);
+                self.agent.add_notice(format!(
+                    "⚠ Could not connect to MCP server '{}': {:#}\n\nFix the server and run /mcp reconnect {}.",
+                    name, e, name
+                ));
+            }
+        }
+    }
+
+    /// Write the running MCP server list, including servers given on the command
+    /// line, to the config file
+    fn save_mcp_servers(&mut self) {
+        let servers = self.config.mcp_servers.clone();
+        match Config::update_mcp_servers(|saved| *saved = servers) {
+            Ok(path) => {
+                self.status = format!("✓ Saved MCP servers to {}", path.display());
+                tracing::info!("Saved MCP servers to {}", path.display());
+            }
+            Err(e) => {
+                self.status = "✗ Error: could not save MCP servers".to_string();
+                self.agent
+                    .add_notice(format!("⚠ **Error:** {:#}", e));
+            }
+        }
+    }
+
+    /// Apply a change to the MCP servers in the config file
+    fn persist_mcp_servers(&self, update: impl FnOnce(&mut Vec<McpServerConfig>)) {
+        match Config::update_mcp_servers(update) {
+            Ok(path) => tracing::info!("Saved MCP servers to {}", path.display()),
+            Err(e) => tracing::error!("Failed to save MCP servers: {:#}", e),
+        }
+    }
+
+    /// Save a tool policy to the `[permissions]` section of the config file
+    fn persist_tool_policy(&mut self, tool_name: &str, policy: ToolPolicy) {
+        self.config
+            .permissions
+            .tools
+            .insert(tool_name.to_string(), policy);
+
+        match Config::save_tool_policy(tool_name, policy) {
+            Ok(path) => tracing::info!("Saved permission for {} to {}", tool_name, path.display()),
+            Err(e) => tracing::error!("Failed to save permission for {}: {:#}", tool_name, e),
+        }
     }
 
     /// Render the UI
@@ -858,7 +2059,7 @@ Type /help for available commands · Type /quit to exit
     }
 
     /// Get the pending tool call
-    pub fn pending_tool_call(&self) -> Option<&PendingToolCall> {
+    pub fn pending_tool_call(&self) -> Option<&ApprovalRequest> {
         self.pending_tool_call.as_ref()
     }
 
@@ -984,6 +2185,22 @@ Type /help for available commands · Type /quit to exit
         if let Some(token) = self.cancel_token.take() {
             token.cancel();
             self.is_thinking = false;
+            if std::mem::take(&mut self.streaming) {
+                // Keep the partial reply, marked as interrupted
+                let replying = self
+                    .agent
+                    .chat_history()
+                    .last()
+                    .is_some_and(|msg| msg.role == MessageRole::Assistant);
+                if replying {
+                    self.agent.append_to_last_message("\n\n*[interrupted]*");
+                } else {
+                    self.agent.add_notice("*[interrupted]*".to_string());
+                }
+            } else {
+                self.agent
+                    .add_notice("⚠ **Error:** Request cancelled by user".to_string());
+            }
             self.status = "Request cancelled".to_string();
             debug!("Request cancelled by user");
         }
@@ -1035,17 +2252,13 @@ Type /help for available commands · Type /quit to exit
                     self.log_scroll_offset = self.log_scroll_offset.saturating_sub(3);
                 }
             }
-            MouseEventKind::ScrollLeft => {
-                // Horizontal scroll left in logs pane
-                if self.focus_pane == 2 {
-                    self.log_hscroll_offset = self.log_hscroll_offset.saturating_sub(5);
-                }
+            // Horizontal scroll left in logs pane
+            MouseEventKind::ScrollLeft if self.focus_pane == 2 => {
+                self.log_hscroll_offset = self.log_hscroll_offset.saturating_sub(5);
             }
-            MouseEventKind::ScrollRight => {
-                // Horizontal scroll right in logs pane
-                if self.focus_pane == 2 {
-                    self.log_hscroll_offset = self.log_hscroll_offset.saturating_add(5);
-                }
+            // Horizontal scroll right in logs pane
+            MouseEventKind::ScrollRight if self.focus_pane == 2 => {
+                self.log_hscroll_offset = self.log_hscroll_offset.saturating_add(5);
             }
             _ => {}
         }
@@ -1066,6 +2279,34 @@ Type /help for available commands · Type /quit to exit
         self.show_help
     }
 
+    /// Whether YOLO mode is on
+    pub fn is_yolo(&self) -> bool {
+        self.config.yolo
+    }
+
+    /// Whether the "enable YOLO mode?" confirmation is shown
+    pub fn confirming_yolo(&self) -> bool {
+        self.confirm_yolo
+    }
+
+    /// Whether a dialog (help, model picker or a confirmation) covers the chat
+    pub fn has_dialog(&self) -> bool {
+        self.show_help
+            || self.model_picker.is_some()
+            || self.confirm_yolo
+            || self.pending_tool_call.is_some()
+    }
+
+    /// Get the image renderer
+    pub fn images(&self) -> &ImageRenderer {
+        &self.images
+    }
+
+    /// Get the model picker, if open
+    pub fn model_picker(&self) -> Option<&ModelPicker> {
+        self.model_picker.as_ref()
+    }
+
     /// Get help scroll offset
     pub fn help_scroll_offset(&self) -> usize {
         self.help_scroll_offset
@@ -1088,13 +2329,31 @@ COMMANDS
 /help, /h, /?     Show this help message
 /quit, /exit, /q  Exit the application
 /clear, /cls      Clear chat history
-/model [name]     Show or set the current model
+/model [name]     Pick a model (or switch to [name])
 /history, /hist   Show message count
+/sessions         List saved sessions
+/load <id>        Load a saved session
+/save [name]      Save (and optionally name) this session
+/export <path>    Export chat as .md, .json or .html
+/save-image <n> [path]  Save image [n] from the chat to a file
+/attach <path>    Attach an image to your next message
 /status           Show current status
 /mcp              Show MCP server status
 /mcp list         List configured MCP servers
-/mcp tools        Show available MCP tools
-/yolo             Toggle YOLO mode (skip tool confirmation)
+/mcp tools        Show available tools by server
+/mcp resources    Show MCP resources
+/mcp prompts      Show MCP prompts
+/mcp add <name> <url|command>  Add and connect an MCP server
+/mcp remove <name>   Remove an MCP server
+/mcp enable|disable <name>  Turn an MCP server on or off
+/mcp reconnect [name]  Reconnect one or all MCP servers
+/mcp health       Show MCP server health (up, degraded, down)
+/mcp save         Save the MCP server list to the config file
+/tools            List all tools with their on/off state
+/tools on|off <name|glob>  Switch tools on or off for this session
+/server:prompt    Run an MCP prompt, e.g. /pcli2:summarize-folder
+@server:uri       Inline an MCP resource into your message
+/yolo [on|off]    Toggle YOLO mode (skip tool confirmation)
 
 MOUSE CONTROLS
 ───────────────────────────────────────────────────────────
@@ -1137,8 +2396,10 @@ Logs Pane (when focused):
   PageUp/PageDown Scroll 5 lines
 
 Tool Confirmation:
-  Y/Enter         Confirm tool execution
-  N/Esc           Cancel tool execution
+  Y/Enter         Allow this call once
+  S               Allow this tool for the session
+  A               Always allow this tool (saved to config)
+  N/Esc           Decline (the model is told you declined)
 
 PANES
 ───────────────────────────────────────────────────────────
@@ -1149,7 +2410,9 @@ Chat History [N]  - Conversation with AI (N = message count)
 
 Input │ model │ 🔌N - Text input for messages
                   - Model name shown in title
-                  - 🔌N shows N connected MCP servers
+                  - 🔌N shows N connected MCP servers, with a
+                    dot per server: green up, yellow degraded,
+                    red down (see /mcp health)
 
 Logs              - Real-time application logs
                   - Color-coded by log level:
@@ -1173,6 +2436,11 @@ Example configuration:
   host = "http://localhost:11434"
   yolo = false
 
+  [permissions]
+  read_file = "allow"
+  run_command = "ask"
+  "pcli2_*_delete" = "deny"
+
   [[mcp_servers]]
   name = "filesystem"
   url = "http://localhost:3000"
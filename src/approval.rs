@@ -0,0 +1,406 @@
+//! Tool-call approval gate
+//!
+//! Every tool registered on the agent's `ToolServer` is wrapped in a [`GatedTool`].
+//! When the model calls a tool, the wrapper resolves the tool's [`ToolPolicy`] from
+//! the `[permissions]` config. `ask` sends an [`ApprovalRequest`] to the TUI and
+//! waits for the user's answer before running the inner tool. Declined and denied
+//! calls are returned to the model as a structured tool result instead of an error.
+
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use rig::{completion::ToolDefinition, tool::Tool, tool::server::ToolServer};
+use serde::Serialize;
+use serde_json::json;
+use tokio::sync::{mpsc, oneshot};
+
+use crate::config::{PermissionsConfig, ToolPolicy};
+
+/// The user's answer to an approval request
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApprovalDecision {
+    /// Run this tool call only
+    Approve,
+    /// Run this tool call and allow the tool for the rest of the session
+    ApproveForSession,
+    /// Run this tool call and always allow the tool (persisted to config)
+    AlwaysApprove,
+    /// Do not run the tool call
+    Decline,
+}
+
+impl ApprovalDecision {
+    /// Whether the tool call should run
+    pub fn is_approved(self) -> bool {
+        self != ApprovalDecision::Decline
+    }
+}
+
+/// Outcome of the approval gate for a single tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// Run the tool call
+    Run,
+    /// The user declined the call
+    Declined,
+    /// A `deny` policy blocked the call
+    Denied,
+}
+
+/// A tool call awaiting the user's confirmation
+#[derive(Debug)]
+pub struct ApprovalRequest {
+    /// Name of the tool the model wants to call
+    pub tool_name: String,
+    /// JSON-encoded tool arguments
+    pub arguments: String,
+    /// Channel used to send the decision back to the waiting tool call
+    responder: oneshot::Sender<ApprovalDecision>,
+}
+
+impl ApprovalRequest {
+    /// Send the user's decision back to the waiting tool call
+    pub fn respond(self, decision: ApprovalDecision) {
+        if self.responder.send(decision).is_err() {
+            tracing::warn!(
+                "Tool call '{}' was abandoned before it could be {}",
+                self.tool_name,
+                if decision.is_approved() { "approved" } else { "declined" }
+            );
+        }
+    }
+}
+
+/// Decides whether tool calls run, asking the TUI when the policy is `ask`
+#[derive(Debug, Clone)]
+pub struct ToolApprover {
+    requests: mpsc::Sender<ApprovalRequest>,
+    yolo: Arc<AtomicBool>,
+    permissions: Arc<Mutex<PermissionsConfig>>,
+    session_allowed: Arc<Mutex<HashSet<String>>>,
+}
+
+impl ToolApprover {
+    /// Create an approver that forwards requests over the given channel
+    pub fn new(
+        requests: mpsc::Sender<ApprovalRequest>,
+        yolo: bool,
+        permissions: PermissionsConfig,
+    ) -> Self {
+        Self {
+            requests,
+            yolo: Arc::new(AtomicBool::new(yolo)),
+            permissions: Arc::new(Mutex::new(permissions)),
+            session_allowed: Arc::new(Mutex::new(HashSet::new())),
+        }
+    }
+
+    /// Turn YOLO mode (auto-approve `ask` tools) on or off
+    pub fn set_yolo(&self, yolo: bool) {
+        self.yolo.store(yolo, Ordering::Relaxed);
+    }
+
+    /// Resolve the policy for a call, taking session approvals and YOLO mode into account
+    fn policy_for(&self, tool_name: &str, raw_name: Option<&str>, arguments: &str) -> ToolPolicy {
+        let args: serde_json::Value =
+            serde_json::from_str(arguments).unwrap_or(serde_json::Value::Null);
+        let names: Vec<&str> = std::iter::once(tool_name).chain(raw_name).collect();
+        let policy = self
+            .permissions
+            .lock()
+            .map(|p| p.policy_for(&names, &args))
+            .unwrap_or(ToolPolicy::Ask);
+
+        // `deny` always wins; YOLO and session approvals only skip the question
+        if policy == ToolPolicy::Ask
+            && (self.yolo.load(Ordering::Relaxed)
+                || self
+                    .session_allowed
+                    .lock()
+                    .is_ok_and(|allowed| allowed.contains(tool_name)))
+        {
+            return ToolPolicy::Allow;
+        }
+        policy
+    }
+
+    /// Check the policy, ask for approval if needed, and wait for the decision
+    ///
+    /// Policies match `tool_name` or, for MCP tools, the server's `raw_name`.
+    /// If the TUI is gone (channel closed or request dropped), the call is declined.
+    pub async fn request(&self, tool_name: &str, raw_name: Option<&str>, arguments: &str) -> Verdict {
+        match self.policy_for(tool_name, raw_name, arguments) {
+            ToolPolicy::Allow => return Verdict::Run,
+            ToolPolicy::Deny => {
+                tracing::warn!("⛔ {} denied by permission policy", tool_name);
+                return Verdict::Denied;
+            }
+            ToolPolicy::Ask => {}
+        }
+
+        let (responder, decision) = oneshot::channel();
+        let request = ApprovalRequest {
+            tool_name: tool_name.to_string(),
+            arguments: arguments.to_string(),
+            responder,
+        };
+
+        if self.requests.send(request).await.is_err() {
+            tracing::warn!("Approval channel closed, declining tool call '{}'", tool_name);
+            return Verdict::Declined;
+        }
+
+        let decision = decision.await.unwrap_or(ApprovalDecision::Decline);
+        match decision {
+            ApprovalDecision::ApproveForSession => {
+                if let Ok(mut allowed) = self.session_allowed.lock() {
+                    allowed.insert(tool_name.to_string());
+                }
+            }
+            ApprovalDecision::AlwaysApprove => {
+                if let Ok(mut permissions) = self.permissions.lock() {
+                    permissions
+                        .tools
+                        .insert(tool_name.to_string(), ToolPolicy::Allow);
+                }
+            }
+            ApprovalDecision::Approve | ApprovalDecision::Decline => {}
+        }
+
+        if decision.is_approved() {
+            Verdict::Run
+        } else {
+            Verdict::Declined
+        }
+    }
+}
+
+/// Tool result returned to the model when a call is declined or denied
+pub fn refused_result(tool_name: &str, verdict: Verdict) -> String {
+    let (status, message) = match verdict {
+        Verdict::Denied => (
+            "denied",
+            "This tool call is blocked by the user's permission policy. Do not retry it; tell the user it is not allowed.",
+        ),
+        _ => (
+            "declined",
+            "The user declined this tool call. Do not retry it; ask the user how they would like to proceed.",
+        ),
+    };
+    json!({
+        "status": status,
+        "tool": tool_name,
+        "message": message
+    })
+    .to_string()
+}
+
+/// A tool that asks for approval before every call
+pub struct GatedTool<T> {
+    inner: T,
+    approver: ToolApprover,
+    /// Name the MCP server gives the tool, when it is exposed under another one
+    raw_name: Option<String>,
+}
+
+impl<T> GatedTool<T> {
+    /// Wrap a tool with an approval gate
+    pub fn new(inner: T, approver: ToolApprover) -> Self {
+        Self {
+            inner,
+            approver,
+            raw_name: None,
+        }
+    }
+
+    /// Let permission policies also match the tool by its server's name for it
+    pub fn with_raw_name(mut self, raw_name: String) -> Self {
+        self.raw_name = Some(raw_name);
+        self
+    }
+}
+
+impl<T> Tool for GatedTool<T>
+where
+    T: Tool<Output = String>,
+    T::Args: Serialize,
+{
+    const NAME: &'static str = T::NAME;
+    type Error = T::Error;
+    type Args = T::Args;
+    type Output = String;
+
+    fn name(&self) -> String {
+        self.inner.name()
+    }
+
+    async fn definition(&self, prompt: String) -> ToolDefinition {
+        self.inner.definition(prompt).await
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        let name = self.inner.name();
+        let arguments = serde_json::to_string(&args).unwrap_or_else(|_| "{}".to_string());
+
+        match self.approver.request(&name, self.raw_name.as_deref(), &arguments).await {
+            Verdict::Run => self.inner.call(args).await,
+            verdict => {
+                if verdict == Verdict::Declined {
+                    tracing::info!("🚫 {} declined by user", name);
+                }
+                Ok(refused_result(&name, verdict))
+            }
+        }
+    }
+}
+
+/// Add a tool to a tool server, gated by the approver if one is given
+pub fn add_tool<T>(tool_server: ToolServer, tool: T, approver: Option<&ToolApprover>) -> ToolServer
+where
+    T: Tool<Output = String> + 'static,
+    T::Args: Serialize,
+{
+    add_tool_as(tool_server, tool, None, approver)
+}
+
+/// Add an MCP tool exposed under a name other than its server's `raw_name`;
+/// permission policies match either name
+pub fn add_tool_as<T>(
+    tool_server: ToolServer,
+    tool: T,
+    raw_name: Option<String>,
+    approver: Option<&ToolApprover>,
+) -> ToolServer
+where
+    T: Tool<Output = String> + 'static,
+    T::Args: Serialize,
+{
+    match (approver, raw_name) {
+        (Some(approver), Some(raw_name)) => {
+            tool_server.tool(GatedTool::new(tool, approver.clone()).with_raw_name(raw_name))
+        }
+        (Some(approver), None) => tool_server.tool(GatedTool::new(tool, approver.clone())),
+        (None, _) => tool_server.tool(tool),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{ListDirectory, ListDirectoryArgs};
+
+    fn list_args() -> ListDirectoryArgs {
+        ListDirectoryArgs {
+            path: std::env::temp_dir().display().to_string(),
+        }
+    }
+
+    fn approver(yolo: bool, permissions: &str) -> (ToolApprover, mpsc::Receiver<ApprovalRequest>) {
+        let (tx, rx) = mpsc::channel(1);
+        let permissions = toml::from_str(permissions).unwrap();
+        (ToolApprover::new(tx, yolo, permissions), rx)
+    }
+
+    #[tokio::test]
+    async fn test_approved_call_runs_tool() {
+        let (approver, mut rx) = approver(false, "");
+        let tool = GatedTool::new(ListDirectory, approver);
+
+        let call = tokio::spawn(async move { tool.call(list_args()).await });
+        let request = rx.recv().await.unwrap();
+        assert_eq!(request.tool_name, "list_directory");
+        assert!(request.arguments.contains("path"));
+        request.respond(ApprovalDecision::Approve);
+
+        let result = call.await.unwrap().unwrap();
+        assert!(result.starts_with("Contents of"));
+    }
+
+    #[tokio::test]
+    async fn test_declined_call_returns_structured_result() {
+        let (approver, mut rx) = approver(false, "");
+        let tool = GatedTool::new(ListDirectory, approver);
+
+        let call = tokio::spawn(async move { tool.call(list_args()).await });
+        rx.recv().await.unwrap().respond(ApprovalDecision::Decline);
+
+        let result: serde_json::Value =
+            serde_json::from_str(&call.await.unwrap().unwrap()).unwrap();
+        assert_eq!(result["status"], "declined");
+        assert_eq!(result["tool"], "list_directory");
+    }
+
+    #[tokio::test]
+    async fn test_yolo_skips_approval() {
+        let (approver, mut rx) = approver(true, "");
+        let tool = GatedTool::new(ListDirectory, approver);
+
+        let result = tool.call(list_args()).await.unwrap();
+        assert!(result.starts_with("Contents of"));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_yolo_can_be_toggled() {
+        let (approver, mut rx) = approver(false, "");
+        let gated = approver.clone();
+        approver.set_yolo(true);
+        assert_eq!(gated.request("run_command", None, "{}").await, Verdict::Run);
+
+        approver.set_yolo(false);
+        let call = tokio::spawn(async move { gated.request("run_command", None, "{}").await });
+        rx.recv().await.unwrap().respond(ApprovalDecision::Decline);
+        assert_eq!(call.await.unwrap(), Verdict::Declined);
+    }
+
+    #[tokio::test]
+    async fn test_closed_channel_declines() {
+        let (approver, rx) = approver(false, "");
+        drop(rx);
+
+        assert_eq!(approver.request("run_command", None, "{}").await, Verdict::Declined);
+    }
+
+    #[tokio::test]
+    async fn test_policies_skip_or_block_the_dialog() {
+        let (approver, mut rx) = approver(true, "list_directory = \"allow\"\nrun_command = \"deny\"");
+
+        assert_eq!(approver.request("list_directory", None, "{}").await, Verdict::Run);
+        // deny wins even in YOLO mode
+        assert_eq!(approver.request("run_command", None, "{}").await, Verdict::Denied);
+        assert!(rx.try_recv().is_err());
+
+        // Raw-name policies still apply to namespaced MCP tools
+        assert_eq!(approver.request("srv__list_directory", Some("list_directory"), "{}").await, Verdict::Run);
+        assert_eq!(approver.request("srv__run_command", Some("run_command"), "{}").await, Verdict::Denied);
+
+        let tool = GatedTool::new(crate::tools::RunCommand, approver);
+        let args = crate::tools::RunCommandArgs {
+            command: "true".to_string(),
+            cwd: None,
+        };
+        let result: serde_json::Value =
+            serde_json::from_str(&tool.call(args).await.unwrap()).unwrap();
+        assert_eq!(result["status"], "denied");
+    }
+
+    #[tokio::test]
+    async fn test_session_and_always_approvals_are_remembered() {
+        let (approver, mut rx) = approver(false, "");
+
+        for (tool, decision) in [
+            ("read_file", ApprovalDecision::ApproveForSession),
+            ("search_code", ApprovalDecision::AlwaysApprove),
+        ] {
+            let pending = approver.clone();
+            let call = tokio::spawn(async move { pending.request(tool, None, "{}").await });
+            rx.recv().await.unwrap().respond(decision);
+            assert_eq!(call.await.unwrap(), Verdict::Run);
+        }
+
+        assert_eq!(approver.request("read_file", None, "{}").await, Verdict::Run);
+        assert_eq!(approver.request("search_code", None, "{}").await, Verdict::Run);
+        assert!(rx.try_recv().is_err());
+    }
+}
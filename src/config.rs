@@ -1,31 +1,397 @@
 //! Configuration for PCLI2-RIG
 
+use anyhow::{Context, Result};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use toml_edit::{ArrayOfTables, DocumentMut, Item, Table};
+
+use crate::images::GraphicsProtocol;
 
 /// MCP Server configuration
+///
+/// A server is either reached over Streamable HTTP (`url`) or launched as a
+/// child process speaking stdio (`command`, with optional `args`, `env` and `cwd`).
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct McpServerConfig {
     /// Server name (e.g., "filesystem", "github")
     pub name: String,
 
-    /// Server URL (e.g., "http://localhost:3000")
-    pub url: String,
+    /// Server URL for HTTP servers (e.g., "http://localhost:3000")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<String>,
+
+    /// Command that starts a stdio server (e.g., "npx")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+
+    /// Arguments for `command`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub args: Vec<String>,
 
-    /// Optional authentication token
+    /// Working directory for `command`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cwd: Option<PathBuf>,
+
+    /// Optional authentication token, sent as `Authorization: Bearer <token>`
     #[serde(default)]
     pub token: Option<String>,
 
+    /// Environment variable holding the token
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_env: Option<String>,
+
+    /// Shell command that prints the token (e.g. a password manager lookup)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_cmd: Option<String>,
+
     /// Whether the server is enabled
-    #[serde(default = "default_true")]
+    #[serde(default = "default_true", skip_serializing_if = "is_true")]
     pub enabled: bool,
+
+    /// Maximum number of tool calls running on the server at once
+    #[serde(default = "default_max_concurrency", skip_serializing_if = "is_default_max_concurrency")]
+    pub max_concurrency: usize,
+
+    /// Extra environment variables for `command`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub env: BTreeMap<String, String>,
+
+    /// Extra HTTP headers sent with every request
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// Namespace for the server's tools, exposed as `<prefix>__<tool>`
+    /// (defaults to the server name; empty keeps the raw tool names)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+
+    /// Globs of the server's tool names to offer (all tools if empty)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub include_tools: Vec<String>,
+
+    /// Globs of the server's tool names to leave out, applied after `include_tools`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude_tools: Vec<String>,
+}
+
+impl McpServerConfig {
+    fn new(name: String) -> Self {
+        Self {
+            name,
+            url: None,
+            command: None,
+            args: Vec::new(),
+            cwd: None,
+            token: None,
+            token_env: None,
+            token_cmd: None,
+            enabled: true,
+            max_concurrency: DEFAULT_MCP_CONCURRENCY,
+            env: BTreeMap::new(),
+            headers: BTreeMap::new(),
+            prefix: None,
+            include_tools: Vec::new(),
+            exclude_tools: Vec::new(),
+        }
+    }
+
+    /// An HTTP server at `url`
+    pub fn http(name: String, url: String) -> Self {
+        Self {
+            url: Some(url),
+            ..Self::new(name)
+        }
+    }
+
+    /// A stdio server started with `command args...`
+    pub fn stdio(name: String, command: String, args: Vec<String>) -> Self {
+        Self {
+            command: Some(command),
+            args,
+            ..Self::new(name)
+        }
+    }
+
+    /// The auth token from `token`, `token_env` or `token_cmd`, checked in that order
+    pub fn resolve_token(&self) -> Result<Option<String>> {
+        if let Some(token) = &self.token {
+            return Ok(Some(token.clone()));
+        }
+        if let Some(var) = &self.token_env {
+            return std::env::var(var)
+                .map(Some)
+                .with_context(|| format!("token_env: ${} is not set", var));
+        }
+        if let Some(cmd) = &self.token_cmd {
+            let output = std::process::Command::new("bash")
+                .arg("-c")
+                .arg(cmd)
+                .output()
+                .with_context(|| format!("Failed to run token_cmd '{}'", cmd))?;
+            if !output.status.success() {
+                anyhow::bail!(
+                    "token_cmd '{}' failed ({}): {}",
+                    cmd,
+                    output.status,
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+            }
+            let token = String::from_utf8_lossy(&output.stdout).trim().to_string();
+            if token.is_empty() {
+                anyhow::bail!("token_cmd '{}' printed no token", cmd);
+            }
+            return Ok(Some(token));
+        }
+        Ok(None)
+    }
+
+    /// Namespace for the server's tools: `prefix` or the server name, with
+    /// characters not allowed in tool names replaced by `_`
+    pub fn tool_prefix(&self) -> String {
+        self.prefix
+            .as_deref()
+            .unwrap_or(&self.name)
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+            .collect()
+    }
+
+    /// Whether `include_tools` and `exclude_tools` let the server's tool `name` through
+    pub fn tool_allowed(&self, name: &str) -> bool {
+        (self.include_tools.is_empty() || self.include_tools.iter().any(|glob| glob_match(glob, name)))
+            && !self.exclude_tools.iter().any(|glob| glob_match(glob, name))
+    }
+
+    /// Where the server is reached: its URL, or the command line of a stdio server
+    pub fn endpoint(&self) -> String {
+        match (&self.command, &self.url) {
+            (Some(command), _) => std::iter::once(command.as_str())
+                .chain(self.args.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(" "),
+            (None, Some(url)) => url.clone(),
+            (None, None) => "no url or command".to_string(),
+        }
+    }
 }
 
 fn default_true() -> bool {
     true
 }
 
+fn is_true(value: &bool) -> bool {
+    *value
+}
+
+/// Default for `McpServerConfig::max_concurrency`
+pub const DEFAULT_MCP_CONCURRENCY: usize = 4;
+
+fn default_max_concurrency() -> usize {
+    DEFAULT_MCP_CONCURRENCY
+}
+
+fn is_default_max_concurrency(value: &usize) -> bool {
+    *value == DEFAULT_MCP_CONCURRENCY
+}
+
+/// Approval policy for a tool call
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolPolicy {
+    /// Run without asking
+    Allow,
+    /// Ask for confirmation in the TUI
+    Ask,
+    /// Never run
+    Deny,
+}
+
+/// Argument-pattern rule, e.g. allow `run_command` when `command` matches `cargo test*`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArgumentRule {
+    /// Tool name or glob (e.g., "run_command", "pcli2_*")
+    pub tool: String,
+    /// Name of the tool argument to match (e.g., "command")
+    pub argument: String,
+    /// Glob the argument value must match (e.g., "cargo test*")
+    pub pattern: String,
+    /// Policy applied when the rule matches
+    pub policy: ToolPolicy,
+}
+
+/// Tool permissions (`[permissions]` section)
+///
+/// ```toml
+/// [permissions]
+/// read_file = "allow"
+/// run_command = "ask"
+/// "pcli2_*_delete" = "deny"
+///
+/// [[permissions.rules]]
+/// tool = "run_command"
+/// argument = "command"
+/// pattern = "cargo test*"
+/// policy = "allow"
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct PermissionsConfig {
+    /// Argument-pattern rules, checked before per-tool policies
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ArgumentRule>,
+
+    /// Per-tool policies keyed by tool name or glob
+    #[serde(flatten)]
+    pub tools: BTreeMap<String, ToolPolicy>,
+}
+
+/// Characters that make an allow rule refuse to match, so `cargo test*` can't
+/// approve `cargo test; rm -rf ~`
+const SHELL_CONTROL_CHARS: &[char] = &[';', '&', '|', '`', '$', '(', ')', '<', '>', '\n'];
+
+impl PermissionsConfig {
+    /// Resolve the policy for a tool call, for a tool known under `names` (an MCP
+    /// tool's namespaced name, then the server's raw name)
+    ///
+    /// `deny` under any name wins; otherwise the first name with a policy decides.
+    /// Tools without a policy default to `ask`.
+    pub fn policy_for(&self, names: &[&str], arguments: &serde_json::Value) -> ToolPolicy {
+        let policies: Vec<ToolPolicy> = names
+            .iter()
+            .filter_map(|name| self.name_policy(name, arguments))
+            .collect();
+        if policies.contains(&ToolPolicy::Deny) {
+            return ToolPolicy::Deny;
+        }
+        policies.first().copied().unwrap_or(ToolPolicy::Ask)
+    }
+
+    /// Policy configured for one tool name, if any
+    ///
+    /// Matching argument rules win over per-tool policies, and `deny` wins among
+    /// rules. For per-tool policies an exact name beats a glob, and a longer glob
+    /// beats a shorter one.
+    fn name_policy(&self, tool_name: &str, arguments: &serde_json::Value) -> Option<ToolPolicy> {
+        let mut rule_policy = None;
+        for rule in &self.rules {
+            if !glob_match(&rule.tool, tool_name) {
+                continue;
+            }
+            let Some(value) = arguments.get(&rule.argument) else {
+                continue;
+            };
+            let value = match value {
+                serde_json::Value::String(s) => s.clone(),
+                other => other.to_string(),
+            };
+            if rule.policy == ToolPolicy::Allow && value.contains(SHELL_CONTROL_CHARS) {
+                continue;
+            }
+            if glob_match(&rule.pattern, &value) {
+                match rule.policy {
+                    ToolPolicy::Deny => return Some(ToolPolicy::Deny),
+                    policy => rule_policy = rule_policy.or(Some(policy)),
+                }
+            }
+        }
+        if rule_policy.is_some() {
+            return rule_policy;
+        }
+
+        if let Some(policy) = self.tools.get(tool_name) {
+            return Some(*policy);
+        }
+        self.tools
+            .iter()
+            .filter(|(pattern, _)| glob_match(pattern, tool_name))
+            .max_by_key(|(pattern, _)| pattern.len())
+            .map(|(_, policy)| *policy)
+    }
+
+    /// Per-tool keys and rule tool patterns that match none of `tool_names`,
+    /// typically typos or names of tools that are no longer offered
+    pub fn unmatched_patterns(&self, tool_names: &[String]) -> Vec<&str> {
+        let mut unmatched: Vec<&str> = self
+            .tools
+            .keys()
+            .chain(self.rules.iter().map(|rule| &rule.tool))
+            .filter(|pattern| !tool_names.iter().any(|name| glob_match(pattern, name)))
+            .map(String::as_str)
+            .collect();
+        unmatched.sort_unstable();
+        unmatched.dedup();
+        unmatched
+    }
+}
+
+/// Dynamic tool selection (`[tool_selection]` section)
+///
+/// ```toml
+/// [tool_selection]
+/// enabled = true
+/// model = "nomic-embed-text"
+/// top_k = 6
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ToolSelectionConfig {
+    /// Advertise only the tools most relevant to each message
+    #[serde(default)]
+    pub enabled: bool,
+
+    /// Ollama embedding model used to index the tools
+    #[serde(default = "default_embedding_model")]
+    pub model: String,
+
+    /// Number of tools advertised per message
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+}
+
+impl Default for ToolSelectionConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            model: default_embedding_model(),
+            top_k: default_top_k(),
+        }
+    }
+}
+
+fn default_embedding_model() -> String {
+    "nomic-embed-text".to_string()
+}
+
+fn default_top_k() -> usize {
+    6
+}
+
+/// Match text against a glob supporting `*` (any run) and `?` (any single character)
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+
+    // Iterative matcher with backtracking to the last `*`
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
 /// Application configuration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Config {
@@ -39,6 +405,18 @@ pub struct Config {
     #[serde(default)]
     pub yolo: bool,
 
+    /// How to draw images in the chat (detected from the terminal if unset)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub graphics: Option<GraphicsProtocol>,
+
+    /// Tool approval policies
+    #[serde(default)]
+    pub permissions: PermissionsConfig,
+
+    /// Dynamic tool selection
+    #[serde(default)]
+    pub tool_selection: ToolSelectionConfig,
+
     /// MCP servers configuration
     #[serde(default)]
     pub mcp_servers: Vec<McpServerConfig>,
@@ -50,6 +428,9 @@ impl Default for Config {
             model: "qwen2.5-coder:3b".to_string(),
             host: "http://localhost:11434".to_string(),
             yolo: false,
+            permissions: PermissionsConfig::default(),
+            tool_selection: ToolSelectionConfig::default(),
+            graphics: None,
             mcp_servers: Vec::new(),
         }
     }
@@ -63,10 +444,25 @@ impl Config {
             model,
             host,
             yolo,
+            permissions: PermissionsConfig::default(),
+            tool_selection: ToolSelectionConfig::default(),
+            graphics: None,
             mcp_servers: Vec::new(),
         }
     }
 
+    /// Ollama base URL: `host` with a scheme and without a trailing slash
+    ///
+    /// Accepts the `host[:port]` form Ollama itself uses for `OLLAMA_HOST`.
+    pub fn ollama_url(&self) -> String {
+        let host = self.host.trim().trim_end_matches('/');
+        if host.contains("://") {
+            host.to_string()
+        } else {
+            format!("http://{}", host)
+        }
+    }
+
     /// Get enabled MCP servers
     pub fn enabled_mcp_servers(&self) -> Vec<&McpServerConfig> {
         self.mcp_servers.iter().filter(|s| s.enabled).collect()
@@ -81,22 +477,420 @@ impl Config {
     }
 
     /// Load configuration from file, or return default if not found
-    pub fn load() -> Self {
-        if let Some(config_path) = Self::config_file_path() {
-            if config_path.exists() {
-                if let Ok(content) = fs::read_to_string(&config_path) {
-                    if let Ok(config) = toml::from_str::<Config>(&content) {
-                        tracing::info!("Loaded config from {}", config_path.display());
-                        tracing::debug!("Loaded {} MCP servers from config", config.mcp_servers.len());
-                        for server in &config.mcp_servers {
-                            tracing::debug!("  MCP server: {} -> {}", server.name, server.url);
-                        }
-                        return config;
-                    }
-                }
+    ///
+    /// A file that cannot be read or parsed is an error rather than a silent
+    /// fallback, so its permissions are never dropped.
+    pub fn load() -> Result<Self> {
+        match Self::config_file_path() {
+            Some(config_path) if config_path.exists() => Self::load_from(&config_path),
+            _ => {
+                tracing::debug!("Using default configuration");
+                Ok(Config::default())
             }
         }
-        tracing::debug!("Using default configuration");
-        Config::default()
+    }
+
+    /// Load configuration from a file
+    pub fn load_from(config_path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(config_path)
+            .with_context(|| format!("Failed to read {}", config_path.display()))?;
+        let config = toml::from_str::<Config>(&content)
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+        tracing::info!("Loaded config from {}", config_path.display());
+        tracing::debug!("Loaded {} MCP servers from config", config.mcp_servers.len());
+        for server in &config.mcp_servers {
+            tracing::debug!("  MCP server: {} -> {}", server.name, server.endpoint());
+        }
+        Ok(config)
+    }
+
+    /// Save a tool policy to the `[permissions]` table of the config file
+    pub fn save_tool_policy(tool_name: &str, policy: ToolPolicy) -> Result<PathBuf> {
+        Self::edit_file(|doc| set_tool_policy(doc, tool_name, policy))
+    }
+
+    /// Apply a change to the MCP servers in the config file
+    ///
+    /// `update` sees the servers saved in the file, not the running list, so
+    /// servers given on the command line (`--mcp-remote`) are not written back.
+    pub fn update_mcp_servers(update: impl FnOnce(&mut Vec<McpServerConfig>)) -> Result<PathBuf> {
+        Self::edit_file(|doc| {
+            #[derive(Deserialize)]
+            struct Saved {
+                #[serde(default)]
+                mcp_servers: Vec<McpServerConfig>,
+            }
+            let mut servers = toml::from_str::<Saved>(&doc.to_string())?.mcp_servers;
+            update(&mut servers);
+            set_mcp_servers(doc, &servers)
+        })
+    }
+
+    /// Edit the config file on disk in place
+    ///
+    /// Only the tables `update` touches change, so the user's comments, ordering
+    /// and omitted defaults elsewhere in the file are kept.
+    fn edit_file(update: impl FnOnce(&mut DocumentMut) -> Result<()>) -> Result<PathBuf> {
+        let config_path =
+            Self::config_file_path().context("Could not determine config file path")?;
+
+        let content = if config_path.exists() {
+            fs::read_to_string(&config_path)
+                .with_context(|| format!("Failed to read {}", config_path.display()))?
+        } else {
+            String::new()
+        };
+        let mut doc = content
+            .parse::<DocumentMut>()
+            .with_context(|| format!("Failed to parse {}", config_path.display()))?;
+
+        update(&mut doc).with_context(|| format!("Failed to update {}", config_path.display()))?;
+
+        if let Some(config_dir) = config_path.parent() {
+            fs::create_dir_all(config_dir)?;
+        }
+        fs::write(&config_path, doc.to_string())
+            .with_context(|| format!("Failed to write {}", config_path.display()))?;
+
+        Ok(config_path)
+    }
+}
+
+/// Set `tool_name = "<policy>"` in the `[permissions]` table
+fn set_tool_policy(doc: &mut DocumentMut, tool_name: &str, policy: ToolPolicy) -> Result<()> {
+    let permissions = doc
+        .entry("permissions")
+        .or_insert_with(toml_edit::table)
+        .as_table_like_mut()
+        .context("`permissions` is not a table")?;
+    let policy = match policy {
+        ToolPolicy::Allow => "allow",
+        ToolPolicy::Ask => "ask",
+        ToolPolicy::Deny => "deny",
+    };
+    permissions.insert(tool_name, toml_edit::value(policy));
+    Ok(())
+}
+
+/// Replace the `[[mcp_servers]]` tables with `servers`
+///
+/// Tables of servers that did not change are kept as written.
+fn set_mcp_servers(doc: &mut DocumentMut, servers: &[McpServerConfig]) -> Result<()> {
+    let existing: Vec<Table> = doc
+        .get("mcp_servers")
+        .and_then(Item::as_array_of_tables)
+        .map(|tables| tables.iter().cloned().collect())
+        .unwrap_or_default();
+
+    let mut tables = ArrayOfTables::new();
+    for server in servers {
+        let saved = toml::to_string(server)?;
+        let unchanged = existing.iter().find(|table| {
+            toml::from_str::<McpServerConfig>(&table.to_string())
+                .ok()
+                .and_then(|old| toml::to_string(&old).ok())
+                .is_some_and(|old| old == saved)
+        });
+        let table = match unchanged {
+            Some(table) => table.clone(),
+            None => {
+                let mut table = saved.parse::<DocumentMut>()?.as_table().clone();
+                // `env` and `headers` stay inline so they cannot drift away from their server
+                for (_, item) in table.iter_mut() {
+                    item.make_value();
+                }
+                table
+            }
+        };
+        tables.push(table);
+    }
+
+    if tables.is_empty() {
+        doc.remove("mcp_servers");
+    } else {
+        doc.insert("mcp_servers", Item::ArrayOfTables(tables));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn permissions() -> PermissionsConfig {
+        toml::from_str(
+            r#"
+read_file = "allow"
+run_command = "ask"
+"pcli2_*" = "allow"
+"pcli2_*_delete" = "deny"
+
+[[rules]]
+tool = "run_command"
+argument = "command"
+pattern = "cargo test*"
+policy = "allow"
+
+[[rules]]
+tool = "write_file"
+argument = "path"
+pattern = "/etc/*"
+policy = "deny"
+"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_glob_match() {
+        assert!(glob_match("pcli2_*_delete", "pcli2_folder_delete"));
+        assert!(glob_match("cargo test*", "cargo test"));
+        assert!(glob_match("read_?ile", "read_file"));
+        assert!(glob_match("*", ""));
+        assert!(!glob_match("pcli2_*_delete", "pcli2_folder_list"));
+        assert!(!glob_match("cargo test*", "cargo build"));
+    }
+
+    #[test]
+    fn test_tool_policies() {
+        let permissions = permissions();
+        let no_args = json!({});
+
+        assert_eq!(permissions.policy_for(&["read_file"], &no_args), ToolPolicy::Allow);
+        assert_eq!(permissions.policy_for(&["run_command"], &no_args), ToolPolicy::Ask);
+        assert_eq!(permissions.policy_for(&["pcli2_folder_list"], &no_args), ToolPolicy::Allow);
+        // Longer glob is more specific
+        assert_eq!(permissions.policy_for(&["pcli2_folder_delete"], &no_args), ToolPolicy::Deny);
+        assert_eq!(permissions.policy_for(&["search_code"], &no_args), ToolPolicy::Ask);
+
+        // An MCP tool matches under its namespaced and raw names, and deny wins
+        assert_eq!(permissions.policy_for(&["srv__folder_list", "pcli2_folder_list"], &no_args), ToolPolicy::Allow);
+        assert_eq!(permissions.policy_for(&["read_file", "pcli2_folder_delete"], &no_args), ToolPolicy::Deny);
+        assert_eq!(permissions.policy_for(&["srv__search", "search"], &no_args), ToolPolicy::Ask);
+
+        let names = ["read_file".to_string(), "pcli2_folder_list".to_string()];
+        assert_eq!(permissions.unmatched_patterns(&names), vec!["pcli2_*_delete", "run_command", "write_file"]);
+    }
+
+    #[test]
+    fn test_argument_rules() {
+        let permissions = permissions();
+
+        let allowed = json!({ "command": "cargo test --workspace" });
+        assert_eq!(permissions.policy_for(&["run_command"], &allowed), ToolPolicy::Allow);
+
+        let other = json!({ "command": "cargo publish" });
+        assert_eq!(permissions.policy_for(&["run_command"], &other), ToolPolicy::Ask);
+
+        let chained = json!({ "command": "cargo test; rm -rf ~" });
+        assert_eq!(permissions.policy_for(&["run_command"], &chained), ToolPolicy::Ask);
+
+        let etc = json!({ "path": "/etc/hosts", "content": "" });
+        assert_eq!(permissions.policy_for(&["write_file"], &etc), ToolPolicy::Deny);
+    }
+
+    #[test]
+    fn test_permissions_roundtrip() {
+        let config = Config {
+            permissions: permissions(),
+            ..Config::default()
+        };
+        let toml = toml::to_string_pretty(&config).unwrap();
+        assert!(toml.contains("[permissions]"));
+        assert!(toml.contains("\"pcli2_*_delete\" = \"deny\""));
+
+        let parsed: Config = toml::from_str(&toml).unwrap();
+        assert_eq!(parsed.permissions, config.permissions);
+    }
+
+    #[test]
+    fn test_tool_selection_defaults() {
+        let config: Config = toml::from_str(
+            r#"
+model = "qwen2.5-coder:3b"
+host = "http://localhost:11434"
+
+[tool_selection]
+enabled = true
+"#,
+        )
+        .unwrap();
+        assert!(config.tool_selection.enabled);
+        assert_eq!(config.tool_selection.model, "nomic-embed-text");
+        assert_eq!(config.tool_selection.top_k, 6);
+        assert!(!Config::default().tool_selection.enabled);
+    }
+
+    #[test]
+    fn test_ollama_url() {
+        let url = |host: &str| Config::new("m".to_string(), host.to_string(), false).ollama_url();
+        assert_eq!(url("http://192.168.1.100:11434/"), "http://192.168.1.100:11434");
+        assert_eq!(url("https://ollama.example.com"), "https://ollama.example.com");
+        assert_eq!(url("0.0.0.0:11434"), "http://0.0.0.0:11434");
+    }
+
+    #[test]
+    fn test_stdio_server_roundtrip() {
+        let mut server = McpServerConfig::stdio(
+            "filesystem".to_string(),
+            "npx".to_string(),
+            vec!["-y".to_string(), "@modelcontextprotocol/server-filesystem".to_string()],
+        );
+        server.env.insert("DEBUG".to_string(), "1".to_string());
+        server.cwd = Some(PathBuf::from("/tmp"));
+        let config = Config {
+            mcp_servers: vec![
+                server,
+                McpServerConfig::http("pcli2".to_string(), "http://localhost:8080/mcp".to_string()),
+            ],
+            ..Config::default()
+        };
+
+        let parsed: Config = toml::from_str(&toml::to_string_pretty(&config).unwrap()).unwrap();
+        let stdio = &parsed.mcp_servers[0];
+        assert_eq!(stdio.command.as_deref(), Some("npx"));
+        assert_eq!(stdio.url, None);
+        assert_eq!(stdio.env.get("DEBUG").map(String::as_str), Some("1"));
+        assert_eq!(stdio.endpoint(), "npx -y @modelcontextprotocol/server-filesystem");
+        assert_eq!(parsed.mcp_servers[1].endpoint(), "http://localhost:8080/mcp");
+    }
+
+    #[test]
+    fn test_resolve_token() {
+        let mut server = McpServerConfig::http("pcli2".to_string(), "https://example.com/mcp".to_string());
+        assert_eq!(server.resolve_token().unwrap(), None);
+
+        server.token_cmd = Some("echo ' s3cret '".to_string());
+        assert_eq!(server.resolve_token().unwrap().as_deref(), Some("s3cret"));
+
+        server.token_env = Some("PATH".to_string());
+        assert_eq!(server.resolve_token().unwrap(), std::env::var("PATH").ok());
+        server.token_env = Some("PCLI2_RIG_TEST_UNSET_TOKEN".to_string());
+        assert!(server.resolve_token().is_err());
+
+        // A literal token wins over the other sources
+        server.token = Some("literal".to_string());
+        assert_eq!(server.resolve_token().unwrap().as_deref(), Some("literal"));
+
+        server.token = None;
+        server.token_env = None;
+        server.token_cmd = Some("exit 3".to_string());
+        assert!(server.resolve_token().is_err());
+    }
+
+    #[test]
+    fn test_tool_prefix() {
+        let mut server = McpServerConfig::http("pcli2 prod".to_string(), "http://localhost:8080/mcp".to_string());
+        assert_eq!(server.tool_prefix(), "pcli2_prod");
+        server.prefix = Some("p2".to_string());
+        assert_eq!(server.tool_prefix(), "p2");
+        server.prefix = Some(String::new());
+        assert_eq!(server.tool_prefix(), "");
+    }
+
+    #[test]
+    fn test_tool_filters() {
+        let mut server: McpServerConfig = toml::from_str(
+            r#"
+name = "pcli2"
+url = "http://localhost:8080/mcp"
+include_tools = ["folder_*", "asset_*"]
+exclude_tools = ["*_delete"]
+"#,
+        )
+        .unwrap();
+        assert!(server.tool_allowed("folder_list"));
+        assert!(server.tool_allowed("asset_get"));
+        assert!(!server.tool_allowed("asset_delete"));
+        assert!(!server.tool_allowed("tenant_list"));
+
+        server.include_tools.clear();
+        assert!(server.tool_allowed("tenant_list"));
+        assert!(!server.tool_allowed("folder_delete"));
+    }
+
+    const USER_CONFIG: &str = r#"# My Ollama setup
+model = "llama3.2"   # small and fast
+host = "http://localhost:11434"
+
+[permissions]
+# Reading is harmless
+read_file = "allow"
+
+# The PCLI2 server
+[[mcp_servers]]
+name = "pcli2"
+url = "http://localhost:8080/mcp"
+
+[[mcp_servers]]
+name = "fs"
+command = "npx"
+"#;
+
+    #[test]
+    fn test_tool_policy_edit_keeps_file_layout() {
+        let mut doc = USER_CONFIG.parse::<DocumentMut>().unwrap();
+        set_tool_policy(&mut doc, "pcli2__folder_*", ToolPolicy::Allow).unwrap();
+        let content = doc.to_string();
+
+        assert_eq!(
+            content,
+            USER_CONFIG.replace(
+                "read_file = \"allow\"\n",
+                "read_file = \"allow\"\n\"pcli2__folder_*\" = \"allow\"\n"
+            )
+        );
+        let config: Config = toml::from_str(&content).unwrap();
+        assert_eq!(config.permissions.tools["pcli2__folder_*"], ToolPolicy::Allow);
+    }
+
+    #[test]
+    fn test_mcp_server_edit_keeps_unchanged_servers() {
+        let mut doc = USER_CONFIG.parse::<DocumentMut>().unwrap();
+        let mut servers = toml::from_str::<Config>(USER_CONFIG).unwrap().mcp_servers;
+        servers.retain(|s| s.name != "fs");
+        servers[0].max_concurrency = DEFAULT_MCP_CONCURRENCY;
+        let mut github = McpServerConfig::http("github".to_string(), "https://example.com/mcp".to_string());
+        github.headers.insert("X-Tenant".to_string(), "acme".to_string());
+        servers.push(github);
+        set_mcp_servers(&mut doc, &servers).unwrap();
+        let content = doc.to_string();
+
+        // Comments and untouched servers stay as written; defaults are not added
+        assert!(content.starts_with("# My Ollama setup\nmodel = \"llama3.2\"   # small and fast\n"));
+        assert!(content.contains("# The PCLI2 server\n[[mcp_servers]]\nname = \"pcli2\"\nurl = \"http://localhost:8080/mcp\"\n"));
+        assert!(!content.contains("npx"));
+        assert!(!content.contains("enabled"));
+        assert!(!content.contains("max_concurrency"));
+
+        let config: Config = toml::from_str(&content).unwrap();
+        let names: Vec<_> = config.mcp_servers.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, ["pcli2", "github"]);
+        assert_eq!(config.mcp_servers[1].headers["X-Tenant"], "acme");
+
+        // A changed server keeps its place in the list
+        servers[0].enabled = false;
+        set_mcp_servers(&mut doc, &servers).unwrap();
+        let config: Config = toml::from_str(&doc.to_string()).unwrap();
+        assert_eq!(config.mcp_servers[0].name, "pcli2");
+        assert!(!config.mcp_servers[0].enabled);
+
+        set_mcp_servers(&mut doc, &[]).unwrap();
+        assert!(!doc.to_string().contains("mcp_servers"));
+    }
+
+    #[test]
+    fn test_load_from_rejects_invalid_file() {
+        let path = std::env::temp_dir().join(format!("pcli2-rig-config-{}.toml", std::process::id()));
+        fs::write(&path, USER_CONFIG).unwrap();
+        assert_eq!(Config::load_from(&path).unwrap().mcp_servers.len(), 2);
+
+        // A typo must not silently drop the permissions
+        fs::write(&path, "[permissions\nrun_command = \"deny\"\n").unwrap();
+        let error = format!("{:#}", Config::load_from(&path).unwrap_err());
+        assert!(error.starts_with(&format!("Failed to parse {}", path.display())));
+
+        fs::remove_file(&path).unwrap();
     }
 }
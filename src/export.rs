@@ -0,0 +1,359 @@
+//! Conversation export
+//!
+//! Renders a chat history as Markdown, structured JSON or a standalone HTML page,
+//! for `/export <path>` and `pcli2-rig export <session>`.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use chrono::Local;
+use clap::ValueEnum;
+use pulldown_cmark::{Event, Options, Parser, Tag};
+use serde_json::json;
+
+use crate::agent::{ChatMessage, MessageRole, ToolCallRequest};
+use crate::session::SessionInfo;
+
+/// Export file format
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    Markdown,
+    Json,
+    Html,
+}
+
+impl ExportFormat {
+    /// Guess the format from a file extension
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?.to_lowercase();
+        match ext.as_str() {
+            "md" | "markdown" => Some(ExportFormat::Markdown),
+            "json" => Some(ExportFormat::Json),
+            "html" | "htm" => Some(ExportFormat::Html),
+            _ => None,
+        }
+    }
+}
+
+/// Render a conversation in the given format
+pub fn render(format: ExportFormat, info: &SessionInfo, messages: &[ChatMessage]) -> Result<String> {
+    match format {
+        ExportFormat::Markdown => Ok(to_markdown(info, messages)),
+        ExportFormat::Json => to_json(info, messages),
+        ExportFormat::Html => Ok(to_html(info, messages)),
+    }
+}
+
+/// Write a conversation to a file, in `format` or the format implied by its extension
+///
+/// Unknown extensions default to Markdown.
+pub fn write_file(
+    path: &Path,
+    format: Option<ExportFormat>,
+    info: &SessionInfo,
+    messages: &[ChatMessage],
+) -> Result<ExportFormat> {
+    let format = format
+        .or_else(|| ExportFormat::from_path(path))
+        .unwrap_or(ExportFormat::Markdown);
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create {}", dir.display()))?;
+    }
+    std::fs::write(path, render(format, info, messages)?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(format)
+}
+
+/// Heading shown for each message role
+fn role_heading(role: &MessageRole) -> &'static str {
+    match role {
+        MessageRole::User => "👤 You",
+        MessageRole::Assistant => "🤖 Assistant",
+        MessageRole::System => "⚙️ System",
+        MessageRole::ToolResult => "🔧 Tool result",
+        MessageRole::Notice => "ℹ️ Notice",
+    }
+}
+
+/// Messages of the conversation, without UI notices
+fn without_notices(messages: &[ChatMessage]) -> impl Iterator<Item = &ChatMessage> {
+    messages.iter().filter(|msg| msg.role != MessageRole::Notice)
+}
+
+/// One-line description of the session for export headers
+fn subtitle(info: &SessionInfo) -> String {
+    let mut subtitle = format!(
+        "Model: {} · Session: {} · Exported: {}",
+        info.model,
+        info.id,
+        Local::now().format("%Y-%m-%d %H:%M")
+    );
+    if !info.mcp_servers.is_empty() {
+        subtitle.push_str(&format!(" · MCP: {}", info.mcp_servers.join(", ")));
+    }
+    subtitle
+}
+
+/// Pretty-print tool arguments, falling back to the raw string
+fn pretty_arguments(call: &ToolCallRequest) -> String {
+    serde_json::from_str::<serde_json::Value>(&call.arguments)
+        .and_then(|args| serde_json::to_string_pretty(&args))
+        .unwrap_or_else(|_| call.arguments.clone())
+}
+
+/// A code fence longer than any backtick run in `content`
+fn fence_for(content: &str) -> String {
+    let longest = content
+        .split(|c| c != '`')
+        .map(str::len)
+        .max()
+        .unwrap_or(0);
+    "`".repeat(longest.max(2) + 1)
+}
+
+/// Wrap text in a fenced code block
+fn code_block(language: &str, content: &str) -> String {
+    let fence = fence_for(content);
+    format!("{}{}\n{}\n{}\n", fence, language, content.trim_end(), fence)
+}
+
+/// Render as Markdown, with tool calls and results in collapsible sections
+pub fn to_markdown(info: &SessionInfo, messages: &[ChatMessage]) -> String {
+    let mut md = format!("# {}\n\n_{}_\n", info.label(), subtitle(info));
+
+    for msg in without_notices(messages) {
+        if msg.role == MessageRole::ToolResult {
+            // Tool results follow the call that produced them
+            md.push_str(&format!(
+                "\n<details>\n<summary>🔧 Result{}</summary>\n\n{}\n</details>\n",
+                msg.tool_call_id
+                    .as_deref()
+                    .map(|id| format!(": {}", id))
+                    .unwrap_or_default(),
+                code_block("", &msg.content)
+            ));
+            continue;
+        }
+
+        md.push_str(&format!("\n## {}\n\n", role_heading(&msg.role)));
+        if !msg.content.trim().is_empty() {
+            md.push_str(msg.content.trim_end());
+            md.push('\n');
+        }
+        for call in &msg.tool_calls {
+            md.push_str(&format!(
+                "\n<details>\n<summary>🔧 Tool call: {}</summary>\n\n{}\n</details>\n",
+                call.tool_name,
+                code_block("json", &pretty_arguments(call))
+            ));
+        }
+    }
+
+    md
+}
+
+/// Render as structured JSON: session metadata plus the messages
+pub fn to_json(info: &SessionInfo, messages: &[ChatMessage]) -> Result<String> {
+    let export = json!({
+        "session": info,
+        "messages": without_notices(messages).collect::<Vec<_>>(),
+    });
+    Ok(serde_json::to_string_pretty(&export)?)
+}
+
+/// Escape text for HTML
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Whether a link or image target is safe to keep: http(s), mailto or relative
+fn is_safe_url(url: &str) -> bool {
+    let url = url.trim();
+    let Some(colon) = url.find(':') else {
+        return true;
+    };
+    let scheme = &url[..colon];
+    // A colon after the start of a path, query or fragment does not end a scheme
+    scheme.contains(['/', '?', '#'])
+        || matches!(scheme.to_ascii_lowercase().as_str(), "http" | "https" | "mailto")
+}
+
+/// Render Markdown to HTML; raw HTML in the source is shown as text, and links
+/// and images with other schemes (e.g. `javascript:`) point to `#`
+fn markdown_to_html(markdown: &str) -> String {
+    let parser = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH)
+        .map(|event| match event {
+            Event::Html(html) | Event::InlineHtml(html) => Event::Text(html),
+            Event::Start(Tag::Link { link_type, dest_url, title, id }) if !is_safe_url(&dest_url) => {
+                Event::Start(Tag::Link { link_type, dest_url: "#".into(), title, id })
+            }
+            Event::Start(Tag::Image { link_type, dest_url, title, id }) if !is_safe_url(&dest_url) => {
+                Event::Start(Tag::Image { link_type, dest_url: "#".into(), title, id })
+            }
+            event => event,
+        });
+    let mut html = String::new();
+    pulldown_cmark::html::push_html(&mut html, parser);
+    html
+}
+
+const HTML_STYLE: &str = r#"
+body { background: #111; color: #e6dcc8; font-family: -apple-system, "Segoe UI", sans-serif; max-width: 900px; margin: 2rem auto; padding: 0 1rem; line-height: 1.5; }
+h1 { color: #ffb43c; }
+.subtitle { color: #786e64; }
+.message { border-left: 3px solid #786e64; padding: 0.25rem 1rem; margin: 1.5rem 0; }
+.message.user { border-color: #ff823c; }
+.message.assistant { border-color: #78c878; }
+.role { font-weight: bold; color: #ff823c; }
+pre { background: #1e1e1e; padding: 0.75rem; overflow-x: auto; }
+code { font-family: "SF Mono", Menlo, Consolas, monospace; }
+details { margin: 0.5rem 0; }
+summary { cursor: pointer; color: #b482c8; }
+table { border-collapse: collapse; }
+td, th { border: 1px solid #444; padding: 0.25rem 0.5rem; }
+"#;
+
+/// Render as a standalone HTML page
+pub fn to_html(info: &SessionInfo, messages: &[ChatMessage]) -> String {
+    let mut body = String::new();
+    for msg in without_notices(messages) {
+        if msg.role == MessageRole::ToolResult {
+            body.push_str(&format!(
+                "<details><summary>🔧 Result{}</summary><pre><code>{}</code></pre></details>\n",
+                msg.tool_call_id
+                    .as_deref()
+                    .map(|id| format!(": {}", escape_html(id)))
+                    .unwrap_or_default(),
+                escape_html(msg.content.trim_end())
+            ));
+            continue;
+        }
+
+        let class = match msg.role {
+            MessageRole::User => "user",
+            MessageRole::Assistant => "assistant",
+            _ => "system",
+        };
+        body.push_str(&format!(
+            "<div class=\"message {}\">\n<p class=\"role\">{}</p>\n{}",
+            class,
+            role_heading(&msg.role),
+            markdown_to_html(&msg.content)
+        ));
+        for call in &msg.tool_calls {
+            body.push_str(&format!(
+                "<details><summary>🔧 Tool call: {}</summary><pre><code>{}</code></pre></details>\n",
+                escape_html(&call.tool_name),
+                escape_html(&pretty_arguments(call))
+            ));
+        }
+        body.push_str("</div>\n");
+    }
+
+    format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n<style>{style}</style>\n</head>\n<body>\n<h1>{title}</h1>\n<p class=\"subtitle\">{subtitle}</p>\n{body}</body>\n</html>\n",
+        title = escape_html(info.label()),
+        style = HTML_STYLE,
+        subtitle = escape_html(&subtitle(info)),
+        body = body
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, StreamEvent};
+    use crate::config::Config;
+
+    fn conversation() -> Vec<ChatMessage> {
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.add_notice("Type /help for available commands".to_string());
+        agent.add_user_message("Show me <main.rs>".to_string());
+        agent.push_stream_event(StreamEvent::ToolCall(ToolCallRequest {
+            tool_name: "read_file".to_string(),
+            arguments: r#"{"path":"src/main.rs"}"#.to_string(),
+            call_id: "read_file".to_string(),
+        }));
+        agent.push_stream_event(StreamEvent::ToolResult {
+            call_id: "read_file".to_string(),
+            content: "fn main() {}\n```".to_string(),
+            attachments: Vec::new(),
+        });
+        agent.push_stream_event(StreamEvent::Text(
+            "It is empty:\n\n```rust\nfn main() {}\n```".to_string(),
+        ));
+        agent.chat_history().to_vec()
+    }
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(ExportFormat::from_path(Path::new("notes.md")), Some(ExportFormat::Markdown));
+        assert_eq!(ExportFormat::from_path(Path::new("chat.JSON")), Some(ExportFormat::Json));
+        assert_eq!(ExportFormat::from_path(Path::new("a/b.html")), Some(ExportFormat::Html));
+        assert_eq!(ExportFormat::from_path(Path::new("chat.txt")), None);
+    }
+
+    #[test]
+    fn test_markdown_export() {
+        let info = SessionInfo::new("qwen2.5-coder:3b");
+        let md = to_markdown(&info, &conversation());
+
+        assert!(md.contains("## 👤 You\n\nShow me <main.rs>"));
+        assert!(md.contains("<summary>🔧 Tool call: read_file</summary>"));
+        assert!(md.contains("\"path\": \"src/main.rs\""));
+        // The result contains a fence, so it is wrapped in a longer one
+        assert!(md.contains("````\nfn main() {}\n```\n````"));
+        assert!(md.contains("```rust\nfn main() {}\n```"));
+        assert!(!md.contains("/help"));
+    }
+
+    #[test]
+    fn test_json_export() {
+        let info = SessionInfo::new("qwen2.5-coder:3b");
+        let value: serde_json::Value =
+            serde_json::from_str(&to_json(&info, &conversation()).unwrap()).unwrap();
+
+        assert_eq!(value["session"]["model"], "qwen2.5-coder:3b");
+        assert_eq!(value["messages"][1]["tool_calls"][0]["tool_name"], "read_file");
+        assert_eq!(value["messages"][2]["role"], "tool_result");
+        assert_eq!(value["messages"].as_array().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_html_export_escapes_content() {
+        let info = SessionInfo::new("qwen2.5-coder:3b");
+        let html = to_html(&info, &conversation());
+
+        assert!(html.starts_with("<!DOCTYPE html>"));
+        assert!(html.contains("Show me &lt;main.rs&gt;"));
+        assert!(html.contains("<code class=\"language-rust\">"));
+        assert!(!html.contains("<main.rs>"));
+        assert!(!html.contains("/help"));
+    }
+
+    #[test]
+    fn test_html_export_neutralizes_script_links() {
+        let html = markdown_to_html(
+            "[docs](https://example.com) [x](javascript:alert(1)) [y](JavaScript:alert(2)) \
+             ![img](data:image/svg+xml;base64,PHN2Zz4=) [rel](notes/a.md) <vbscript:msgbox>",
+        );
+        assert!(html.contains(r#"<a href="https://example.com">docs</a>"#));
+        assert!(html.contains(r#"<a href="notes/a.md">rel</a>"#));
+        assert!(!html.to_lowercase().contains("javascript:"));
+        assert!(!html.contains("src=\"data:"));
+        assert!(!html.contains("href=\"vbscript:"));
+        assert_eq!(html.matches(r##"href="#""##).count(), 3);
+    }
+}
@@ -0,0 +1,240 @@
+//! Background health checks for MCP servers
+//!
+//! Each connected server gets a monitor task that pings it and records whether it
+//! is up, degraded or down on its [`McpClient`]. A server that is down is
+//! reconnected with exponential backoff, and its tools are listed again so the
+//! tool server can be rebuilt.
+
+use std::time::Duration;
+
+use anyhow::Result;
+use tokio::sync::mpsc;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+use crate::app::AppMessage;
+use crate::mcp::{HealthState, McpClient};
+
+/// Consecutive failed pings before a server is marked down
+const FAILURES_BEFORE_DOWN: u32 = 2;
+
+/// Timing of the health checks
+#[derive(Debug, Clone, Copy)]
+pub struct HealthCheck {
+    /// Time between pings of a server that is up
+    pub interval: Duration,
+    /// How long a ping or reconnect may take before it counts as failed
+    pub timeout: Duration,
+    /// Pings slower than this mark the server degraded
+    pub slow: Duration,
+    /// First reconnect delay, doubled after each failed attempt
+    pub retry: Duration,
+    /// Longest reconnect delay
+    pub max_retry: Duration,
+}
+
+impl Default for HealthCheck {
+    fn default() -> Self {
+        Self {
+            interval: Duration::from_secs(30),
+            timeout: Duration::from_secs(10),
+            slow: Duration::from_secs(2),
+            retry: Duration::from_secs(1),
+            max_retry: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Check a server until cancelled
+///
+/// After a reconnect the server's tools are sent as [`AppMessage::McpReconnected`].
+pub async fn monitor(client: McpClient, check: HealthCheck, tx: mpsc::Sender<AppMessage>, cancel: CancellationToken) {
+    let name = client.name().to_string();
+    let mut retry = check.retry;
+    loop {
+        let down = client.health().state == HealthState::Down;
+        let delay = if down { retry } else { check.interval };
+        tokio::select! {
+            _ = cancel.cancelled() => return,
+            _ = tokio::time::sleep(delay) => {}
+        }
+
+        if down {
+            let result = tokio::select! {
+                _ = cancel.cancelled() => return,
+                result = with_timeout(check.timeout, reconnect(&client)) => result,
+            };
+            match result {
+                Ok(tools) => {
+                    retry = check.retry;
+                    client.update_health(|health| {
+                        health.state = HealthState::Up;
+                        health.latency = None;
+                        health.checked_at = Some(chrono::Local::now());
+                        health.failures = 0;
+                        health.last_error = None;
+                        health.reconnects += 1;
+                    });
+                    tracing::info!("Reconnected to MCP server '{}' ({} tools)", name, tools.len());
+                    if tx.send(AppMessage::McpReconnected(name.clone(), tools)).await.is_err() {
+                        return;
+                    }
+                }
+                Err(e) => {
+                    retry = (retry * 2).min(check.max_retry);
+                    record_failure(&client, &e, HealthState::Down);
+                    debug!("Reconnecting to MCP server '{}' failed: {:#}; retrying in {:?}", name, e, retry);
+                }
+            }
+            continue;
+        }
+
+        let result = tokio::select! {
+            _ = cancel.cancelled() => return,
+            result = with_timeout(check.timeout, client.ping()) => result,
+        };
+        match result {
+            Ok(latency) => {
+                let state = if latency > check.slow { HealthState::Degraded } else { HealthState::Up };
+                let previous = client.health().state;
+                client.update_health(|health| {
+                    health.state = state;
+                    health.latency = Some(latency);
+                    health.checked_at = Some(chrono::Local::now());
+                    health.failures = 0;
+                });
+                if state != previous {
+                    tracing::info!("MCP server '{}' is {} (ping {:?})", name, state.label(), latency);
+                }
+            }
+            Err(e) => {
+                let failures = client.health().failures + 1;
+                if failures >= FAILURES_BEFORE_DOWN {
+                    retry = check.retry;
+                    record_failure(&client, &e, HealthState::Down);
+                    tracing::warn!("MCP server '{}' is down: {:#}; reconnecting", name, e);
+                } else {
+                    record_failure(&client, &e, HealthState::Degraded);
+                    debug!("Ping to MCP server '{}' failed: {:#}", name, e);
+                }
+            }
+        }
+    }
+}
+
+/// Re-initialize a server and list its tools again
+async fn reconnect(client: &McpClient) -> Result<Vec<rmcp::model::Tool>> {
+    client.initialize().await?;
+    if client.supports_tools() {
+        client.list_tools().await
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+/// Run a check, failing if it takes longer than `timeout`
+async fn with_timeout<T>(timeout: Duration, check: impl Future<Output = Result<T>>) -> Result<T> {
+    tokio::time::timeout(timeout, check)
+        .await
+        .unwrap_or_else(|_| Err(anyhow::anyhow!("no answer within {:?}", timeout)))
+}
+
+/// Record a failed check and the state it leaves the server in
+fn record_failure(client: &McpClient, error: &anyhow::Error, state: HealthState) {
+    client.update_health(|health| {
+        health.state = state;
+        health.checked_at = Some(chrono::Local::now());
+        health.failures += 1;
+        health.last_error = Some(format!("{:#}", error));
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use crate::test_http;
+    use serde_json::json;
+
+    /// HTTP MCP server that answers while `alive` is set and fails with 503 otherwise
+    async fn flaky_server(alive: Arc<AtomicBool>) -> String {
+        let addr = test_http::serve(move |request| {
+            let alive = alive.load(Ordering::SeqCst);
+            async move {
+                let body = request.json();
+                let result = match body["method"].as_str() {
+                    Some("initialize") => json!({
+                        "protocolVersion": "2025-06-18",
+                        "capabilities": {"tools": {}},
+                        "serverInfo": {"name": "flaky", "version": "1"}
+                    }),
+                    Some("tools/list") => json!({"tools": [{"name": "echo", "inputSchema": {"type": "object"}}]}),
+                    _ => json!({}),
+                };
+                if !alive {
+                    "HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+                } else if body.get("id").is_none() {
+                    test_http::ACCEPTED.to_string()
+                } else {
+                    test_http::json(&json!({"jsonrpc": "2.0", "id": body["id"], "result": result}))
+                }
+            }
+        })
+        .await;
+        format!("http://{}/mcp", addr)
+    }
+
+    /// Wait until the client reaches a health state
+    async fn wait_for(client: &McpClient, state: HealthState) {
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while client.health().state != state {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("server never became {}", state.label()));
+    }
+
+    #[tokio::test]
+    async fn test_down_server_is_reconnected() {
+        let alive = Arc::new(AtomicBool::new(true));
+        let url = flaky_server(alive.clone()).await;
+        let client = McpClient::new("flaky", &url);
+        client.initialize().await.unwrap();
+        assert!(client.ping().await.is_ok());
+
+        let check = HealthCheck {
+            interval: Duration::from_millis(20),
+            timeout: Duration::from_secs(1),
+            slow: Duration::from_secs(1),
+            retry: Duration::from_millis(20),
+            max_retry: Duration::from_millis(80),
+        };
+        let (tx, mut rx) = mpsc::channel(4);
+        let cancel = CancellationToken::new();
+        let task = tokio::spawn(monitor(client.clone(), check, tx, cancel.clone()));
+
+        alive.store(false, Ordering::SeqCst);
+        wait_for(&client, HealthState::Down).await;
+        let health = client.health();
+        assert!(health.failures >= FAILURES_BEFORE_DOWN);
+        assert!(health.last_error.unwrap().contains("503"));
+
+        alive.store(true, Ordering::SeqCst);
+        match tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap() {
+            Some(AppMessage::McpReconnected(name, tools)) => {
+                assert_eq!(name, "flaky");
+                assert_eq!(tools.len(), 1);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+        let health = client.health();
+        assert_eq!(health.state, HealthState::Up);
+        assert_eq!(health.reconnects, 1);
+        assert_eq!(health.failures, 0);
+
+        cancel.cancel();
+        task.await.unwrap();
+    }
+}
@@ -0,0 +1,507 @@
+//! Inline images in the chat pane
+//!
+//! Image attachments from tool results (e.g. asset thumbnails) are drawn with the
+//! terminal's graphics protocol: Kitty, iTerm2 or Sixel. Every image is also
+//! rendered as half-block characters, which are used on other terminals and
+//! whenever the image is only partly scrolled into view.
+//!
+//! Graphics are written straight to the terminal after each frame, at the
+//! positions recorded while rendering. Their cells are marked as skipped so
+//! ratatui leaves them alone until the layout changes.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use clap::ValueEnum;
+use crossterm::{cursor::MoveTo, queue, style::Print};
+use image::{DynamicImage, ImageFormat, imageops::FilterType};
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::agent::ChatMessage;
+use crate::mcp::{Attachment, image_mime_type};
+
+/// Largest inline image, in terminal cells
+pub const MAX_IMAGE_WIDTH: u16 = 40;
+pub const MAX_IMAGE_HEIGHT: u16 = 12;
+
+/// Cell size assumed when the terminal does not report its pixel size
+const DEFAULT_CELL_PIXELS: (u16, u16) = (10, 20);
+
+/// Kitty graphics payloads are sent in chunks of this many base64 bytes
+const KITTY_CHUNK_SIZE: usize = 4096;
+
+/// How images are drawn in the terminal
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum GraphicsProtocol {
+    /// Kitty graphics protocol (Kitty, Ghostty)
+    Kitty,
+    /// iTerm2 inline images (iTerm2, WezTerm)
+    Iterm2,
+    /// DEC Sixel graphics (foot, mlterm, Windows Terminal, ...)
+    Sixel,
+    /// Colored `▀` characters, works everywhere
+    Halfblocks,
+}
+
+impl GraphicsProtocol {
+    /// Guess the protocol from the terminal's environment variables
+    pub fn detect() -> Self {
+        Self::detect_with(|name| std::env::var(name).ok())
+    }
+
+    fn detect_with(var: impl Fn(&str) -> Option<String>) -> Self {
+        let term = var("TERM").unwrap_or_default();
+        let term_program = var("TERM_PROGRAM").unwrap_or_default();
+
+        // tmux and screen swallow graphics escape sequences
+        if var("TMUX").is_some() || term.starts_with("screen") || term.starts_with("tmux") {
+            return GraphicsProtocol::Halfblocks;
+        }
+        if var("KITTY_WINDOW_ID").is_some() || term == "xterm-kitty" || term_program == "ghostty" {
+            GraphicsProtocol::Kitty
+        } else if term_program == "iTerm.app"
+            || term_program == "WezTerm"
+            || var("LC_TERMINAL").as_deref() == Some("iTerm2")
+        {
+            GraphicsProtocol::Iterm2
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || var("WT_SESSION").is_some()
+        {
+            GraphicsProtocol::Sixel
+        } else {
+            GraphicsProtocol::Halfblocks
+        }
+    }
+}
+
+/// An image prepared for the chat pane
+pub struct RenderedImage {
+    /// Size in cells
+    pub width: u16,
+    pub height: u16,
+    /// Half-block rendering, one line per row
+    pub lines: Vec<Line<'static>>,
+    /// Escape sequence drawing the image with the graphics protocol
+    sequence: Option<String>,
+    key: u64,
+}
+
+/// Where an image was drawn in the last frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Placement {
+    x: u16,
+    y: u16,
+    key: u64,
+}
+
+/// Draws chat images, keeping decoded images between frames
+pub struct ImageRenderer {
+    protocol: GraphicsProtocol,
+    /// Cell size in pixels, used for image aspect ratios
+    cell_pixels: (u16, u16),
+    /// Rendered images by attachment and width (`None` if decoding failed)
+    cache: RefCell<HashMap<u64, Option<Rc<RenderedImage>>>>,
+    /// Images placed while rendering the current frame
+    placements: RefCell<Vec<Placement>>,
+    /// Images currently drawn on the terminal, and the terminal size then
+    painted: (Vec<Placement>, (u16, u16)),
+}
+
+impl ImageRenderer {
+    /// Renderer for the given protocol, reading the cell size from the terminal
+    pub fn new(protocol: GraphicsProtocol) -> Self {
+        let cell_pixels = crossterm::terminal::window_size()
+            .ok()
+            .filter(|size| size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0)
+            .map(|size| (size.width / size.columns, size.height / size.rows))
+            .filter(|&(w, h)| w > 0 && h > 0)
+            .unwrap_or(DEFAULT_CELL_PIXELS);
+        Self {
+            protocol,
+            cell_pixels,
+            cache: RefCell::new(HashMap::new()),
+            placements: RefCell::new(Vec::new()),
+            painted: (Vec::new(), (0, 0)),
+        }
+    }
+
+    /// The protocol used to draw images
+    pub fn protocol(&self) -> GraphicsProtocol {
+        self.protocol
+    }
+
+    /// Prepare an image attachment, at most `max_width` cells wide
+    ///
+    /// Returns `None` if the image cannot be decoded.
+    pub fn render(&self, attachment: &Attachment, max_width: u16) -> Option<Rc<RenderedImage>> {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        attachment.data.hash(&mut hasher);
+        max_width.hash(&mut hasher);
+        let key = hasher.finish();
+
+        self.cache
+            .borrow_mut()
+            .entry(key)
+            .or_insert_with(|| match self.prepare(attachment, max_width, key) {
+                Ok(image) => Some(Rc::new(image)),
+                Err(e) => {
+                    tracing::warn!("Cannot display {} image: {:#}", attachment.mime_type, e);
+                    None
+                }
+            })
+            .clone()
+    }
+
+    fn prepare(&self, attachment: &Attachment, max_width: u16, key: u64) -> Result<RenderedImage> {
+        let image = decode(attachment)?;
+        let (width, height) = cell_size(
+            image.width(),
+            image.height(),
+            max_width.min(MAX_IMAGE_WIDTH),
+            MAX_IMAGE_HEIGHT,
+            self.cell_pixels,
+        );
+        let sequence = match self.protocol {
+            GraphicsProtocol::Halfblocks => None,
+            protocol => Some(escape_sequence(protocol, &image, width, height, self.cell_pixels)?),
+        };
+        Ok(RenderedImage {
+            width,
+            height,
+            lines: half_blocks(&image, width, height),
+            sequence,
+            key,
+        })
+    }
+
+    /// Draw `image` at a screen position after this frame (ignored for half-blocks)
+    pub fn place(&self, image: &RenderedImage, x: u16, y: u16) {
+        if image.sequence.is_some() {
+            self.placements.borrow_mut().push(Placement { x, y, key: image.key });
+        }
+    }
+
+    /// Write the images placed in the last frame to the terminal
+    ///
+    /// Nothing is written if the same images are already on screen.
+    pub fn paint(&mut self, out: &mut impl Write, screen: (u16, u16)) -> Result<()> {
+        let placements = std::mem::take(&mut *self.placements.borrow_mut());
+        if self.painted.0 == placements && self.painted.1 == screen {
+            return Ok(());
+        }
+
+        // Kitty images stay on screen until deleted; the other protocols are
+        // overwritten when ratatui redraws the cells
+        if self.protocol == GraphicsProtocol::Kitty && !self.painted.0.is_empty() {
+            queue!(out, Print("\x1b_Ga=d,d=A,q=2\x1b\\"))?;
+        }
+        let cache = self.cache.borrow();
+        for placement in &placements {
+            let sequence = cache
+                .values()
+                .flatten()
+                .find(|image| image.key == placement.key)
+                .and_then(|image| image.sequence.as_deref());
+            if let Some(sequence) = sequence {
+                queue!(out, MoveTo(placement.x, placement.y), Print(sequence))?;
+            }
+        }
+        out.flush()?;
+
+        self.painted = (placements, screen);
+        Ok(())
+    }
+}
+
+/// Decode an image attachment
+pub fn decode(attachment: &Attachment) -> Result<DynamicImage> {
+    let bytes = attachment.bytes()?;
+    image::load_from_memory(&bytes).context("Unsupported or corrupt image")
+}
+
+/// Image attachments in a chat history, in the order they are numbered in the chat
+pub fn image_attachments(messages: &[ChatMessage]) -> Vec<&Attachment> {
+    messages
+        .iter()
+        .flat_map(|msg| &msg.attachments)
+        .filter(|attachment| attachment.is_image())
+        .collect()
+}
+
+/// Write image number `number` to `path`, or to `image-<number>.<ext>` in the
+/// current directory (or in `path`, if it is a directory)
+pub fn save_image(attachment: &Attachment, number: usize, path: Option<&Path>) -> Result<PathBuf> {
+    let file_name = format!("image-{}.{}", number, extension_for(&attachment.mime_type));
+    let path = match path {
+        Some(path) if path.is_dir() => path.join(file_name),
+        Some(path) => path.to_path_buf(),
+        None => PathBuf::from(file_name),
+    };
+    std::fs::write(&path, attachment.bytes()?)
+        .with_context(|| format!("Failed to write {}", path.display()))?;
+    Ok(path)
+}
+
+/// Read an image file to attach to a message
+pub fn load_image(path: &Path) -> Result<Attachment> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read {}", path.display()))?;
+    let data = BASE64.encode(&bytes);
+    let mime_type = image_mime_type(&data)
+        .with_context(|| format!("{} is not a PNG, JPEG, GIF or WebP image", path.display()))?;
+    Ok(Attachment {
+        mime_type: mime_type.to_string(),
+        data,
+        uri: None,
+    })
+}
+
+/// File extension for an image MIME type
+fn extension_for(mime_type: &str) -> &'static str {
+    match mime_type {
+        "image/jpeg" | "image/jpg" => "jpg",
+        "image/gif" => "gif",
+        "image/webp" => "webp",
+        _ => "png",
+    }
+}
+
+/// Size in cells of an image that fits in `max_width` x `max_height` cells
+fn cell_size(width: u32, height: u32, max_width: u16, max_height: u16, cell_pixels: (u16, u16)) -> (u16, u16) {
+    let (cell_w, cell_h) = (f64::from(cell_pixels.0), f64::from(cell_pixels.1));
+    // Image size in cells at its natural size, capped to the available area
+    let cols = (f64::from(width) / cell_w).max(1.0);
+    let rows = (f64::from(height) / cell_h).max(1.0);
+    let scale = (f64::from(max_width) / cols)
+        .min(f64::from(max_height) / rows)
+        .min(1.0);
+    (
+        ((cols * scale).round() as u16).clamp(1, max_width.max(1)),
+        ((rows * scale).round() as u16).clamp(1, max_height.max(1)),
+    )
+}
+
+/// Render an image as `▀` characters: each cell shows two pixels, the upper one
+/// as the foreground and the lower one as the background color
+fn half_blocks(image: &DynamicImage, width: u16, height: u16) -> Vec<Line<'static>> {
+    let pixels = image
+        .resize_exact(u32::from(width), u32::from(height) * 2, FilterType::Triangle)
+        .to_rgba8();
+    // Transparent pixels are blended onto the black chat background
+    let color = |x: u32, y: u32| {
+        let [r, g, b, a] = pixels.get_pixel(x, y).0;
+        let blend = |c: u8| (u16::from(c) * u16::from(a) / 255) as u8;
+        Color::Rgb(blend(r), blend(g), blend(b))
+    };
+
+    (0..u32::from(height))
+        .map(|row| {
+            Line::from(
+                (0..u32::from(width))
+                    .map(|x| {
+                        Span::styled(
+                            "▀",
+                            Style::default().fg(color(x, row * 2)).bg(color(x, row * 2 + 1)),
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+        })
+        .collect()
+}
+
+/// Escape sequence drawing an image over `width` x `height` cells at the cursor
+fn escape_sequence(
+    protocol: GraphicsProtocol,
+    image: &DynamicImage,
+    width: u16,
+    height: u16,
+    cell_pixels: (u16, u16),
+) -> Result<String> {
+    let pixel_width = u32::from(width) * u32::from(cell_pixels.0);
+    let pixel_height = u32::from(height) * u32::from(cell_pixels.1);
+    let image = image.resize(pixel_width, pixel_height, FilterType::Triangle);
+
+    match protocol {
+        GraphicsProtocol::Kitty => {
+            let data = BASE64.encode(png_bytes(&image)?);
+            let chunks: Vec<&[u8]> = data.as_bytes().chunks(KITTY_CHUNK_SIZE).collect();
+            let mut sequence = String::new();
+            for (i, chunk) in chunks.iter().enumerate() {
+                let more = u8::from(i + 1 < chunks.len());
+                let chunk = std::str::from_utf8(chunk)?;
+                if i == 0 {
+                    // a=T: transmit and display, C=1: do not move the cursor
+                    sequence.push_str(&format!(
+                        "\x1b_Gf=100,a=T,C=1,q=2,c={},r={},m={};{}\x1b\\",
+                        width, height, more, chunk
+                    ));
+                } else {
+                    sequence.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+                }
+            }
+            Ok(sequence)
+        }
+        GraphicsProtocol::Iterm2 => {
+            let png = png_bytes(&image)?;
+            Ok(format!(
+                "\x1b]1337;File=inline=1;size={};width={};height={};preserveAspectRatio=1:{}\x07",
+                png.len(),
+                width,
+                height,
+                BASE64.encode(&png)
+            ))
+        }
+        GraphicsProtocol::Sixel => {
+            let rgba = image.to_rgba8();
+            icy_sixel::sixel_encode(
+                rgba.as_raw(),
+                rgba.width() as usize,
+                rgba.height() as usize,
+                &icy_sixel::EncodeOptions::default(),
+            )
+            .map_err(|e| anyhow::anyhow!("Sixel encoding failed: {}", e))
+        }
+        GraphicsProtocol::Halfblocks => Ok(String::new()),
+    }
+}
+
+/// Encode an image as PNG
+fn png_bytes(image: &DynamicImage) -> Result<Vec<u8>> {
+    let mut png = Vec::new();
+    image
+        .write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .context("PNG encoding failed")?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A 2x2 PNG: red and green on top, blue and white below
+    fn test_image() -> Attachment {
+        let mut image = image::RgbaImage::new(2, 2);
+        image.put_pixel(0, 0, image::Rgba([255, 0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgba([0, 255, 0, 255]));
+        image.put_pixel(0, 1, image::Rgba([0, 0, 255, 255]));
+        image.put_pixel(1, 1, image::Rgba([255, 255, 255, 255]));
+        let png = png_bytes(&DynamicImage::ImageRgba8(image)).unwrap();
+        Attachment {
+            mime_type: "image/png".to_string(),
+            data: BASE64.encode(png),
+            uri: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_protocol() {
+        let detect = |vars: &[(&str, &str)]| {
+            GraphicsProtocol::detect_with(|name| {
+                vars.iter().find(|(k, _)| *k == name).map(|(_, v)| v.to_string())
+            })
+        };
+        assert_eq!(detect(&[("TERM", "xterm-kitty")]), GraphicsProtocol::Kitty);
+        assert_eq!(detect(&[("TERM_PROGRAM", "iTerm.app")]), GraphicsProtocol::Iterm2);
+        assert_eq!(detect(&[("TERM", "foot")]), GraphicsProtocol::Sixel);
+        assert_eq!(detect(&[("TERM", "xterm-256color")]), GraphicsProtocol::Halfblocks);
+        assert_eq!(
+            detect(&[("TERM", "xterm-kitty"), ("TMUX", "/tmp/tmux")]),
+            GraphicsProtocol::Halfblocks
+        );
+    }
+
+    #[test]
+    fn test_half_blocks() {
+        let image = decode(&test_image()).unwrap();
+        let lines = half_blocks(&image, 2, 1);
+
+        assert_eq!(lines.len(), 1);
+        let first = &lines[0].spans[0];
+        assert_eq!(first.content, "▀");
+        assert_eq!(first.style.fg, Some(Color::Rgb(255, 0, 0)));
+        assert_eq!(first.style.bg, Some(Color::Rgb(0, 0, 255)));
+        assert_eq!(lines[0].spans[1].style.bg, Some(Color::Rgb(255, 255, 255)));
+    }
+
+    #[test]
+    fn test_cell_size_keeps_aspect_ratio() {
+        // 400x200 pixels at 10x20 pixels per cell is 40x10 cells
+        assert_eq!(cell_size(400, 200, 40, 12, (10, 20)), (40, 10));
+        // Scaled down to fit the width
+        assert_eq!(cell_size(800, 400, 40, 12, (10, 20)), (40, 10));
+        // Scaled down to fit the height
+        assert_eq!(cell_size(200, 480, 40, 12, (10, 20)), (10, 12));
+        // Small images are not enlarged
+        assert_eq!(cell_size(2, 2, 40, 12, (10, 20)), (1, 1));
+    }
+
+    #[test]
+    fn test_escape_sequences() {
+        let image = decode(&test_image()).unwrap();
+
+        let kitty = escape_sequence(GraphicsProtocol::Kitty, &image, 4, 2, (10, 20)).unwrap();
+        assert!(kitty.starts_with("\x1b_Gf=100,a=T,C=1,q=2,c=4,r=2,m=0;"));
+        assert!(kitty.ends_with("\x1b\\"));
+
+        let iterm = escape_sequence(GraphicsProtocol::Iterm2, &image, 4, 2, (10, 20)).unwrap();
+        assert!(iterm.starts_with("\x1b]1337;File=inline=1;"));
+        assert!(iterm.contains("width=4;height=2"));
+
+        let sixel = escape_sequence(GraphicsProtocol::Sixel, &image, 4, 2, (10, 20)).unwrap();
+        assert!(sixel.starts_with("\x1bP"));
+    }
+
+    #[test]
+    fn test_save_image() {
+        let dir = std::env::temp_dir().join(format!("pcli2-rig-images-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let attachment = test_image();
+
+        let path = save_image(&attachment, 3, Some(&dir)).unwrap();
+        assert_eq!(path, dir.join("image-3.png"));
+        assert_eq!(std::fs::read(&path).unwrap(), attachment.bytes().unwrap());
+
+        let named = save_image(&attachment, 3, Some(&dir.join("thumb.png"))).unwrap();
+        assert!(named.ends_with("thumb.png"));
+        assert!(named.exists());
+
+        assert_eq!(load_image(&named).unwrap(), attachment);
+        std::fs::write(dir.join("notes.txt"), "not an image").unwrap();
+        assert!(load_image(&dir.join("notes.txt")).is_err());
+    }
+
+    #[test]
+    fn test_paint_skips_unchanged_frames() {
+        let mut renderer = ImageRenderer::new(GraphicsProtocol::Kitty);
+        let image = renderer.render(&test_image(), 10).unwrap();
+
+        let mut out = Vec::new();
+        renderer.place(&image, 2, 3);
+        renderer.paint(&mut out, (80, 24)).unwrap();
+        assert!(String::from_utf8_lossy(&out).contains("\x1b_Gf=100"));
+
+        out.clear();
+        renderer.place(&image, 2, 3);
+        renderer.paint(&mut out, (80, 24)).unwrap();
+        assert!(out.is_empty());
+
+        // The image scrolled out of view: it is deleted
+        renderer.paint(&mut out, (80, 24)).unwrap();
+        assert_eq!(String::from_utf8_lossy(&out), "\x1b_Ga=d,d=A,q=2\x1b\\");
+    }
+}
@@ -6,20 +6,32 @@
 //! - Tool calling with confirmation (and --yolo mode)
 //! - Ollama integration for local LLM inference
 
-use anyhow::Result;
-use clap::Parser;
+use anyhow::{Context, Result};
+use clap::{Parser, Subcommand};
 use serde_json::Value;
 use std::sync::{Arc, Mutex};
 use tracing_subscriber::{EnvFilter, fmt, prelude::*};
 
 use app::{App, LOG_BUFFER};
 use config::{Config, McpServerConfig};
+use export::ExportFormat;
+use session::SessionStore;
 use tui::Tui;
 
 mod agent;
 mod app;
+mod approval;
 mod config;
 mod error;
+mod export;
+mod health;
+mod images;
+mod mcp;
+mod models;
+mod session;
+#[cfg(test)]
+mod test_http;
+mod tool_selection;
 mod tools;
 mod tui;
 mod ui;
@@ -33,19 +45,22 @@ struct Args {
     #[arg(short, long, env = "OLLAMA_MODEL")]
     model: Option<String>,
 
-    /// Ollama server URL
-    #[arg(
-        short = 'H',
-        long,
-        env = "OLLAMA_HOST",
-        default_value = "http://localhost:11434"
-    )]
-    host: String,
+    /// Ollama server URL [default: http://localhost:11434]
+    #[arg(short = 'H', long, env = "OLLAMA_HOST")]
+    host: Option<String>,
 
     /// YOLO mode: skip confirmation for destructive tools
     #[arg(long, default_value = "false")]
     yolo: bool,
 
+    /// How to draw images: kitty, iterm2, sixel or halfblocks [default: detected]
+    #[arg(long, value_enum, value_name = "PROTOCOL")]
+    graphics: Option<images::GraphicsProtocol>,
+
+    /// Advertise only the tools most relevant to each message (see [tool_selection])
+    #[arg(long, default_value = "false")]
+    tool_selection: bool,
+
     /// Enable verbose logging
     #[arg(short, long, default_value = "false")]
     verbose: bool,
@@ -58,10 +73,38 @@ struct Args {
     #[arg(long, value_name = "URL")]
     mcp_remote: Vec<String>,
 
+    /// Resume a saved session by id or name (lists sessions if no id is given)
+    #[arg(long, value_name = "ID", num_args = 0..=1, conflicts_with = "continue_session")]
+    resume: Option<Option<String>>,
+
+    /// Resume the most recent session
+    #[arg(short = 'c', long = "continue")]
+    continue_session: bool,
+
     /// Configure MCP servers from pcli2-mcp and save to config file (one-time setup)
     /// This will read the pcli2-mcp config and save it to ~/.config/pcli2-rig/config.toml
     #[arg(long, value_name = "FILE")]
     setup_mcp: Option<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export a saved session to Markdown, JSON or HTML
+    Export {
+        /// Session id, id prefix or name
+        session: String,
+
+        /// Output file (prints to stdout if omitted)
+        #[arg(short, long, value_name = "FILE")]
+        output: Option<std::path::PathBuf>,
+
+        /// Output format [default: from the output file extension, else markdown]
+        #[arg(short, long, value_enum)]
+        format: Option<ExportFormat>,
+    },
 }
 
 #[tokio::main]
@@ -73,6 +116,15 @@ async fn main() -> Result<()> {
         return setup_mcp_config(config_path);
     }
 
+    if let Some(Command::Export {
+        session,
+        output,
+        format,
+    }) = &args.command
+    {
+        return export_session(session, output.as_deref(), *format);
+    }
+
     // Initialize logging to file and shared buffer
     let filter = if args.verbose {
         EnvFilter::new("debug")
@@ -113,16 +165,50 @@ async fn main() -> Result<()> {
     tracing::debug!("Starting PCLI2-RIG with model: {}", args.model.as_deref().unwrap_or("config default"));
 
     // Load configuration from file (if exists)
-    let mut config = Config::load();
+    let mut config = Config::load()?;
+
+    // Load the session to resume, if any
+    let store = SessionStore::default_location();
+    let resumed = match (&args.resume, args.continue_session) {
+        (Some(Some(id)), _) => Some(
+            store
+                .as_ref()
+                .context("Could not determine the sessions directory")?
+                .load(id)?,
+        ),
+        (Some(None), _) => return print_sessions(store.as_ref()),
+        (None, true) => match store.as_ref().map(SessionStore::latest).transpose()?.flatten() {
+            Some(session) => Some(session),
+            None => anyhow::bail!("No saved sessions to continue"),
+        },
+        (None, false) => None,
+    };
+
+    // A resumed session keeps its model unless one is given explicitly
+    if let Some(session) = &resumed
+        && args.model.is_none()
+    {
+        config.model = session.info.model.clone();
+    }
 
     // Override with CLI arguments only if explicitly provided
     if let Some(model) = args.model {
         config.model = model;
     }
-    config.host = args.host.clone();
-    config.yolo = args.yolo;
+    if let Some(host) = args.host {
+        config.host = host;
+    }
+    if args.yolo {
+        config.yolo = true;
+    }
+    if let Some(graphics) = args.graphics {
+        config.graphics = Some(graphics);
+    }
+    if args.tool_selection {
+        config.tool_selection.enabled = true;
+    }
 
-    tracing::info!("Using model: {}", config.model);
+    tracing::info!("Using model: {} on {}", config.model, config.ollama_url());
 
     // Parse MCP configuration
     let mut mcp_servers = Vec::new();
@@ -149,12 +235,10 @@ async fn main() -> Result<()> {
 
     // Add direct MCP remote URLs
     for url in &args.mcp_remote {
-        mcp_servers.push(McpServerConfig {
-            name: format!("remote-{}", mcp_servers.len()),
-            url: url.clone(),
-            token: None,
-            enabled: true,
-        });
+        mcp_servers.push(McpServerConfig::http(
+            format!("remote-{}", mcp_servers.len()),
+            url.clone(),
+        ));
     }
 
     // If MCP servers were provided via CLI, use them; otherwise keep loaded config
@@ -164,6 +248,9 @@ async fn main() -> Result<()> {
 
     // Create the application
     let mut app = App::new(config);
+    if let Some(session) = resumed {
+        app.resume(session);
+    }
 
     // Create and run the TUI
     let mut tui = Tui::new()?;
@@ -183,6 +270,59 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
+/// Export a saved session (`pcli2-rig export <session>`)
+fn export_session(
+    query: &str,
+    output: Option<&std::path::Path>,
+    format: Option<ExportFormat>,
+) -> Result<()> {
+    let store = SessionStore::default_location().context("Could not determine the sessions directory")?;
+    let session = store.load(query)?;
+
+    match output {
+        Some(path) => {
+            let format = export::write_file(path, format, &session.info, &session.messages)?;
+            eprintln!(
+                "Exported session {} ({} messages) as {:?} to {}",
+                session.info.label(),
+                session.messages.len(),
+                format,
+                path.display()
+            );
+        }
+        None => {
+            let format = format.unwrap_or(ExportFormat::Markdown);
+            print!("{}", export::render(format, &session.info, &session.messages)?);
+        }
+    }
+    Ok(())
+}
+
+/// Print saved sessions for `--resume` without an id
+fn print_sessions(store: Option<&SessionStore>) -> Result<()> {
+    let sessions = match store {
+        Some(store) => store.list()?,
+        None => Vec::new(),
+    };
+    if sessions.is_empty() {
+        println!("No saved sessions.");
+        return Ok(());
+    }
+
+    for summary in &sessions {
+        println!(
+            "{}  {:<20}  {:>4} messages  {}  {}",
+            summary.info.id,
+            summary.info.name.as_deref().unwrap_or("-"),
+            summary.message_count,
+            summary.info.model,
+            summary.info.updated_at.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M")
+        );
+    }
+    println!("\nResume one with: pcli2-rig --resume <id>");
+    Ok(())
+}
+
 /// Custom writer that writes to both a file and a shared buffer
 #[derive(Clone)]
 struct DualWriter {
@@ -266,32 +406,62 @@ impl<'a> tracing_subscriber::fmt::writer::MakeWriter<'a> for DualWriter {
 ///     }
 ///   }
 /// }
+///
+/// Entries bridged through `mcp-remote` (to the HTTP URL in its `args`) or with a `url`
+/// are connected to directly over HTTP; other `command` entries are kept as
+/// stdio servers with their `args`, `env` and `cwd`.
 fn parse_mcp_config(json: &str) -> Result<Vec<McpServerConfig>> {
     let value: Value = serde_json::from_str(json)?;
     let mut servers = Vec::new();
 
     if let Some(mcp_servers) = value.get("mcpServers").and_then(|v| v.as_object()) {
         for (name, config) in mcp_servers {
-            // Extract URL from args (look for http:// or https:// URLs)
-            let mut url = None;
-            if let Some(args) = config.get("args").and_then(|a| a.as_array()) {
-                for arg in args {
-                    if let Some(arg_str) = arg.as_str()
-                        && (arg_str.starts_with("http://") || arg_str.starts_with("https://")) {
-                            url = Some(arg_str.to_string());
-                            break;
-                        }
-                }
-            }
+            let args: Vec<String> = config
+                .get("args")
+                .and_then(|a| a.as_array())
+                .map(|args| args.iter().filter_map(|a| a.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+
+            // Servers bridged through mcp-remote are reached at the URL in its args;
+            // other commands may take URLs of their own and stay stdio servers
+            let command = config.get("command").and_then(|c| c.as_str());
+            let is_mcp_remote = |arg: &str| {
+                let arg = arg.rsplit('/').next().unwrap_or(arg);
+                arg == "mcp-remote" || arg.starts_with("mcp-remote@")
+            };
+            let bridged = command.is_some_and(is_mcp_remote) || args.iter().any(|arg| is_mcp_remote(arg));
+            let url = config
+                .get("url")
+                .and_then(|u| u.as_str())
+                .map(str::to_string)
+                .or_else(|| {
+                    if !bridged {
+                        return None;
+                    }
+                    args.iter()
+                        .find(|arg| arg.starts_with("http://") || arg.starts_with("https://"))
+                        .cloned()
+                });
 
             if let Some(server_url) = url {
                 tracing::debug!("Parsed MCP server: {} -> {}", name, server_url);
-                servers.push(McpServerConfig {
-                    name: name.clone(),
-                    url: server_url,
-                    token: None,
-                    enabled: true,
-                });
+                servers.push(McpServerConfig::http(name.clone(), server_url));
+            } else if let Some(command) = command {
+                let mut server = McpServerConfig::stdio(name.clone(), command.to_string(), args);
+                server.cwd = config
+                    .get("cwd")
+                    .and_then(|c| c.as_str())
+                    .map(std::path::PathBuf::from);
+                if let Some(env) = config.get("env").and_then(|e| e.as_object()) {
+                    server.env = env
+                        .iter()
+                        .filter_map(|(key, value)| Some((key.clone(), value.as_str()?.to_string())))
+                        .collect();
+                }
+                tracing::debug!("Parsed stdio MCP server: {} -> {}", name, server.endpoint());
+                servers.push(server);
+            } else {
+                tracing::warn!("Skipping MCP server '{}': no url or command", name);
             }
         }
     }
@@ -352,7 +522,7 @@ fn setup_mcp_config(config_path: &str) -> Result<()> {
     println!();
     println!("Configured {} MCP server(s):", mcp_servers.len());
     for server in &mcp_servers {
-        println!("  • {} → {}", server.name, server.url);
+        println!("  • {} → {}", server.name, server.endpoint());
     }
     println!();
     println!("You can now run: pcli2-rig");
@@ -361,3 +531,41 @@ fn setup_mcp_config(config_path: &str) -> Result<()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_mcp_config_keeps_stdio_servers() {
+        let servers = parse_mcp_config(
+            r#"{
+  "mcpServers": {
+    "pcli2": { "command": "npx", "args": ["-y", "mcp-remote", "http://localhost:8080/mcp"] },
+    "filesystem": {
+      "command": "npx",
+      "args": ["-y", "@modelcontextprotocol/server-filesystem", "/home/me"],
+      "env": { "DEBUG": "1" },
+      "cwd": "/home/me"
+    },
+    "remote": { "url": "https://example.com/mcp" },
+    "grafana": { "command": "mcp-grafana", "args": ["--url", "http://localhost:3000"] }
+  }
+}"#,
+        )
+        .unwrap();
+
+        let find = |name: &str| servers.iter().find(|s| s.name == name).unwrap();
+        assert_eq!(servers.len(), 4);
+        assert_eq!(find("pcli2").url.as_deref(), Some("http://localhost:8080/mcp"));
+        assert_eq!(find("pcli2").command, None);
+        assert_eq!(find("filesystem").command.as_deref(), Some("npx"));
+        assert_eq!(find("filesystem").args.len(), 3);
+        assert_eq!(find("filesystem").env.get("DEBUG").map(String::as_str), Some("1"));
+        assert_eq!(find("remote").url.as_deref(), Some("https://example.com/mcp"));
+        // A URL argument alone does not make a server remote
+        assert_eq!(find("grafana").url, None);
+        assert_eq!(find("grafana").command.as_deref(), Some("mcp-grafana"));
+        assert_eq!(find("grafana").args, ["--url", "http://localhost:3000"]);
+    }
+}
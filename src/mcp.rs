@@ -0,0 +1,2240 @@
+//! MCP client
+//!
+//! Speaks JSON-RPC to MCP servers over one of two transports:
+//!
+//! - Streamable HTTP: every message is POSTed to the server URL, and the reply is
+//!   either a JSON body or an SSE stream that ends with the response. The
+//!   `Mcp-Session-Id` assigned at initialization is sent with every later request
+//!   and the session is deleted on shutdown.
+//! - stdio: the server runs as a child process and exchanges newline-delimited
+//!   messages over stdin/stdout. If it exits, it is restarted on the next request.
+//!
+//! The client asks for the newest protocol revision it supports and accepts any
+//! revision in [`PROTOCOL_VERSIONS`]. The server's [`InitializeResult`] (version,
+//! server info and capabilities) is kept for the UI and capability checks.
+//!
+//! Besides tools, servers can offer resources (read by URI, and mentioned in
+//! chat as `@server:uri`) and prompts (run as `/server:prompt` commands).
+//!
+//! Each client keeps the [`ServerHealth`] recorded by the health monitor (see
+//! [`crate::health`]).
+//!
+//! Server notifications arrive on the stdio server's stdout, on SSE replies, and
+//! on the HTTP GET stream opened for servers that announce list changes or
+//! logging: tool list changes are flagged for the app to pick up, progress is
+//! kept per running tool call, and log messages go to the logs pane.
+
+use std::collections::{HashMap, VecDeque};
+use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use rmcp::model::{
+    CallToolResult, GetPromptResult, InitializeResult, LoggingLevel, LoggingMessageNotificationParam,
+    ProgressNotificationParam, Prompt, PromptMessageContent, RawContent, ReadResourceResult, Resource,
+    ResourceContents, ResourceTemplate, ServerCapabilities,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+use tokio::sync::{Semaphore, oneshot};
+use tracing::debug;
+
+use crate::config::McpServerConfig;
+
+/// Header carrying the session id assigned by the server
+const SESSION_HEADER: &str = "Mcp-Session-Id";
+
+/// Header carrying the negotiated protocol version on HTTP requests after initialization
+const PROTOCOL_VERSION_HEADER: &str = "MCP-Protocol-Version";
+
+/// Protocol revisions we support, newest first (the first one is requested)
+pub const PROTOCOL_VERSIONS: &[&str] = &["2025-11-25", "2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// How long a stdio server gets to exit after its stdin is closed
+const STDIO_EXIT_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Time allowed for connecting to an HTTP server; requests themselves may run
+/// long, so callers bound them as needed
+const HTTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long closing a session may take, so quitting never hangs on a server
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How an MCP server is doing, as seen by the health monitor
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum HealthState {
+    /// Answering pings in time
+    #[default]
+    Up,
+    /// Slow to answer, or the last ping failed
+    Degraded,
+    /// Not answering; being reconnected with backoff
+    Down,
+}
+
+impl HealthState {
+    /// Lowercase name for messages
+    pub fn label(&self) -> &'static str {
+        match self {
+            HealthState::Up => "up",
+            HealthState::Degraded => "degraded",
+            HealthState::Down => "down",
+        }
+    }
+}
+
+/// Results of the latest health checks of a server
+#[derive(Debug, Clone, Default)]
+pub struct ServerHealth {
+    pub state: HealthState,
+    /// Round trip of the last answered ping
+    pub latency: Option<Duration>,
+    /// When the server was last checked
+    pub checked_at: Option<chrono::DateTime<chrono::Local>>,
+    /// Consecutive failed checks
+    pub failures: u32,
+    /// Error of the last failed check
+    pub last_error: Option<String>,
+    /// Times the health monitor reconnected the server
+    pub reconnects: u32,
+}
+
+/// Progress of a running tool call, as reported by the server
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolProgress {
+    pub tool: String,
+    /// Progress so far (`None` until the server reports any)
+    pub progress: Option<f64>,
+    pub total: Option<f64>,
+    pub message: Option<String>,
+}
+
+impl ToolProgress {
+    /// Fraction done, if the server gave a total
+    pub fn fraction(&self) -> Option<f64> {
+        let total = self.total.filter(|total| *total > 0.0)?;
+        Some((self.progress? / total).clamp(0.0, 1.0))
+    }
+}
+
+/// State changed by a server's notifications, shared with the transport's reader tasks
+#[derive(Default)]
+struct ServerEvents {
+    /// Set by `notifications/tools/list_changed` until taken
+    tools_changed: AtomicBool,
+    /// Running tool calls, by progress token
+    progress: Mutex<HashMap<String, ToolProgress>>,
+}
+
+/// Tracks a tool call's progress until the call ends (or is dropped)
+struct ProgressGuard<'a> {
+    events: &'a ServerEvents,
+    token: String,
+}
+
+impl<'a> ProgressGuard<'a> {
+    fn new(events: &'a ServerEvents, token: &Value, tool: &str) -> Self {
+        let token = token.to_string();
+        if let Ok(mut progress) = events.progress.lock() {
+            progress.insert(
+                token.clone(),
+                ToolProgress {
+                    tool: tool.to_string(),
+                    progress: None,
+                    total: None,
+                    message: None,
+                },
+            );
+        }
+        Self { events, token }
+    }
+}
+
+impl Drop for ProgressGuard<'_> {
+    fn drop(&mut self) {
+        if let Ok(mut progress) = self.events.progress.lock() {
+            progress.remove(&self.token);
+        }
+    }
+}
+
+/// MCP client for a single server
+#[derive(Clone)]
+pub struct McpClient {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for McpClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("McpClient").field("name", &self.inner.name).finish_non_exhaustive()
+    }
+}
+
+struct Inner {
+    name: String,
+    transport: Transport,
+    /// Next JSON-RPC request id
+    next_id: AtomicU64,
+    /// Limits concurrent tool calls to the server
+    tool_calls: Semaphore,
+    /// The server's reply to `initialize`
+    server_info: Mutex<Option<InitializeResult>>,
+    /// Latest health check results
+    health: Mutex<ServerHealth>,
+    /// State changed by the server's notifications
+    events: Arc<ServerEvents>,
+}
+
+enum Transport {
+    Http(Arc<HttpTransport>),
+    Stdio(Box<StdioTransport>),
+}
+
+struct HttpTransport {
+    url: String,
+    http: reqwest::Client,
+    /// Session id from the server's initialize response, if it assigned one
+    session_id: Mutex<Option<String>>,
+    /// Negotiated protocol version, sent with every request after initialization
+    protocol_version: Mutex<Option<String>>,
+    /// Task reading the GET stream of server-initiated messages
+    listener: Mutex<Option<tokio::task::JoinHandle<()>>>,
+}
+
+/// A server launched as a child process
+struct StdioTransport {
+    config: McpServerConfig,
+    /// The running process (`None` before initialization and after it exits)
+    process: tokio::sync::Mutex<Option<Arc<StdioProcess>>>,
+    /// Held while restarting, so concurrent requests restart the server only once
+    restart: tokio::sync::Mutex<()>,
+}
+
+/// A stdio server's stdin, shared by requests and the reader task
+type ServerStdin = Arc<tokio::sync::Mutex<Option<ChildStdin>>>;
+
+/// Requests awaiting a reply, by id (`None` once the server's stdout has closed)
+type PendingReplies = Arc<Mutex<Option<HashMap<u64, oneshot::Sender<Value>>>>>;
+
+/// A running stdio server
+///
+/// A reader task routes replies from stdout to the waiting requests by id, so
+/// several requests can be in flight at once.
+struct StdioProcess {
+    child: tokio::sync::Mutex<Child>,
+    /// Taken (closed) when the server is stopped
+    stdin: ServerStdin,
+    pending: PendingReplies,
+}
+
+impl McpClient {
+    /// Client for a Streamable HTTP server, without auth
+    #[cfg(test)]
+    pub fn new(name: &str, url: &str) -> Self {
+        Self::with_transport(
+            name,
+            Transport::Http(Arc::new(HttpTransport {
+                url: url.to_string(),
+                http: reqwest::Client::new(),
+                session_id: Mutex::new(None),
+                protocol_version: Mutex::new(None),
+                listener: Mutex::new(None),
+            })),
+            crate::config::DEFAULT_MCP_CONCURRENCY,
+        )
+    }
+
+    /// Client for a configured server, using stdio if it has a `command`
+    ///
+    /// HTTP clients send the configured `headers` and the resolved token (as
+    /// `Authorization: Bearer`) with every request.
+    pub fn from_config(server: &McpServerConfig) -> Result<Self> {
+        match (&server.url, &server.command) {
+            (Some(_), Some(_)) => anyhow::bail!("set either url or command, not both"),
+            (Some(url), None) => {
+                let headers = http_headers(server)?;
+                let http = reqwest::Client::builder()
+                    .default_headers(headers)
+                    .connect_timeout(HTTP_CONNECT_TIMEOUT)
+                    .build()
+                    .context("Failed to create HTTP client")?;
+                Ok(Self::with_transport(
+                    &server.name,
+                    Transport::Http(Arc::new(HttpTransport {
+                        url: url.clone(),
+                        http,
+                        session_id: Mutex::new(None),
+                        protocol_version: Mutex::new(None),
+                        listener: Mutex::new(None),
+                    })),
+                    server.max_concurrency,
+                ))
+            }
+            (None, Some(_)) => Ok(Self::with_transport(
+                &server.name,
+                Transport::Stdio(Box::new(StdioTransport {
+                    config: server.clone(),
+                    process: tokio::sync::Mutex::new(None),
+                    restart: tokio::sync::Mutex::new(()),
+                })),
+                server.max_concurrency,
+            )),
+            (None, None) => anyhow::bail!("no url or command configured"),
+        }
+    }
+
+    fn with_transport(name: &str, transport: Transport, max_concurrency: usize) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                name: name.to_string(),
+                transport,
+                next_id: AtomicU64::new(1),
+                tool_calls: Semaphore::new(max_concurrency.max(1)),
+                server_info: Mutex::new(None),
+                health: Mutex::new(ServerHealth::default()),
+                events: Arc::new(ServerEvents::default()),
+            }),
+        }
+    }
+
+    /// Server name from the config
+    pub fn name(&self) -> &str {
+        &self.inner.name
+    }
+
+    /// The server's reply to `initialize` (`None` before initialization)
+    pub fn server_info(&self) -> Option<InitializeResult> {
+        self.inner.server_info.lock().ok().and_then(|info| info.clone())
+    }
+
+    /// Whether both clients share one connection
+    pub fn same_connection(&self, other: &McpClient) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+
+    /// Latest health check results
+    pub fn health(&self) -> ServerHealth {
+        self.inner.health.lock().map(|health| health.clone()).unwrap_or_default()
+    }
+
+    /// Record the result of a health check
+    pub fn update_health(&self, update: impl FnOnce(&mut ServerHealth)) {
+        if let Ok(mut health) = self.inner.health.lock() {
+            update(&mut health);
+        }
+    }
+
+    /// Whether the server advertised the `tools` capability
+    pub fn supports_tools(&self) -> bool {
+        self.server_info()
+            .is_some_and(|info| info.capabilities.tools.is_some())
+    }
+
+    /// Whether the server advertised the `resources` capability
+    pub fn supports_resources(&self) -> bool {
+        self.server_info()
+            .is_some_and(|info| info.capabilities.resources.is_some())
+    }
+
+    /// Whether the server advertised the `prompts` capability
+    pub fn supports_prompts(&self) -> bool {
+        self.server_info()
+            .is_some_and(|info| info.capabilities.prompts.is_some())
+    }
+
+    /// Initialize the session and send `notifications/initialized`
+    ///
+    /// For stdio servers this (re)starts the server process. Fails if the
+    /// server answers with a protocol version we do not support.
+    pub async fn initialize(&self) -> Result<()> {
+        match &self.inner.transport {
+            Transport::Http(http) => {
+                http.set_session_id(None);
+                http.set_protocol_version(None);
+            }
+            Transport::Stdio(stdio) => stdio.start(&self.inner.name, self.inner.events.clone()).await?,
+        }
+
+        let params = json!({
+            "protocolVersion": PROTOCOL_VERSIONS[0],
+            "capabilities": {},
+            "clientInfo": {
+                "name": "pcli2-rig",
+                "version": env!("CARGO_PKG_VERSION")
+            }
+        });
+        let result = self
+            .request("initialize", params)
+            .await
+            .context("Initialize failed")?;
+        let info = parse_initialize_result(result)?;
+        debug!(
+            "MCP server '{}' is {} {} (protocol {})",
+            self.inner.name,
+            info.server_info.name,
+            info.server_info.version,
+            info.protocol_version
+        );
+
+        if let Transport::Http(http) = &self.inner.transport {
+            http.set_protocol_version(Some(info.protocol_version.to_string()));
+        }
+        let capabilities = info.capabilities.clone();
+        if let Ok(mut server_info) = self.inner.server_info.lock() {
+            *server_info = Some(info);
+        }
+
+        self.notify("notifications/initialized", None).await?;
+
+        // Log messages are only sent once a level is set
+        if capabilities.logging.is_some()
+            && let Err(e) = self.request("logging/setLevel", json!({"level": "info"})).await
+        {
+            debug!("Could not set the log level of '{}': {:#}", self.inner.name, e);
+        }
+        if let Transport::Http(http) = &self.inner.transport
+            && sends_notifications(&capabilities)
+        {
+            http.listen(&self.inner.name, self.inner.events.clone());
+        }
+        Ok(())
+    }
+
+    /// Whether the server changed its tool list since the last call
+    pub fn take_tools_changed(&self) -> bool {
+        self.inner.events.tools_changed.swap(false, Ordering::Relaxed)
+    }
+
+    /// Progress of the running tool calls the server has reported progress for
+    pub fn tool_progress(&self) -> Vec<ToolProgress> {
+        self.inner
+            .events
+            .progress
+            .lock()
+            .map(|progress| progress.values().filter(|call| call.progress.is_some()).cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub async fn list_tools(&self) -> Result<Vec<rmcp::model::Tool>> {
+        let result = self
+            .request("tools/list", json!({}))
+            .await
+            .context("tools/list failed")?;
+
+        // Parse the response to extract tools
+        if let Some(tools) = result.get("tools") {
+            let tools: Vec<rmcp::model::Tool> = serde_json::from_value(tools.clone())
+                .context("Failed to parse tools response")?;
+            Ok(tools)
+        } else {
+            Ok(Vec::new())
+        }
+    }
+
+    /// List the server's resources
+    pub async fn list_resources(&self) -> Result<Vec<Resource>> {
+        self.list_all("resources/list", "resources").await
+    }
+
+    /// List the server's resource templates (URIs with `{placeholders}`)
+    pub async fn list_resource_templates(&self) -> Result<Vec<ResourceTemplate>> {
+        self.list_all("resources/templates/list", "resourceTemplates").await
+    }
+
+    /// Read a resource; a resource can have several contents (e.g. a directory)
+    pub async fn read_resource(&self, uri: &str) -> Result<Vec<ResourceContents>> {
+        let result = self
+            .request("resources/read", json!({ "uri": uri }))
+            .await
+            .with_context(|| format!("resources/read failed for {}", uri))?;
+        let result: ReadResourceResult =
+            serde_json::from_value(result).context("Failed to parse resources/read response")?;
+        Ok(result.contents)
+    }
+
+    /// List the server's prompts
+    pub async fn list_prompts(&self) -> Result<Vec<Prompt>> {
+        self.list_all("prompts/list", "prompts").await
+    }
+
+    /// Get a prompt's messages, filled in with `arguments`
+    pub async fn get_prompt(&self, name: &str, arguments: &HashMap<String, String>) -> Result<GetPromptResult> {
+        let result = self
+            .request("prompts/get", json!({ "name": name, "arguments": arguments }))
+            .await
+            .with_context(|| format!("prompts/get failed for {}", name))?;
+        serde_json::from_value(result).context("Failed to parse prompts/get response")
+    }
+
+    /// Collect the items under `key` from every page of a paginated list method
+    async fn list_all<T: DeserializeOwned>(&self, method: &str, key: &str) -> Result<Vec<T>> {
+        let mut items = Vec::new();
+        let mut cursor: Option<String> = None;
+        loop {
+            let params = match &cursor {
+                Some(cursor) => json!({ "cursor": cursor }),
+                None => json!({}),
+            };
+            let result = self
+                .request(method, params)
+                .await
+                .with_context(|| format!("{} failed", method))?;
+            if let Some(page) = result.get(key) {
+                let page: Vec<T> = serde_json::from_value(page.clone())
+                    .with_context(|| format!("Failed to parse {} response", method))?;
+                items.extend(page);
+            }
+            // Stop on a repeated cursor too, so a broken server cannot loop us forever
+            match result.get("nextCursor").and_then(Value::as_str) {
+                Some(next) if cursor.as_deref() != Some(next) => cursor = Some(next.to_string()),
+                _ => return Ok(items),
+            }
+        }
+    }
+
+    /// Call a tool and decode its result
+    ///
+    /// A result flagged with `isError` is returned as `Ok`, with
+    /// [`ToolOutput::is_error`] set.
+    pub async fn call_tool(&self, name: &str, arguments: Value) -> Result<ToolOutput> {
+        // The server reports progress under this token
+        let token = json!(self.inner.next_id.fetch_add(1, Ordering::Relaxed));
+        let params = json!({
+            "name": name,
+            "arguments": arguments,
+            "_meta": {"progressToken": token}
+        });
+        let result_value = {
+            let _permit = self.inner.tool_calls.acquire().await?;
+            let _progress = ProgressGuard::new(&self.inner.events, &token, name);
+            self.request("tools/call", params)
+                .await
+                .context("Tool call error")?
+        };
+
+        Ok(decode_tool_result(name, result_value))
+    }
+
+    /// Send a `ping` and return the round trip time
+    ///
+    /// An error reply still shows that the server is answering, so only transport
+    /// failures are errors.
+    pub async fn ping(&self) -> Result<Duration> {
+        let start = Instant::now();
+        self.round_trip("ping", json!({})).await.context("ping failed")?;
+        Ok(start.elapsed())
+    }
+
+    /// End the session: `DELETE` it on HTTP servers, stop the process of stdio servers
+    pub async fn shutdown(&self) {
+        match &self.inner.transport {
+            Transport::Http(http) => {
+                http.stop_listening();
+                http.delete_session(&self.inner.name).await
+            }
+            Transport::Stdio(stdio) => stdio.stop(&self.inner.name).await,
+        }
+    }
+
+    /// End the session in a background task, so callers need not wait for the server
+    pub fn shutdown_in_background(self) {
+        tokio::spawn(async move {
+            if tokio::time::timeout(SHUTDOWN_TIMEOUT, self.shutdown()).await.is_err() {
+                debug!("MCP server '{}' did not close its session in time", self.name());
+            }
+        });
+    }
+
+    /// Send a request with a fresh id and wait for its result
+    async fn request(&self, method: &str, params: Value) -> Result<Value> {
+        let reply = self.round_trip(method, params).await?;
+        if let Some(error) = reply.get("error") {
+            let message = error
+                .get("message")
+                .and_then(Value::as_str)
+                .unwrap_or("unknown error");
+            match error.get("code") {
+                Some(code) => anyhow::bail!("{} (code {})", message, code),
+                None => anyhow::bail!("{}", message),
+            }
+        }
+        Ok(reply.get("result").cloned().unwrap_or(Value::Null))
+    }
+
+    /// Send a request with a fresh id and wait for the reply, result or error
+    async fn round_trip(&self, method: &str, params: Value) -> Result<Value> {
+        let id = self.inner.next_id.fetch_add(1, Ordering::Relaxed);
+        let message = json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": method,
+            "params": params
+        });
+
+        match &self.inner.transport {
+            Transport::Http(http) => self.http_request(http, &message, id, method).await,
+            Transport::Stdio(stdio) => self.stdio_request(stdio, &message, id, method).await,
+        }
+    }
+
+    /// POST a request to an HTTP server and read the reply
+    ///
+    /// If the server has forgotten our session (404), the session is
+    /// re-initialized and the request retried once.
+    async fn http_request(&self, http: &HttpTransport, message: &Value, id: u64, method: &str) -> Result<Value> {
+        let had_session = http.session_id().is_some();
+        let mut response = http.post(message).await?;
+        if response.status() == reqwest::StatusCode::NOT_FOUND && had_session && method != "initialize" {
+            debug!("MCP session for '{}' expired, re-initializing", self.inner.name);
+            Box::pin(self.initialize()).await?;
+            response = http.post(message).await?;
+        }
+
+        match response.status() {
+            status if status.is_success() => {}
+            status @ (reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) => {
+                anyhow::bail!("{} failed with status: {} (check the server's token or headers)", method, status)
+            }
+            status => anyhow::bail!("{} failed with status: {}", method, status),
+        }
+
+        // The server assigns the session id in its reply to initialize
+        if method == "initialize"
+            && let Some(session_id) = response
+                .headers()
+                .get(SESSION_HEADER)
+                .and_then(|v| v.to_str().ok())
+        {
+            debug!("MCP server '{}' assigned session {}", self.inner.name, session_id);
+            http.set_session_id(Some(session_id.to_string()));
+        }
+
+        read_reply(response, id, http, &self.inner.name, &self.inner.events).await
+    }
+
+    /// Exchange a request with a stdio server
+    ///
+    /// If the server process has exited, it is restarted and re-initialized,
+    /// and the request retried once.
+    async fn stdio_request(&self, stdio: &StdioTransport, message: &Value, id: u64, method: &str) -> Result<Value> {
+        if let Some(reply) = stdio.exchange(&self.inner.name, message, id).await? {
+            return Ok(reply);
+        }
+        if method == "initialize" {
+            anyhow::bail!("MCP server exited during initialization");
+        }
+
+        {
+            // Another request may have restarted the server while we waited
+            let _restart = stdio.restart.lock().await;
+            if !stdio.is_running().await {
+                tracing::warn!("MCP server '{}' is not running, restarting it", self.inner.name);
+                Box::pin(self.initialize()).await?;
+            }
+        }
+        stdio
+            .exchange(&self.inner.name, message, id)
+            .await?
+            .context("MCP server exited")
+    }
+
+    /// Send a notification (no response expected)
+    async fn notify(&self, method: &str, params: Option<Value>) -> Result<()> {
+        let mut message = json!({
+            "jsonrpc": "2.0",
+            "method": method
+        });
+        if let Some(params) = params {
+            message["params"] = params;
+        }
+
+        match &self.inner.transport {
+            Transport::Http(http) => {
+                let response = http.post(&message).await?;
+                if !response.status().is_success() {
+                    anyhow::bail!("{} failed with status: {}", method, response.status());
+                }
+            }
+            Transport::Stdio(stdio) => stdio.send(&message).await?,
+        }
+        Ok(())
+    }
+}
+
+/// Find base64 image data in the result of an image-related tool
+///
+/// Returns the data and its MIME type, guessed from the base64 signature.
+fn detect_base64_image(tool_name: &str, result_value: &Value) -> Option<(String, &'static str)> {
+    // Check if this is likely an image/thumbnail tool
+    let is_image_tool = tool_name.to_lowercase().contains("thumbnail") 
+        || tool_name.to_lowercase().contains("image");
+    
+    if !is_image_tool {
+        return None;
+    }
+
+    // Try to extract base64 data from the result
+    let base64_data = if let Some(text) = result_value.as_str() {
+        // Result is a plain string (likely base64)
+        Some(text.to_string())
+    } else if let Some(obj) = result_value.as_object() {
+        // Result might be an object with a "text" or "data" field
+        obj.get("text")
+            .or_else(|| obj.get("data"))
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+    } else {
+        None
+    }?;
+
+    // Validate that it looks like base64 (contains only valid characters and is reasonably long)
+    if base64_data.is_empty() || !base64_data.chars().all(|c| c.is_alphanumeric() || c == '+' || c == '/' || c == '=') {
+        return None;
+    }
+
+    // Default assumption for thumbnails is PNG
+    let mime_type = image_mime_type(&base64_data).unwrap_or("image/png");
+
+    Some((base64_data, mime_type))
+}
+
+/// Image MIME type of base64 data, from its signature
+pub fn image_mime_type(base64_data: &str) -> Option<&'static str> {
+    if base64_data.starts_with("iVBORw0KGgo") {
+        Some("image/png")
+    } else if base64_data.starts_with("/9j/") {
+        Some("image/jpeg")
+    } else if base64_data.starts_with("R0lGOD") {
+        Some("image/gif")
+    } else if base64_data.starts_with("UklGR") {
+        Some("image/webp")
+    } else {
+        None
+    }
+}
+
+/// Image types sent to vision models and rendered inline; other types,
+/// SVG included, are only summarized
+const IMAGE_MIME_TYPES: [&str; 4] = ["image/png", "image/jpeg", "image/gif", "image/webp"];
+
+/// Binary content from a tool result (image, audio or embedded resource)
+///
+/// Attachments are kept for the UI; the model only sees a one-line summary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Attachment {
+    pub mime_type: String,
+    /// Base64-encoded content
+    pub data: String,
+    /// URI of an embedded resource
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uri: Option<String>,
+}
+
+impl Attachment {
+    /// Whether the attachment is a PNG, JPEG, GIF or WebP image
+    pub fn is_image(&self) -> bool {
+        let mime_type = self.mime_type.split(';').next().unwrap_or_default().trim();
+        IMAGE_MIME_TYPES.iter().any(|t| t.eq_ignore_ascii_case(mime_type))
+    }
+
+    /// Decoded content
+    pub fn bytes(&self) -> Result<Vec<u8>> {
+        BASE64.decode(self.data.trim()).context("Invalid base64 data")
+    }
+
+    /// Decoded size in bytes
+    pub fn size(&self) -> usize {
+        let padding = self.data.bytes().rev().take_while(|&b| b == b'=').count();
+        (self.data.len() * 3 / 4).saturating_sub(padding)
+    }
+
+    /// Short description, e.g. `image/png, 12.4 KB`
+    pub fn label(&self) -> String {
+        let size = self.size();
+        let size = if size >= 1024 {
+            format!("{:.1} KB", size as f64 / 1024.0)
+        } else {
+            format!("{} bytes", size)
+        };
+        match &self.uri {
+            Some(uri) => format!("{} ({}, {})", uri, self.mime_type, size),
+            None => format!("{}, {}", self.mime_type, size),
+        }
+    }
+
+    /// Text content inlined into a message, wrapped in a `<resource>` tag
+    /// (binary content is only described)
+    pub fn resource_text(&self) -> String {
+        let uri = self.uri.as_deref().unwrap_or("attachment");
+        match self.bytes().ok().and_then(|bytes| String::from_utf8(bytes).ok()) {
+            Some(text) => format!(
+                "<resource uri=\"{}\" mimeType=\"{}\">\n{}\n</resource>",
+                uri, self.mime_type, text
+            ),
+            None => format!("[resource {}: binary content not shown]", self.label()),
+        }
+    }
+
+    /// Summary shown to the model in place of the content
+    pub fn summary(&self, id: u64) -> String {
+        match &self.uri {
+            Some(_) => format!("[attachment {}: resource {}, stored for the user]", id, self.label()),
+            None if self.is_image() => format!("[attachment {}: {}, shown to the user]", id, self.label()),
+            None => format!("[attachment {}: {}, stored for the user]", id, self.label()),
+        }
+    }
+}
+
+impl From<ResourceContents> for Attachment {
+    fn from(contents: ResourceContents) -> Self {
+        match contents {
+            ResourceContents::TextResourceContents { uri, mime_type, text, .. } => Attachment {
+                mime_type: mime_type.unwrap_or_else(|| "text/plain".to_string()),
+                data: BASE64.encode(text),
+                uri: Some(uri),
+            },
+            ResourceContents::BlobResourceContents { uri, mime_type, blob, .. } => Attachment {
+                mime_type: mime_type.unwrap_or_else(|| "application/octet-stream".to_string()),
+                data: blob,
+                uri: Some(uri),
+            },
+        }
+    }
+}
+
+/// One part of a decoded tool result
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputPart {
+    Text(String),
+    Attachment(Attachment),
+}
+
+/// A decoded `tools/call` result
+#[derive(Debug, Clone, PartialEq)]
+pub struct ToolOutput {
+    pub parts: Vec<OutputPart>,
+    /// The server's `structuredContent`, passed to the model as JSON
+    pub structured_content: Option<Value>,
+    /// Whether the server flagged the result as an error (`isError`)
+    pub is_error: bool,
+}
+
+impl ToolOutput {
+    /// Text for the model: the text parts, one summary per attachment (from
+    /// `attach`, which takes ownership of the attachment) and the structured
+    /// content if the text does not already contain it
+    pub fn into_text(self, mut attach: impl FnMut(Attachment) -> String) -> String {
+        let mut texts = Vec::new();
+        let mut has_structured = false;
+        for part in self.parts {
+            match part {
+                OutputPart::Text(text) => {
+                    // Servers should also serialize structured content as text
+                    has_structured |= self.structured_content.as_ref().is_some_and(|structured| {
+                        serde_json::from_str::<Value>(&text).is_ok_and(|value| &value == structured)
+                    });
+                    texts.push(text);
+                }
+                OutputPart::Attachment(attachment) => texts.push(attach(attachment)),
+            }
+        }
+        if let Some(structured) = self.structured_content.filter(|_| !has_structured) {
+            texts.push(serde_json::to_string_pretty(&structured).unwrap_or_default());
+        }
+
+        let text = texts.join("\n");
+        if text.is_empty() && self.is_error {
+            "The tool reported an error without details".to_string()
+        } else {
+            text
+        }
+    }
+}
+
+/// Decode a `CallToolResult`
+///
+/// Text parts that hold base64 image data from image-related tools are
+/// turned into image attachments. Results that are not a valid
+/// `CallToolResult` are passed through as pretty-printed JSON.
+pub fn decode_tool_result(tool_name: &str, result: Value) -> ToolOutput {
+    let result: CallToolResult = match serde_json::from_value(result.clone()) {
+        Ok(result) => result,
+        Err(e) => {
+            debug!("Tool result from {} is not a CallToolResult: {}", tool_name, e);
+            let part = match detect_base64_image(tool_name, &result) {
+                Some((data, mime_type)) => OutputPart::Attachment(Attachment {
+                    mime_type: mime_type.to_string(),
+                    data,
+                    uri: None,
+                }),
+                None => OutputPart::Text(serde_json::to_string_pretty(&result).unwrap_or_default()),
+            };
+            return ToolOutput {
+                parts: vec![part],
+                structured_content: None,
+                is_error: false,
+            };
+        }
+    };
+
+    let parts = result
+        .content
+        .into_iter()
+        .map(|content| match content.raw {
+            RawContent::Text(text) => {
+                let value = serde_json::from_str(&text.text)
+                    .unwrap_or_else(|_| Value::String(text.text.clone()));
+                match detect_base64_image(tool_name, &value) {
+                    Some((data, mime_type)) => OutputPart::Attachment(Attachment {
+                        mime_type: mime_type.to_string(),
+                        data,
+                        uri: None,
+                    }),
+                    None => OutputPart::Text(text.text),
+                }
+            }
+            RawContent::Image(image) => OutputPart::Attachment(Attachment {
+                mime_type: image.mime_type,
+                data: image.data,
+                uri: None,
+            }),
+            RawContent::Audio(audio) => OutputPart::Attachment(Attachment {
+                mime_type: audio.mime_type,
+                data: audio.data,
+                uri: None,
+            }),
+            RawContent::Resource(resource) => OutputPart::Attachment(resource.resource.into()),
+            RawContent::ResourceLink(link) => {
+                OutputPart::Text(format!("Resource link: {} ({})", link.name, link.uri))
+            }
+        })
+        .collect();
+
+    ToolOutput {
+        parts,
+        structured_content: result.structured_content,
+        is_error: result.is_error.unwrap_or(false),
+    }
+}
+
+impl HttpTransport {
+    fn session_id(&self) -> Option<String> {
+        self.session_id.lock().ok().and_then(|id| id.clone())
+    }
+
+    fn set_session_id(&self, id: Option<String>) {
+        if let Ok(mut session_id) = self.session_id.lock() {
+            *session_id = id;
+        }
+    }
+
+    fn set_protocol_version(&self, version: Option<String>) {
+        if let Ok(mut protocol_version) = self.protocol_version.lock() {
+            *protocol_version = version;
+        }
+    }
+
+    /// POST a JSON-RPC message with the transport headers
+    async fn post(&self, message: &Value) -> Result<reqwest::Response> {
+        let mut request = self
+            .http
+            .post(&self.url)
+            .header(reqwest::header::ACCEPT, "application/json, text/event-stream")
+            .json(message);
+        if let Some(session_id) = self.session_id() {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        if let Some(version) = self.protocol_version.lock().ok().and_then(|v| v.clone()) {
+            request = request.header(PROTOCOL_VERSION_HEADER, version);
+        }
+        request
+            .send()
+            .await
+            .with_context(|| format!("Failed to reach MCP server at {}", self.url))
+    }
+
+    /// POST our reply to a request the server sent us
+    async fn send_reply(&self, name: &str, reply: &Value) {
+        match self.post(reply).await {
+            Ok(response) if response.status().is_success() => {}
+            Ok(response) => debug!("Reply to '{}' was refused: {}", name, response.status()),
+            Err(e) => debug!("Could not reply to '{}': {:#}", name, e),
+        }
+    }
+
+    /// Read the GET stream of server-initiated messages in the background
+    ///
+    /// Replaces the stream of an earlier session. Servers that offer no such
+    /// stream answer 405, which just means there is nothing to listen to.
+    fn listen(self: &Arc<Self>, name: &str, events: Arc<ServerEvents>) {
+        let mut request = self
+            .http
+            .get(&self.url)
+            .header(reqwest::header::ACCEPT, "text/event-stream");
+        if let Some(session_id) = self.session_id() {
+            request = request.header(SESSION_HEADER, session_id);
+        }
+        if let Some(version) = self.protocol_version.lock().ok().and_then(|v| v.clone()) {
+            request = request.header(PROTOCOL_VERSION_HEADER, version);
+        }
+
+        let name = name.to_string();
+        // Weak, so the task does not keep the transport (which aborts it on drop) alive
+        let transport = Arc::downgrade(self);
+        let task = tokio::spawn(async move {
+            let response = match request.send().await {
+                Ok(response) if response.status().is_success() => response,
+                Ok(response) => {
+                    debug!("MCP server '{}' offers no notification stream ({})", name, response.status());
+                    return;
+                }
+                Err(e) => {
+                    debug!("Could not open the notification stream of '{}': {}", name, e);
+                    return;
+                }
+            };
+            let mut stream = SseReader::new(response);
+            while let Ok(Some(message)) = stream.next_message().await {
+                let Some(transport) = transport.upgrade() else {
+                    return;
+                };
+                handle_server_message(&transport, &name, &events, &message).await;
+            }
+            debug!("Notification stream of '{}' closed", name);
+        });
+        if let Ok(mut listener) = self.listener.lock()
+            && let Some(previous) = listener.replace(task)
+        {
+            previous.abort();
+        }
+    }
+
+    /// Stop reading the GET stream
+    fn stop_listening(&self) {
+        if let Some(task) = self.listener.lock().ok().and_then(|mut listener| listener.take()) {
+            task.abort();
+        }
+    }
+
+    /// `DELETE` the session, if the server assigned one
+    async fn delete_session(&self, name: &str) {
+        let Some(session_id) = self.session_id() else {
+            return;
+        };
+        debug!("Closing MCP session for '{}'", name);
+        match self
+            .http
+            .delete(&self.url)
+            .header(SESSION_HEADER, session_id)
+            .timeout(SHUTDOWN_TIMEOUT)
+            .send()
+            .await
+        {
+            // 405 means the server does not allow clients to end sessions
+            Ok(response) if response.status().is_success() || response.status() == 405 => {}
+            Ok(response) => debug!("MCP session DELETE for '{}' returned {}", name, response.status()),
+            Err(e) => debug!("MCP session DELETE for '{}' failed: {}", name, e),
+        }
+    }
+}
+
+impl Drop for HttpTransport {
+    fn drop(&mut self) {
+        self.stop_listening();
+    }
+}
+
+impl StdioTransport {
+    /// Start the server process, replacing any running one
+    async fn start(&self, name: &str, events: Arc<ServerEvents>) -> Result<()> {
+        let mut process = self.process.lock().await;
+        if let Some(old) = process.take() {
+            let _ = old.child.lock().await.kill().await;
+        }
+
+        let command = self.config.command.as_deref().unwrap_or_default();
+        let mut cmd = Command::new(command);
+        cmd.args(&self.config.args)
+            .envs(&self.config.env)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = &self.config.cwd {
+            cmd.current_dir(cwd);
+        }
+
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Failed to start '{}'", self.config.endpoint()))?;
+        debug!("Started MCP server '{}' (pid {:?})", name, child.id());
+
+        // The server's stderr is its log
+        if let Some(stderr) = child.stderr.take() {
+            let name = name.to_string();
+            tokio::spawn(async move {
+                let mut lines = BufReader::new(stderr).lines();
+                while let Ok(Some(line)) = lines.next_line().await {
+                    debug!("[{}] {}", name, line);
+                }
+            });
+        }
+
+        let stdin = Arc::new(tokio::sync::Mutex::new(Some(
+            child.stdin.take().context("Failed to open server stdin")?,
+        )));
+        let stdout = child.stdout.take().context("Failed to open server stdout")?;
+        let pending: PendingReplies = Arc::new(Mutex::new(Some(HashMap::new())));
+        tokio::spawn(read_stdout(name.to_string(), stdout, stdin.clone(), pending.clone(), events));
+
+        *process = Some(Arc::new(StdioProcess {
+            child: tokio::sync::Mutex::new(child),
+            stdin,
+            pending,
+        }));
+        Ok(())
+    }
+
+    /// The running process, if any
+    async fn current(&self) -> Option<Arc<StdioProcess>> {
+        self.process.lock().await.clone()
+    }
+
+    async fn is_running(&self) -> bool {
+        self.current().await.is_some()
+    }
+
+    /// Forget a process that has exited (unless it was already replaced)
+    async fn forget(&self, name: &str, exited: &Arc<StdioProcess>) {
+        let mut process = self.process.lock().await;
+        if process.as_ref().is_some_and(|current| Arc::ptr_eq(current, exited)) {
+            let status = exited.child.lock().await.try_wait();
+            tracing::warn!("MCP server '{}' exited ({:?})", name, status);
+            *process = None;
+        }
+    }
+
+    /// Write a message without waiting for a reply
+    async fn send(&self, message: &Value) -> Result<()> {
+        let process = self.current().await.context("MCP server is not running")?;
+        write_message(&process.stdin, message)
+            .await
+            .context("Failed to write to MCP server")
+    }
+
+    /// Send a request and wait for its reply
+    ///
+    /// Returns `None` if the process is not running or exits before replying.
+    async fn exchange(&self, name: &str, message: &Value, id: u64) -> Result<Option<Value>> {
+        let Some(process) = self.current().await else {
+            return Ok(None);
+        };
+        if matches!(process.child.lock().await.try_wait(), Ok(Some(_))) {
+            self.forget(name, &process).await;
+            return Ok(None);
+        }
+
+        let (reply_tx, reply_rx) = oneshot::channel();
+        let registered = process
+            .pending
+            .lock()
+            .ok()
+            .and_then(|mut pending| pending.as_mut().map(|pending| pending.insert(id, reply_tx)))
+            .is_some();
+        if !registered {
+            self.forget(name, &process).await;
+            return Ok(None);
+        }
+        if write_message(&process.stdin, message).await.is_err() {
+            if let Ok(mut pending) = process.pending.lock()
+                && let Some(pending) = pending.as_mut()
+            {
+                pending.remove(&id);
+            }
+            self.forget(name, &process).await;
+            return Ok(None);
+        }
+
+        match reply_rx.await {
+            Ok(reply) => Ok(Some(reply)),
+            // The reader task drops all pending requests when stdout closes
+            Err(_) => {
+                self.forget(name, &process).await;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Close stdin and wait for the server to exit, killing it if it does not
+    async fn stop(&self, name: &str) {
+        let Some(process) = self.process.lock().await.take() else {
+            return;
+        };
+        debug!("Stopping MCP server '{}'", name);
+        process.stdin.lock().await.take();
+        let mut child = process.child.lock().await;
+        if tokio::time::timeout(STDIO_EXIT_TIMEOUT, child.wait()).await.is_err() {
+            let _ = child.kill().await;
+        }
+    }
+}
+
+/// Write one newline-delimited message to a stdio server
+async fn write_message(stdin: &ServerStdin, message: &Value) -> std::io::Result<()> {
+    let mut line = message.to_string();
+    line.push('\n');
+    let mut stdin = stdin.lock().await;
+    let stdin = stdin.as_mut().ok_or(std::io::ErrorKind::BrokenPipe)?;
+    stdin.write_all(line.as_bytes()).await?;
+    stdin.flush().await
+}
+
+/// Read a stdio server's stdout until it closes, routing replies by id
+///
+/// Requests from the server are answered here: pings succeed, anything else is
+/// declined. When stdout closes, all pending requests are dropped.
+async fn read_stdout(
+    name: String,
+    stdout: ChildStdout,
+    stdin: ServerStdin,
+    pending: PendingReplies,
+    events: Arc<ServerEvents>,
+) {
+    let mut lines = BufReader::new(stdout).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Ok(message) = serde_json::from_str::<Value>(&line) else {
+            debug!("[{}] {}", name, line);
+            continue;
+        };
+
+        let is_reply = message.get("result").is_some() || message.get("error").is_some();
+        if is_reply && let Some(id) = message.get("id").and_then(Value::as_u64) {
+            let waiting = pending
+                .lock()
+                .ok()
+                .and_then(|mut pending| pending.as_mut()?.remove(&id));
+            match waiting {
+                Some(reply_tx) => {
+                    let _ = reply_tx.send(message);
+                }
+                None => debug!("Unexpected reply from '{}' for id {}", name, id),
+            }
+            continue;
+        }
+
+        if let Some(reply) = server_request_reply(&message) {
+            if write_message(&stdin, &reply).await.is_err() {
+                break;
+            }
+            continue;
+        }
+        handle_notification(&name, &events, &message);
+    }
+
+    if let Ok(mut pending) = pending.lock() {
+        *pending = None;
+    }
+}
+
+/// Resource mentions in chat input: `@server:uri` words, as `(server, uri)`
+///
+/// Trailing sentence punctuation is not part of the URI.
+pub fn resource_mentions(input: &str) -> Vec<(&str, &str)> {
+    input
+        .split_whitespace()
+        .filter_map(|word| word.strip_prefix('@'))
+        .filter_map(|mention| mention.split_once(':'))
+        .map(|(server, uri)| (server, uri.trim_end_matches(['.', ',', ';', '!', '?', ')'])))
+        .filter(|(server, uri)| !server.is_empty() && !uri.is_empty())
+        .collect()
+}
+
+/// Arguments for a prompt from a command line such as `folder="My Parts" depth=2`
+///
+/// A prompt with a single argument also takes the whole line as its value.
+/// Fails with a usage message when a required argument is missing.
+pub fn prompt_arguments(prompt: &Prompt, line: &str) -> Result<HashMap<String, String>> {
+    let declared = prompt.arguments.as_deref().unwrap_or_default();
+    let words = split_words(line);
+    let mut arguments = HashMap::new();
+    if let [single] = declared
+        && !words.iter().any(|word| word.starts_with(&format!("{}=", single.name)))
+    {
+        if !line.trim().is_empty() {
+            arguments.insert(single.name.clone(), words.join(" "));
+        }
+    } else {
+        for word in words {
+            let Some((name, value)) = word.split_once('=') else {
+                anyhow::bail!("Expected name=value, got '{}'. {}", word, prompt_usage(prompt));
+            };
+            arguments.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    let missing: Vec<_> = declared
+        .iter()
+        .filter(|arg| arg.required == Some(true) && !arguments.contains_key(&arg.name))
+        .map(|arg| arg.name.as_str())
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!("Missing {}. {}", missing.join(", "), prompt_usage(prompt));
+    }
+    Ok(arguments)
+}
+
+/// Argument list of a prompt, e.g. `Arguments: folder=<folder> [depth=<depth>]`
+pub fn prompt_usage(prompt: &Prompt) -> String {
+    let arguments: Vec<String> = prompt
+        .arguments
+        .iter()
+        .flatten()
+        .map(|arg| match arg.required {
+            Some(true) => format!("{}=<{}>", arg.name, arg.name),
+            _ => format!("[{}=<{}>]", arg.name, arg.name),
+        })
+        .collect();
+    format!("Arguments: {}", if arguments.is_empty() { "none".to_string() } else { arguments.join(" ") })
+}
+
+/// Split a line into words, keeping double-quoted text together (quotes removed)
+fn split_words(line: &str) -> Vec<String> {
+    let mut words = Vec::new();
+    let mut word = String::new();
+    let mut quoted = false;
+    for c in line.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            c if c.is_whitespace() && !quoted => {
+                if !word.is_empty() {
+                    words.push(std::mem::take(&mut word));
+                }
+            }
+            c => word.push(c),
+        }
+    }
+    if !word.is_empty() {
+        words.push(word);
+    }
+    words
+}
+
+/// Text and attachment of a prompt message
+pub fn prompt_content(content: PromptMessageContent) -> (String, Option<Attachment>) {
+    match content {
+        PromptMessageContent::Text { text } => (text, None),
+        PromptMessageContent::Image { image } => (
+            String::new(),
+            Some(Attachment {
+                mime_type: image.raw.mime_type,
+                data: image.raw.data,
+                uri: None,
+            }),
+        ),
+        PromptMessageContent::Resource { resource } => (String::new(), Some(resource.raw.resource.into())),
+        PromptMessageContent::ResourceLink { link } => {
+            (format!("Resource link: {} ({})", link.name, link.uri), None)
+        }
+    }
+}
+
+/// Parse the reply to `initialize` and check the negotiated protocol version
+fn parse_initialize_result(result: Value) -> Result<InitializeResult> {
+    let info: InitializeResult =
+        serde_json::from_value(result).context("Invalid initialize result")?;
+    let version = info.protocol_version.to_string();
+    if !PROTOCOL_VERSIONS.contains(&version.as_str()) {
+        anyhow::bail!(
+            "Server uses protocol version {}, supported versions are {}",
+            version,
+            PROTOCOL_VERSIONS.join(", ")
+        );
+    }
+    Ok(info)
+}
+
+/// Names of the capabilities a server advertised, e.g. `["tools", "resources"]`
+pub fn capability_names(capabilities: &ServerCapabilities) -> Vec<&'static str> {
+    [
+        ("tools", capabilities.tools.is_some()),
+        ("resources", capabilities.resources.is_some()),
+        ("prompts", capabilities.prompts.is_some()),
+        ("logging", capabilities.logging.is_some()),
+        ("completions", capabilities.completions.is_some()),
+    ]
+    .into_iter()
+    .filter_map(|(name, present)| present.then_some(name))
+    .collect()
+}
+
+/// Default headers for an HTTP server: its `headers` plus the bearer token
+fn http_headers(server: &McpServerConfig) -> Result<reqwest::header::HeaderMap> {
+    use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderName, HeaderValue};
+
+    let mut headers = HeaderMap::new();
+    for (name, value) in &server.headers {
+        let name = HeaderName::from_bytes(name.as_bytes())
+            .with_context(|| format!("Invalid header name '{}'", name))?;
+        let mut value = HeaderValue::from_str(value)
+            .with_context(|| format!("Invalid value for header '{}'", name))?;
+        value.set_sensitive(true);
+        headers.insert(name, value);
+    }
+    if let Some(token) = server.resolve_token()? {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))
+            .context("The token is not a valid header value")?;
+        value.set_sensitive(true);
+        headers.insert(AUTHORIZATION, value);
+    }
+    Ok(headers)
+}
+
+/// Read the JSON-RPC reply with the given id from a JSON or SSE response
+async fn read_reply(
+    response: reqwest::Response,
+    id: u64,
+    http: &HttpTransport,
+    name: &str,
+    events: &ServerEvents,
+) -> Result<Value> {
+    let is_sse = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("text/event-stream"));
+
+    if !is_sse {
+        let reply: Value = response.json().await.context("Invalid JSON-RPC response")?;
+        // Errors about unparseable requests carry a null id
+        if let Some(reply_id) = reply.get("id").filter(|reply_id| !reply_id.is_null())
+            && reply_id.as_u64() != Some(id)
+        {
+            anyhow::bail!("Reply id {} does not match request id {}", reply_id, id);
+        }
+        return Ok(reply);
+    }
+
+    // Messages other than our reply (server notifications and requests) may come
+    // first on the stream
+    let mut stream = SseReader::new(response);
+    while let Some(message) = stream.next_message().await? {
+        if message.get("id").and_then(Value::as_u64) == Some(id)
+            && (message.get("result").is_some() || message.get("error").is_some())
+        {
+            return Ok(message);
+        }
+        handle_server_message(http, name, events, &message).await;
+    }
+
+    anyhow::bail!("SSE stream ended without a response")
+}
+
+/// JSON-RPC messages read from an SSE response, as POST replies and the GET
+/// stream both carry them
+struct SseReader {
+    response: reqwest::Response,
+    /// Bytes of the event being received
+    buffer: Vec<u8>,
+    /// Data of complete events not yet returned
+    pending: VecDeque<String>,
+}
+
+impl SseReader {
+    fn new(response: reqwest::Response) -> Self {
+        Self {
+            response,
+            buffer: Vec::new(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Next JSON message, skipping events that are not JSON; `None` once the
+    /// stream ends
+    async fn next_message(&mut self) -> Result<Option<Value>> {
+        loop {
+            while let Some(data) = self.pending.pop_front() {
+                match serde_json::from_str(&data) {
+                    Ok(message) => return Ok(Some(message)),
+                    Err(_) => debug!("Ignoring non-JSON SSE event: {}", data),
+                }
+            }
+            let Some(chunk) = self.response.chunk().await.context("Failed to read SSE stream")? else {
+                return Ok(None);
+            };
+            self.buffer.extend_from_slice(&chunk);
+            self.pending.extend(take_sse_events(&mut self.buffer));
+        }
+    }
+}
+
+/// Answer a request from the server or act on its notification
+async fn handle_server_message(http: &HttpTransport, name: &str, events: &ServerEvents, message: &Value) {
+    match server_request_reply(message) {
+        Some(reply) => http.send_reply(name, &reply).await,
+        None => handle_notification(name, events, message),
+    }
+}
+
+/// Our reply to a request the server sent us (`None` for other messages)
+///
+/// Only `ping` is supported; other methods get a "method not found" error.
+fn server_request_reply(message: &Value) -> Option<Value> {
+    let request_id = message.get("id")?;
+    let method = message.get("method").and_then(Value::as_str)?;
+    Some(if method == "ping" {
+        json!({"jsonrpc": "2.0", "id": request_id, "result": {}})
+    } else {
+        json!({"jsonrpc": "2.0", "id": request_id, "error": {
+            "code": -32601,
+            "message": format!("Method not supported: {}", method)
+        }})
+    })
+}
+
+/// Whether a server announces changes or log messages it may send unprompted
+fn sends_notifications(capabilities: &ServerCapabilities) -> bool {
+    capabilities.logging.is_some()
+        || capabilities.tools.as_ref().is_some_and(|tools| tools.list_changed == Some(true))
+        || capabilities.resources.as_ref().is_some_and(|resources| resources.list_changed == Some(true))
+        || capabilities.prompts.as_ref().is_some_and(|prompts| prompts.list_changed == Some(true))
+}
+
+/// Act on a notification (or other unsolicited message) from a server
+fn handle_notification(server: &str, events: &ServerEvents, message: &Value) {
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    match message.get("method").and_then(Value::as_str) {
+        Some("notifications/tools/list_changed") => {
+            debug!("MCP server '{}' changed its tool list", server);
+            events.tools_changed.store(true, Ordering::Relaxed);
+        }
+        Some("notifications/progress") => {
+            let token = params.get("progressToken").map(Value::to_string).unwrap_or_default();
+            match serde_json::from_value::<ProgressNotificationParam>(params) {
+                Ok(update) => {
+                    if let Ok(mut progress) = events.progress.lock()
+                        && let Some(call) = progress.get_mut(&token)
+                    {
+                        call.progress = Some(update.progress);
+                        call.total = update.total;
+                        if update.message.is_some() {
+                            call.message = update.message;
+                        }
+                    }
+                }
+                Err(e) => debug!("Invalid progress notification from '{}': {}", server, e),
+            }
+        }
+        Some("notifications/message") => match serde_json::from_value::<LoggingMessageNotificationParam>(params) {
+            Ok(entry) => log_server_message(server, entry),
+            Err(e) => debug!("Invalid log notification from '{}': {}", server, e),
+        },
+        _ => debug!("MCP server message: {}", message),
+    }
+}
+
+/// Send a server's log message to the logs pane, tagged with the server name
+fn log_server_message(server: &str, entry: LoggingMessageNotificationParam) {
+    let data = match entry.data {
+        Value::String(text) => text,
+        other => other.to_string(),
+    };
+    let text = match entry.logger {
+        Some(logger) => format!("[{}] {}: {}", server, logger, data),
+        None => format!("[{}] {}", server, data),
+    };
+    match entry.level {
+        LoggingLevel::Debug => debug!("{}", text),
+        LoggingLevel::Info | LoggingLevel::Notice => tracing::info!("{}", text),
+        LoggingLevel::Warning => tracing::warn!("{}", text),
+        _ => tracing::error!("{}", text),
+    }
+}
+
+/// Remove complete events from an SSE buffer and return their `data` payloads
+///
+/// Events are separated by a blank line; multiple `data:` lines in one event
+/// are joined with newlines. Incomplete trailing events stay in the buffer. The
+/// buffer holds raw bytes, so a character split across network chunks is only
+/// decoded once its event is complete.
+fn take_sse_events(buffer: &mut Vec<u8>) -> Vec<String> {
+    if buffer.contains(&b'\r') {
+        let mut normalized = Vec::with_capacity(buffer.len());
+        for (i, &byte) in buffer.iter().enumerate() {
+            if byte != b'\r' || buffer.get(i + 1) != Some(&b'\n') {
+                normalized.push(byte);
+            }
+        }
+        *buffer = normalized;
+    }
+
+    let mut events = Vec::new();
+    while let Some(end) = buffer.windows(2).position(|pair| pair == b"\n\n") {
+        let event: Vec<u8> = buffer.drain(..end + 2).collect();
+        let event = String::from_utf8_lossy(&event);
+        let data: Vec<&str> = event
+            .lines()
+            .filter_map(|line| line.strip_prefix("data:"))
+            .map(|data| data.strip_prefix(' ').unwrap_or(data))
+            .collect();
+        if !data.is_empty() {
+            events.push(data.join("\n"));
+        }
+    }
+    events
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http;
+
+    const BASE64_PNG: &str = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+
+    #[test]
+    fn test_detects_thumbnail_tool_name() {
+        let detected = detect_base64_image("pcli2_asset_thumbnail", &json!(BASE64_PNG));
+        assert_eq!(detected, Some((BASE64_PNG.to_string(), "image/png")));
+    }
+
+    #[test]
+    fn test_detects_jpeg_mime_type() {
+        let base64_jpeg = "/9j/4AAQSkZJRgABAQEASABIAAD/2wBDAAgGBgcGBQgHBwcJCQgKDBQNDAsLDBkSEw8UHRofHh0aHBwgJC4nICIsIxwcKDcpLDAxNDQ0Hyc5PTgyPC4zNDL/2wBDAQkJCQwLDBgNDRgyIRwhMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjIyMjL/wAARCAABAAEDASIAAhEBAxEB/8QAFQABAQAAAAAAAAAAAAAAAAAAAAn/xAAUEAEAAAAAAAAAAAAAAAAAAAAA/8QAFQEBAQAAAAAAAAAAAAAAAAAAAAX/xAAUEQEAAAAAAAAAAAAAAAAAAAAA/9oADAMBEQCEAwEPwAB//9k=";
+        let detected = detect_base64_image("pcli2_asset_thumbnail", &json!(base64_jpeg));
+        assert_eq!(detected.map(|(_, mime_type)| mime_type), Some("image/jpeg"));
+    }
+
+    #[test]
+    fn test_rejects_non_image_tool() {
+        // Tool name doesn't contain 'thumbnail' or 'image'
+        assert_eq!(detect_base64_image("pcli2_folder_list", &json!(BASE64_PNG)), None);
+    }
+
+    #[test]
+    fn test_rejects_invalid_base64() {
+        let detected = detect_base64_image("pcli2_asset_thumbnail", &json!("This is not valid base64!!!"));
+        assert_eq!(detected, None);
+    }
+
+    #[test]
+    fn test_handles_object_result_with_data_field() {
+        let result_value = json!({"data": BASE64_PNG, "other_field": "value"});
+        let detected = detect_base64_image("pcli2_asset_thumbnail", &result_value);
+        assert_eq!(detected, Some((BASE64_PNG.to_string(), "image/png")));
+    }
+
+    #[test]
+    fn test_handles_object_result_with_text_field() {
+        let detected = detect_base64_image("pcli2_image_get", &json!({"text": BASE64_PNG}));
+        assert_eq!(detected, Some((BASE64_PNG.to_string(), "image/png")));
+    }
+
+    #[test]
+    fn test_take_sse_events() {
+        let mut buffer = b"event: message\r\ndata: {\"a\":1}\r\n\r\n: keep-alive\n\ndata: line one\ndata: line two\n\ndata: partial".to_vec();
+        let events = take_sse_events(&mut buffer);
+
+        assert_eq!(events, vec!["{\"a\":1}", "line one\nline two"]);
+        assert_eq!(buffer, b"data: partial");
+    }
+
+    #[test]
+    fn test_sse_characters_split_across_chunks() {
+        let event = "data: 部品.stl\n\n".as_bytes();
+        let split = event.iter().position(|&b| b >= 0x80).unwrap() + 1;
+        let mut buffer = event[..split].to_vec();
+        assert!(take_sse_events(&mut buffer).is_empty());
+
+        buffer.extend_from_slice(&event[split..]);
+        assert_eq!(take_sse_events(&mut buffer), vec!["部品.stl"]);
+    }
+
+    /// A request received by the stub MCP server
+    #[derive(Debug)]
+    struct StubRequest {
+        method: String,
+        /// Request line and headers, lowercased
+        head: String,
+        session_id: Option<String>,
+        body: Value,
+    }
+
+    /// A running stub server
+    struct Stub {
+        url: String,
+        requests: Arc<Mutex<Vec<StubRequest>>>,
+        /// Most tool calls seen in flight at once
+        max_in_flight: Arc<std::sync::atomic::AtomicUsize>,
+    }
+
+    /// Streamable HTTP MCP server stub: assigns a session, answers tools/list
+    /// over SSE (after a progress notification), answers tool calls after a
+    /// delay and records every request
+    async fn stub_server() -> Stub {
+        use std::sync::atomic::AtomicUsize;
+
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let log = requests.clone();
+        let max = max_in_flight.clone();
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let addr = test_http::serve(move |request| {
+            let body = request.json();
+            log.lock().unwrap().push(StubRequest {
+                method: request.method().to_string(),
+                head: request.head.to_ascii_lowercase(),
+                session_id: request.header("mcp-session-id").map(str::to_string),
+                body: body.clone(),
+            });
+            let (max, in_flight) = (max.clone(), in_flight.clone());
+            async move {
+                match body.get("method").and_then(Value::as_str) {
+                    Some("initialize") => {
+                        let reply = json!({"jsonrpc": "2.0", "id": body["id"], "result": {
+                            "protocolVersion": "2025-06-18",
+                            "capabilities": {"tools": {}},
+                            "serverInfo": {"name": "stub", "version": "1.0"}
+                        }})
+                        .to_string();
+                        format!(
+                            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nMcp-Session-Id: abc123\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                            reply.len(),
+                            reply
+                        )
+                    }
+                    Some("tools/list") => test_http::event_stream(&[
+                        json!({"jsonrpc": "2.0", "method": "notifications/progress", "params": {"progressToken": 1, "progress": 1}}),
+                        json!({"jsonrpc": "2.0", "id": "srv-1", "method": "ping"}),
+                        json!({"jsonrpc": "2.0", "id": body["id"], "result": {"tools": [
+                            {"name": "echo", "description": "Echo", "inputSchema": {"type": "object"}}
+                        ]}}),
+                    ]),
+                    Some("tools/call") => {
+                        let now = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                        max.fetch_max(now, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(150)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        test_http::json(&json!({"jsonrpc": "2.0", "id": body["id"], "result": {
+                            "content": [{"type": "text", "text": body["params"]["arguments"]["n"].to_string()}]
+                        }}))
+                    }
+                    Some(method) if method.starts_with("resources/") || method.starts_with("prompts/") => {
+                        test_http::json(&json!({"jsonrpc": "2.0", "id": body["id"], "result": stub_result(method, &body["params"])}))
+                    }
+                    _ => test_http::ACCEPTED.to_string(),
+                }
+            }
+        })
+        .await;
+        Stub {
+            url: format!("http://{}/mcp", addr),
+            requests,
+            max_in_flight,
+        }
+    }
+
+    /// Results of the stub's resource and prompt methods; resources/list has two pages
+    fn stub_result(method: &str, params: &Value) -> Value {
+        match method {
+            "resources/list" if params.get("cursor").is_none() => json!({
+                "resources": [{"uri": "pcli2://folders", "name": "folders"}],
+                "nextCursor": "page2"
+            }),
+            "resources/list" => json!({"resources": [{"uri": "pcli2://tenants", "name": "tenants"}]}),
+            "resources/templates/list" => json!({
+                "resourceTemplates": [{"uriTemplate": "pcli2://assets/{id}", "name": "asset"}]
+            }),
+            "resources/read" => json!({
+                "contents": [{"uri": params["uri"], "mimeType": "text/plain", "text": "Bracket\nHousing"}]
+            }),
+            "prompts/list" => json!({"prompts": [{
+                "name": "summarize-folder",
+                "description": "Summarize a folder",
+                "arguments": [{"name": "folder", "required": true}]
+            }]}),
+            "prompts/get" => json!({"messages": [{
+                "role": "user",
+                "content": {"type": "text", "text": format!("Summarize {}", params["arguments"]["folder"])}
+            }]}),
+            _ => json!({}),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_streamable_http_session() {
+        let Stub { url, requests, .. } = stub_server().await;
+        let client = McpClient::new("stub", &url);
+
+        client.initialize().await.unwrap();
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+        client.shutdown().await;
+
+        let requests = requests.lock().unwrap();
+        let methods: Vec<_> = requests
+            .iter()
+            .map(|r| {
+                let rpc = r.body.get("method").and_then(Value::as_str).unwrap_or("");
+                format!("{} {}", r.method, rpc).trim().to_string()
+            })
+            .collect();
+        // The server's ping on the tools/list stream is answered with a POST
+        assert_eq!(
+            methods,
+            vec!["POST initialize", "POST notifications/initialized", "POST tools/list", "POST", "DELETE"]
+        );
+        assert_eq!(requests[3].body, json!({"jsonrpc": "2.0", "id": "srv-1", "result": {}}));
+        // The session id is only sent after the server assigned it
+        assert_eq!(requests[0].session_id, None);
+        assert!(requests[1..].iter().all(|r| r.session_id.as_deref() == Some("abc123")));
+    }
+
+    /// A stdio server that answers initialize and one tools/list, then exits
+    #[cfg(unix)]
+    fn stdio_stub() -> McpServerConfig {
+        let script = r#"
+id_of() { echo "$1" | sed 's/.*"id":\([0-9]*\).*/\1/'; }
+while read -r line; do
+  case "$line" in
+    *'"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$(id_of "$line")"',"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"stub","version":"1"}}}' ;;
+    *'"tools/list"'*)
+      echo "listing tools" >&2
+      echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"info","data":"hi"}}'
+      echo '{"jsonrpc":"2.0","id":'"$(id_of "$line")"',"result":{"tools":[{"name":"'"$TOOL_NAME"'","description":"'"$(pwd)"'","inputSchema":{"type":"object"}}]}}'
+      exit 0 ;;
+  esac
+done
+"#;
+        let mut server = McpServerConfig::stdio(
+            "stub".to_string(),
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+        );
+        server.env.insert("TOOL_NAME".to_string(), "echo".to_string());
+        server.cwd = Some(std::env::temp_dir());
+        server
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stdio_server_is_restarted_after_exit() {
+        let client = McpClient::from_config(&stdio_stub()).unwrap();
+        client.initialize().await.unwrap();
+
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].name, "echo");
+        let cwd = std::env::temp_dir().canonicalize().unwrap();
+        assert_eq!(
+            tools[0].description.as_deref().map(std::path::PathBuf::from),
+            Some(cwd)
+        );
+
+        // The stub exits after listing tools; the next request restarts it
+        let tools = client.list_tools().await.unwrap();
+        assert_eq!(tools[0].name, "echo");
+        client.shutdown().await;
+    }
+
+    #[test]
+    fn test_server_needs_url_or_command() {
+        let mut server = McpServerConfig::http("both".to_string(), "http://localhost:8080/mcp".to_string());
+        server.command = Some("npx".to_string());
+        assert!(McpClient::from_config(&server).is_err());
+
+        server.url = None;
+        server.command = None;
+        assert!(McpClient::from_config(&server).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_token_and_headers_are_sent() {
+        let Stub { url, requests, .. } = stub_server().await;
+        let mut server = McpServerConfig::http("stub".to_string(), url);
+        server.token_cmd = Some("echo s3cret".to_string());
+        server.headers.insert("X-Tenant".to_string(), "acme".to_string());
+        let client = McpClient::from_config(&server).unwrap();
+
+        client.initialize().await.unwrap();
+        client.shutdown().await;
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 3);
+        for request in requests.iter() {
+            assert!(request.head.contains("authorization: bearer s3cret"), "{}", request.head);
+            assert!(request.head.contains("x-tenant: acme"), "{}", request.head);
+        }
+    }
+
+    #[test]
+    fn test_invalid_header_is_an_error() {
+        let mut server = McpServerConfig::http("stub".to_string(), "http://localhost:9999".to_string());
+        server.headers.insert("Bad Header".to_string(), "x".to_string());
+        assert!(McpClient::from_config(&server).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_tool_calls_respect_the_limit() {
+        let stub = stub_server().await;
+        let mut server = McpServerConfig::http("stub".to_string(), stub.url.clone());
+        server.max_concurrency = 2;
+        let client = McpClient::from_config(&server).unwrap();
+        client.initialize().await.unwrap();
+
+        let calls = (0..4).map(|n| client.call_tool("echo", json!({ "n": n })));
+        let results = futures::future::join_all(calls).await;
+        for (n, result) in results.into_iter().enumerate() {
+            let text = result.unwrap().into_text(|_| String::new());
+            assert_eq!(text, n.to_string());
+        }
+        assert_eq!(stub.max_in_flight.load(Ordering::SeqCst), 2);
+
+        // Every request got its own id
+        let requests = stub.requests.lock().unwrap();
+        let mut ids: Vec<u64> = requests.iter().filter_map(|r| r.body["id"].as_u64()).collect();
+        let count = ids.len();
+        ids.sort();
+        ids.dedup();
+        assert_eq!(ids.len(), count);
+        assert_eq!(count, 5);
+    }
+
+    /// A stdio server that reads two tool calls before answering them in reverse order
+    #[cfg(unix)]
+    fn reordering_stub() -> McpServerConfig {
+        let script = r#"
+id_of() { echo "$1" | sed 's/.*"id":\([0-9]*\).*/\1/'; }
+reply() {
+  n=$(echo "$1" | sed 's/.*"n":"\([a-z]*\)".*/\1/')
+  echo '{"jsonrpc":"2.0","id":'"$(id_of "$1")"',"result":{"content":[{"type":"text","text":"'"$n"'"}]}}'
+}
+while read -r line; do
+  case "$line" in
+    *'"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$(id_of "$line")"',"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{}},"serverInfo":{"name":"stub","version":"1"}}}' ;;
+    *'"tools/call"'*)
+      read -r second
+      reply "$second"
+      reply "$line" ;;
+  esac
+done
+"#;
+        McpServerConfig::stdio(
+            "stub".to_string(),
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+        )
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stdio_replies_are_matched_by_id() {
+        let client = McpClient::from_config(&reordering_stub()).unwrap();
+        client.initialize().await.unwrap();
+
+        let (first, second) = tokio::time::timeout(
+            Duration::from_secs(5),
+            futures::future::join(
+                client.call_tool("echo", json!({ "n": "first" })),
+                client.call_tool("echo", json!({ "n": "second" })),
+            ),
+        )
+        .await
+        .expect("calls should run concurrently");
+        assert_eq!(first.unwrap().parts, vec![OutputPart::Text("first".to_string())]);
+        assert_eq!(second.unwrap().parts, vec![OutputPart::Text("second".to_string())]);
+        client.shutdown().await;
+    }
+
+    /// A stdio server that reports progress, a log message and a tool list change during a call
+    #[cfg(unix)]
+    fn notifying_stub() -> McpServerConfig {
+        let script = r#"
+id_of() { echo "$1" | sed 's/.*"id":\([0-9]*\).*/\1/'; }
+while read -r line; do
+  case "$line" in
+    *'"initialize"'*)
+      echo '{"jsonrpc":"2.0","id":'"$(id_of "$line")"',"result":{"protocolVersion":"2024-11-05","capabilities":{"tools":{"listChanged":true}},"serverInfo":{"name":"stub","version":"1"}}}' ;;
+    *'"tools/call"'*)
+      token=$(echo "$line" | sed 's/.*"progressToken":\([0-9]*\).*/\1/')
+      echo '{"jsonrpc":"2.0","method":"notifications/progress","params":{"progressToken":'"$token"',"progress":1,"total":4,"message":"Uploading"}}'
+      echo '{"jsonrpc":"2.0","method":"notifications/message","params":{"level":"warning","logger":"upload","data":"slow disk"}}'
+      echo '{"jsonrpc":"2.0","method":"notifications/tools/list_changed"}'
+      sleep 0.3
+      echo '{"jsonrpc":"2.0","id":'"$(id_of "$line")"',"result":{"content":[{"type":"text","text":"done"}]}}' ;;
+  esac
+done
+"#;
+        McpServerConfig::stdio(
+            "stub".to_string(),
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+        )
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_stdio_notifications_are_handled() {
+        let client = McpClient::from_config(&notifying_stub()).unwrap();
+        client.initialize().await.unwrap();
+        assert!(!client.take_tools_changed());
+
+        let call = tokio::spawn({
+            let client = client.clone();
+            async move { client.call_tool("upload", json!({})).await }
+        });
+        let progress = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Some(progress) = client.tool_progress().pop() {
+                    return progress;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("progress should be reported");
+        assert_eq!(progress.tool, "upload");
+        assert_eq!(progress.fraction(), Some(0.25));
+        assert_eq!(progress.message.as_deref(), Some("Uploading"));
+
+        let output = call.await.unwrap().unwrap();
+        assert_eq!(output.parts, vec![OutputPart::Text("done".to_string())]);
+        // Progress is dropped once the call finishes; the list change is reported once
+        assert!(client.tool_progress().is_empty());
+        assert!(client.take_tools_changed());
+        assert!(!client.take_tools_changed());
+        client.shutdown().await;
+    }
+
+    /// HTTP MCP server that announces list changes and sends one, and a request
+    /// of its own, over the GET stream; returns the URL and the replies it got
+    async fn streaming_server() -> (String, Arc<Mutex<Vec<Value>>>) {
+        let replies = Arc::new(Mutex::new(Vec::new()));
+        let received = replies.clone();
+        let addr = test_http::serve(move |request| {
+            let body = request.json();
+            let response = if request.method() == "GET" {
+                test_http::event_stream(&[
+                    json!({"jsonrpc": "2.0", "method": "notifications/tools/list_changed"}),
+                    json!({"jsonrpc": "2.0", "id": 7, "method": "sampling/createMessage"}),
+                ])
+            } else if body.get("id").is_none() || body.get("method").is_none() {
+                if body.get("method").is_none() && !body.is_null() {
+                    received.lock().unwrap().push(body);
+                }
+                test_http::ACCEPTED.to_string()
+            } else {
+                let result = json!({
+                    "protocolVersion": "2025-06-18",
+                    "capabilities": {"tools": {"listChanged": true}},
+                    "serverInfo": {"name": "streaming", "version": "1"}
+                });
+                test_http::json(&json!({"jsonrpc": "2.0", "id": body["id"], "result": result}))
+            };
+            async move { response }
+        })
+        .await;
+        (format!("http://{}/mcp", addr), replies)
+    }
+
+    #[tokio::test]
+    async fn test_http_notifications_are_read_from_get_stream() {
+        let (url, replies) = streaming_server().await;
+        let client = McpClient::new("streaming", &url);
+        client.initialize().await.unwrap();
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while !client.take_tools_changed() || replies.lock().unwrap().is_empty() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("list change should arrive over the GET stream");
+        // The server's request is answered over POST
+        let reply = replies.lock().unwrap()[0].clone();
+        assert_eq!(reply["id"], 7);
+        assert_eq!(reply["error"]["code"], -32601);
+        client.shutdown().await;
+    }
+
+    #[tokio::test]
+    async fn test_protocol_version_is_negotiated() {
+        let Stub { url, requests, .. } = stub_server().await;
+        let client = McpClient::new("stub", &url);
+        assert!(client.server_info().is_none());
+
+        client.initialize().await.unwrap();
+        let info = client.server_info().unwrap();
+        assert_eq!(info.protocol_version.to_string(), "2025-06-18");
+        assert_eq!(info.server_info.name, "stub");
+        assert!(client.supports_tools());
+        client.list_tools().await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests[0].body["params"]["protocolVersion"], PROTOCOL_VERSIONS[0]);
+        // The version header is only sent once it has been negotiated
+        assert!(!requests[0].head.contains("mcp-protocol-version"));
+        assert!(requests[2].head.contains("mcp-protocol-version: 2025-06-18"));
+    }
+
+    #[test]
+    fn test_initialize_result_is_checked() {
+        let result = |version: &str, capabilities: Value| {
+            json!({
+                "protocolVersion": version,
+                "capabilities": capabilities,
+                "serverInfo": {"name": "stub", "version": "1"}
+            })
+        };
+
+        let info = parse_initialize_result(result("2024-11-05", json!({"resources": {}, "logging": {}}))).unwrap();
+        assert_eq!(capability_names(&info.capabilities), vec!["resources", "logging"]);
+        assert!(info.capabilities.tools.is_none());
+
+        let err = parse_initialize_result(result("2023-01-01", json!({}))).unwrap_err();
+        assert!(err.to_string().contains("2023-01-01"));
+        assert!(parse_initialize_result(json!({"capabilities": {}})).is_err());
+    }
+
+    #[test]
+    fn test_decode_tool_result() {
+        let png = "iVBORw0KGgoAAAANSUhEUgAAAAEAAAABCAYAAAAfFcSJAAAADUlEQVR42mNk+M9QDwADhgGAWjR9awAAAABJRU5ErkJggg==";
+        let output = decode_tool_result("pcli2_asset_get", json!({
+            "content": [
+                {"type": "text", "text": "Asset found"},
+                {"type": "image", "data": png, "mimeType": "image/png"},
+                {"type": "resource", "resource": {"uri": "file:///a.txt", "mimeType": "text/plain", "text": "hello"}},
+                {"type": "text", "text": "{\"id\": 7}"}
+            ],
+            "structuredContent": {"id": 7}
+        }));
+        assert!(!output.is_error);
+        assert_eq!(output.parts.len(), 4);
+
+        let mut attachments = Vec::new();
+        let text = output.into_text(|attachment| {
+            attachments.push(attachment);
+            format!("<{}>", attachments.len())
+        });
+        // The structured content is already serialized in the text, so it is not repeated
+        assert_eq!(text, "Asset found\n<1>\n<2>\n{\"id\": 7}");
+        assert!(attachments[0].is_image());
+        assert_eq!(attachments[0].size(), 70);
+        assert_eq!(attachments[1].uri.as_deref(), Some("file:///a.txt"));
+        assert_eq!(attachments[1].data, BASE64.encode("hello"));
+        assert_eq!(
+            attachments[1].summary(2),
+            "[attachment 2: resource file:///a.txt (text/plain, 5 bytes), stored for the user]"
+        );
+    }
+
+    #[test]
+    fn test_only_raster_images_are_images() {
+        let attachment = |mime_type: &str| Attachment {
+            mime_type: mime_type.to_string(),
+            data: BASE64.encode("<svg/>"),
+            uri: None,
+        };
+        assert!(attachment("image/png").is_image());
+        assert!(attachment("IMAGE/WEBP").is_image());
+        assert!(!attachment("image/svg+xml").is_image());
+        assert!(!attachment("image/bmp").is_image());
+        assert!(!attachment("image/tiff").is_image());
+        assert_eq!(
+            attachment("image/svg+xml").summary(1),
+            "[attachment 1: image/svg+xml, 6 bytes, stored for the user]"
+        );
+    }
+
+    #[test]
+    fn test_decode_errors_structured_content_and_thumbnails() {
+        let error = decode_tool_result("lookup", json!({
+            "content": [{"type": "text", "text": "Asset not found"}],
+            "isError": true
+        }));
+        assert!(error.is_error);
+        assert_eq!(error.into_text(|_| String::new()), "Asset not found");
+
+        let structured = decode_tool_result("lookup", json!({"structuredContent": {"count": 2}}));
+        assert_eq!(structured.into_text(|_| String::new()), "{\n  \"count\": 2\n}");
+
+        // Thumbnail tools that return base64 as text get an image attachment
+        let thumbnail = decode_tool_result("pcli2_asset_thumbnail", json!({
+            "content": [{"type": "text", "text": "/9j/4AAQSkZJRgABAQEASABIAAD"}]
+        }));
+        match &thumbnail.parts[..] {
+            [OutputPart::Attachment(attachment)] => assert_eq!(attachment.mime_type, "image/jpeg"),
+            parts => panic!("expected an image, got {:?}", parts),
+        }
+
+        // Anything else is passed through as JSON
+        let raw = decode_tool_result("lookup", json!({"answer": 42}));
+        assert_eq!(raw.parts, vec![OutputPart::Text("{\n  \"answer\": 42\n}".to_string())]);
+
+        // A bare base64 result from an image tool is an image too
+        let raw = decode_tool_result("pcli2_asset_thumbnail", json!(BASE64_PNG));
+        assert!(matches!(&raw.parts[..], [OutputPart::Attachment(a)] if a.mime_type == "image/png"));
+    }
+
+    #[tokio::test]
+    async fn test_resources_and_prompts() {
+        let Stub { url, .. } = stub_server().await;
+        let client = McpClient::new("stub", &url);
+        client.initialize().await.unwrap();
+
+        // Both pages are collected
+        let resources = client.list_resources().await.unwrap();
+        let uris: Vec<_> = resources.iter().map(|r| r.uri.as_str()).collect();
+        assert_eq!(uris, vec!["pcli2://folders", "pcli2://tenants"]);
+        let templates = client.list_resource_templates().await.unwrap();
+        assert_eq!(templates[0].uri_template, "pcli2://assets/{id}");
+
+        let contents = client.read_resource("pcli2://folders").await.unwrap();
+        let attachment = Attachment::from(contents[0].clone());
+        assert_eq!(attachment.uri.as_deref(), Some("pcli2://folders"));
+        assert_eq!(
+            attachment.resource_text(),
+            "<resource uri=\"pcli2://folders\" mimeType=\"text/plain\">\nBracket\nHousing\n</resource>"
+        );
+
+        let prompts = client.list_prompts().await.unwrap();
+        assert_eq!(prompt_usage(&prompts[0]), "Arguments: folder=<folder>");
+        let arguments = prompt_arguments(&prompts[0], "\"My Parts\"").unwrap();
+        let result = client.get_prompt("summarize-folder", &arguments).await.unwrap();
+        let (text, attachment) = prompt_content(result.messages[0].content.clone());
+        assert_eq!(text, "Summarize \"My Parts\"");
+        assert!(attachment.is_none());
+        client.shutdown().await;
+    }
+
+    #[test]
+    fn test_resource_mentions_and_prompt_arguments() {
+        assert_eq!(
+            resource_mentions("Compare @pcli2:pcli2://folders/1 and @docs:file:///a.md. Mail me@example.com"),
+            vec![("pcli2", "pcli2://folders/1"), ("docs", "file:///a.md")]
+        );
+
+        let prompt: Prompt = serde_json::from_value(json!({
+            "name": "report",
+            "arguments": [{"name": "folder", "required": true}, {"name": "depth"}]
+        }))
+        .unwrap();
+        let arguments = prompt_arguments(&prompt, r#"folder="My Parts" depth=2"#).unwrap();
+        assert_eq!(arguments["folder"], "My Parts");
+        assert_eq!(arguments["depth"], "2");
+        let err = prompt_arguments(&prompt, "depth=2").unwrap_err();
+        assert_eq!(err.to_string(), "Missing folder. Arguments: folder=<folder> [depth=<depth>]");
+        assert!(prompt_arguments(&prompt, "folder").is_err());
+    }
+}
@@ -0,0 +1,224 @@
+//! Ollama model discovery
+//!
+//! Lists installed models from `/api/tags` and enriches each one with the
+//! capabilities reported by `/api/show` (tool calling, vision).
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use serde_json::json;
+
+/// An installed Ollama model
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModelInfo {
+    /// Model name, e.g. "qwen2.5-coder:3b"
+    pub name: String,
+    /// Size on disk in bytes
+    pub size: u64,
+    /// Parameter count, e.g. "3.1B"
+    pub parameter_size: Option<String>,
+    /// Quantization level, e.g. "Q4_K_M"
+    pub quantization: Option<String>,
+    /// Capabilities from `/api/show` (`None` if the server does not report them)
+    pub capabilities: Option<Vec<String>>,
+}
+
+impl ModelInfo {
+    /// Whether the model supports tool calling (`None` if unknown)
+    pub fn supports_tools(&self) -> Option<bool> {
+        self.has_capability("tools")
+    }
+
+    /// Whether the model accepts image input (`None` if unknown)
+    pub fn supports_vision(&self) -> Option<bool> {
+        self.has_capability("vision")
+    }
+
+    fn has_capability(&self, capability: &str) -> Option<bool> {
+        self.capabilities
+            .as_ref()
+            .map(|caps| caps.iter().any(|c| c == capability))
+    }
+
+    /// Human-readable size on disk
+    pub fn size_label(&self) -> String {
+        const GB: f64 = 1024.0 * 1024.0 * 1024.0;
+        const MB: f64 = 1024.0 * 1024.0;
+        let size = self.size as f64;
+        if size >= GB {
+            format!("{:.1} GB", size / GB)
+        } else {
+            format!("{:.0} MB", size / MB)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsResponse {
+    #[serde(default)]
+    models: Vec<TagsModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagsModel {
+    name: String,
+    #[serde(default)]
+    size: u64,
+    #[serde(default)]
+    details: ModelDetails,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ModelDetails {
+    parameter_size: Option<String>,
+    quantization_level: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShowResponse {
+    capabilities: Option<Vec<String>>,
+}
+
+/// List installed models, with capabilities from `/api/show`
+///
+/// `base_url` is the Ollama base URL (see `Config::ollama_url`). A failing
+/// `/api/show` call only leaves that model's capabilities unknown.
+pub async fn list_models(base_url: &str) -> Result<Vec<ModelInfo>> {
+    let client = reqwest::Client::new();
+
+    let tags: TagsResponse = client
+        .get(format!("{}/api/tags", base_url))
+        .send()
+        .await
+        .with_context(|| format!("Failed to connect to Ollama at {}", base_url))?
+        .error_for_status()
+        .context("Ollama returned an error listing models")?
+        .json()
+        .await
+        .context("Invalid /api/tags response")?;
+
+    let shows = tags
+        .models
+        .iter()
+        .map(|model| async { show(&client, base_url, &model.name).await.ok() });
+    let shows = futures::future::join_all(shows).await;
+
+    let mut models: Vec<ModelInfo> = tags
+        .models
+        .into_iter()
+        .zip(shows)
+        .map(|(model, show)| ModelInfo {
+            name: model.name,
+            size: model.size,
+            parameter_size: model.details.parameter_size,
+            quantization: model.details.quantization_level,
+            capabilities: show.and_then(|s| s.capabilities),
+        })
+        .collect();
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(models)
+}
+
+/// Find an installed model by name; a name without a tag means `:latest`, as in Ollama
+pub fn find_model<'a>(models: &'a [ModelInfo], name: &str) -> Option<&'a ModelInfo> {
+    models
+        .iter()
+        .find(|m| m.name == name)
+        .or_else(|| models.iter().find(|m| !name.contains(':') && m.name == format!("{}:latest", name)))
+}
+
+/// Capabilities of one model from `/api/show` (`None` if the server does not report them)
+pub async fn model_capabilities(base_url: &str, model: &str) -> Result<Option<Vec<String>>> {
+    let show = show(&reqwest::Client::new(), base_url, model).await?;
+    Ok(show.capabilities)
+}
+
+async fn show(client: &reqwest::Client, base_url: &str, model: &str) -> Result<ShowResponse> {
+    client
+        .post(format!("{}/api/show", base_url))
+        .json(&json!({ "model": model }))
+        .send()
+        .await
+        .with_context(|| format!("Failed to connect to Ollama at {}", base_url))?
+        .error_for_status()
+        .with_context(|| format!("Ollama returned an error describing {}", model))?
+        .json()
+        .await
+        .context("Invalid /api/show response")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_http;
+
+    /// Serve canned `/api/tags` and `/api/show` responses until the test ends
+    async fn stub_ollama() -> String {
+        let addr = test_http::serve(|request| async move {
+            let body = if request.head.starts_with("GET /api/tags") {
+                json!({ "models": [
+                    { "name": "qwen2.5-coder:3b", "size": 1_929_912_432u64,
+                      "details": { "parameter_size": "3.1B", "quantization_level": "Q4_K_M" } },
+                    { "name": "gemma:2b", "size": 1_678_456_656u64,
+                      "details": { "parameter_size": "3B", "quantization_level": "Q4_0" } }
+                ]})
+            } else if request.body.contains("qwen2.5-coder") {
+                json!({ "capabilities": ["completion", "tools"] })
+            } else if request.body.contains("llava") {
+                json!({ "capabilities": ["completion", "vision"] })
+            } else {
+                json!({ "capabilities": ["completion"] })
+            };
+            test_http::json(&body)
+        })
+        .await;
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_list_models_with_capabilities() {
+        let base_url = stub_ollama().await;
+        let models = list_models(&base_url).await.unwrap();
+
+        assert_eq!(models.len(), 2);
+        assert_eq!(models[0].name, "gemma:2b");
+        assert_eq!(models[0].supports_tools(), Some(false));
+        assert_eq!(models[1].name, "qwen2.5-coder:3b");
+        assert_eq!(models[1].supports_tools(), Some(true));
+        assert_eq!(models[1].quantization.as_deref(), Some("Q4_K_M"));
+        assert_eq!(models[1].size_label(), "1.8 GB");
+        assert_eq!(models[1].supports_vision(), Some(false));
+    }
+
+    #[test]
+    fn test_find_model() {
+        let model = |name: &str| ModelInfo {
+            name: name.to_string(),
+            size: 0,
+            parameter_size: None,
+            quantization: None,
+            capabilities: None,
+        };
+        let models = vec![model("llama3.2:latest"), model("qwen2.5-coder:3b")];
+        assert_eq!(find_model(&models, "qwen2.5-coder:3b").unwrap().name, "qwen2.5-coder:3b");
+        assert_eq!(find_model(&models, "llama3.2").unwrap().name, "llama3.2:latest");
+        assert!(find_model(&models, "qwen2.5-coder").is_none());
+        assert!(find_model(&models, "qwen2.5-coder:7b").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_model_capabilities() {
+        let base_url = stub_ollama().await;
+        let capabilities = model_capabilities(&base_url, "llava:7b").await.unwrap();
+        assert_eq!(capabilities, Some(vec!["completion".to_string(), "vision".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_host_is_an_error() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        assert!(list_models(&format!("http://{}", addr)).await.is_err());
+    }
+}
@@ -0,0 +1,333 @@
+//! Chat session persistence
+//!
+//! Each session is a JSON lines file under `$XDG_DATA_HOME/pcli2-rig/sessions`:
+//! a header line with the session metadata, then one line per [`ChatMessage`].
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail, ensure};
+use chrono::{DateTime, Local, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::agent::{ChatMessage, MessageRole};
+
+/// Session metadata, stored as the first line of the session file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    /// Session id (also the file name)
+    pub id: String,
+    /// Optional name given with `/save <name>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Model used when the session was last saved
+    pub model: String,
+    /// MCP servers connected when the session was last saved
+    #[serde(default)]
+    pub mcp_servers: Vec<String>,
+}
+
+impl SessionInfo {
+    /// Start a new session, with an id based on the current time
+    ///
+    /// A random suffix keeps sessions started in the same second (e.g. by two
+    /// instances) from overwriting each other.
+    pub fn new(model: &str) -> Self {
+        use std::hash::{BuildHasher, Hasher};
+
+        let now = Utc::now();
+        let suffix = std::collections::hash_map::RandomState::new().build_hasher().finish() & 0xff_ffff;
+        Self {
+            id: format!("{}-{:06x}", now.with_timezone(&Local).format("%Y%m%d-%H%M%S"), suffix),
+            name: None,
+            created_at: now,
+            updated_at: now,
+            model: model.to_string(),
+            mcp_servers: Vec::new(),
+        }
+    }
+
+    /// Name if set, otherwise the id
+    pub fn label(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.id)
+    }
+}
+
+/// A saved session with its messages
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub info: SessionInfo,
+    pub messages: Vec<ChatMessage>,
+}
+
+/// A saved session, without its messages (for listing)
+#[derive(Debug, Clone)]
+pub struct SessionSummary {
+    pub info: SessionInfo,
+    /// File the session is stored in
+    pub path: PathBuf,
+    pub message_count: usize,
+}
+
+/// One line of a session file
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+enum Record {
+    Session(SessionInfo),
+    Message(ChatMessage),
+}
+
+/// Directory of saved sessions
+#[derive(Debug, Clone)]
+pub struct SessionStore {
+    dir: PathBuf,
+}
+
+impl SessionStore {
+    /// Store in a specific directory
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Store under the platform data dir (`~/.local/share/pcli2-rig/sessions` on Linux)
+    pub fn default_location() -> Option<Self> {
+        dirs::data_dir().map(|dir| Self::new(dir.join("pcli2-rig").join("sessions")))
+    }
+
+    /// File for a session id; ids that could point outside the store are rejected
+    fn path_for(&self, id: &str) -> Result<PathBuf> {
+        ensure!(
+            !id.is_empty() && !id.contains(['/', '\\']) && !id.contains(".."),
+            "Invalid session id '{}'",
+            id
+        );
+        Ok(self.dir.join(format!("{}.jsonl", id)))
+    }
+
+    /// Write a session, replacing any previous version of it (UI notices are left out)
+    pub fn save(&self, info: &SessionInfo, messages: &[ChatMessage]) -> Result<PathBuf> {
+        fs::create_dir_all(&self.dir)
+            .with_context(|| format!("Failed to create {}", self.dir.display()))?;
+
+        let mut content = serde_json::to_string(&Record::Session(info.clone()))?;
+        content.push('\n');
+        for msg in messages.iter().filter(|msg| msg.role != MessageRole::Notice) {
+            content.push_str(&serde_json::to_string(&Record::Message(msg.clone()))?);
+            content.push('\n');
+        }
+
+        // Write to a temporary file first so a crash never leaves a truncated session
+        let path = self.path_for(&info.id)?;
+        let tmp = path.with_extension("jsonl.tmp");
+        fs::write(&tmp, content).with_context(|| format!("Failed to write {}", tmp.display()))?;
+        fs::rename(&tmp, &path).with_context(|| format!("Failed to write {}", path.display()))?;
+
+        Ok(path)
+    }
+
+    /// Load a session by id, unique id prefix, or name
+    ///
+    /// Only sessions found by [`SessionStore::list`] are considered, so the query
+    /// is never used as a path.
+    pub fn load(&self, query: &str) -> Result<Session> {
+        let sessions = self.list()?;
+        if let Some(summary) = sessions.iter().find(|s| s.info.id == query) {
+            return read_session(&summary.path);
+        }
+
+        let matches: Vec<&SessionSummary> = sessions
+            .iter()
+            .filter(|s| s.info.id.starts_with(query) || s.info.name.as_deref() == Some(query))
+            .collect();
+        match matches.as_slice() {
+            [] => bail!("No saved session matches '{}'", query),
+            [summary] => read_session(&summary.path),
+            _ => bail!(
+                "'{}' matches {} sessions: {}",
+                query,
+                matches.len(),
+                matches
+                    .iter()
+                    .map(|s| s.info.id.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        }
+    }
+
+    /// Load the most recently updated session
+    pub fn latest(&self) -> Result<Option<Session>> {
+        match self.list()?.first() {
+            Some(summary) => read_session(&summary.path).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// List saved sessions, most recently updated first
+    pub fn list(&self) -> Result<Vec<SessionSummary>> {
+        if !self.dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let mut sessions = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "jsonl") {
+                continue;
+            }
+            match read_summary(&path) {
+                Ok(summary) => sessions.push(summary),
+                Err(e) => tracing::warn!("Skipping session {}: {:#}", path.display(), e),
+            }
+        }
+        sessions.sort_by_key(|s| std::cmp::Reverse(s.info.updated_at));
+
+        Ok(sessions)
+    }
+}
+
+/// Read a full session file
+fn read_session(path: &Path) -> Result<Session> {
+    let file = fs::File::open(path).with_context(|| format!("Failed to open {}", path.display()))?;
+    let mut info = None;
+    let mut messages = Vec::new();
+
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: Record = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}: invalid session record", path.display(), i + 1))?;
+        match record {
+            Record::Session(header) => info = Some(header),
+            Record::Message(msg) => messages.push(msg),
+        }
+    }
+
+    let info = info.with_context(|| format!("{} has no session header", path.display()))?;
+    Ok(Session { info, messages })
+}
+
+/// Read the header of a session file and count its messages
+fn read_summary(path: &Path) -> Result<SessionSummary> {
+    let file = fs::File::open(path)?;
+    let mut lines = BufReader::new(file).lines();
+    let header = lines.next().context("empty session file")??;
+    let Record::Session(info) = serde_json::from_str(&header)? else {
+        bail!("missing session header");
+    };
+    let message_count = lines.map_while(|l| l.ok()).filter(|l| !l.trim().is_empty()).count();
+
+    Ok(SessionSummary {
+        info,
+        path: path.to_path_buf(),
+        message_count,
+    })
+}
+
+/// Whether a chat history has anything worth saving (at least one user message)
+pub fn has_conversation(messages: &[ChatMessage]) -> bool {
+    messages.iter().any(|msg| msg.role == MessageRole::User)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::agent::{Agent, StreamEvent, ToolCallRequest};
+    use crate::config::Config;
+
+    fn temp_store(name: &str) -> SessionStore {
+        let dir = std::env::temp_dir().join(format!("pcli2-rig-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        SessionStore::new(dir)
+    }
+
+    fn conversation() -> Vec<ChatMessage> {
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.add_user_message("What is in this folder?".to_string());
+        agent.push_stream_event(StreamEvent::ToolCall(ToolCallRequest {
+            tool_name: "list_directory".to_string(),
+            arguments: r#"{"path":"."}"#.to_string(),
+            call_id: "list_directory".to_string(),
+        }));
+        agent.push_stream_event(StreamEvent::ToolResult {
+            call_id: "list_directory".to_string(),
+            content: "Cargo.toml".to_string(),
+            attachments: Vec::new(),
+        });
+        agent.push_stream_event(StreamEvent::Text("Just a `Cargo.toml`.".to_string()));
+        agent.chat_history().to_vec()
+    }
+
+    #[test]
+    fn test_save_and_load_roundtrip() {
+        let store = temp_store("roundtrip");
+        let mut info = SessionInfo::new("qwen2.5-coder:3b");
+        info.mcp_servers = vec!["pcli2".to_string()];
+        let messages = conversation();
+
+        // UI notices are not saved
+        let mut agent = Agent::new(&Config::default()).unwrap();
+        agent.add_notice("Type /help for available commands".to_string());
+        let mut shown = agent.chat_history().to_vec();
+        shown.extend(messages.iter().cloned());
+
+        let path = store.save(&info, &shown).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 5);
+
+        let session = store.load(&info.id).unwrap();
+        assert_eq!(session.info, info);
+        assert_eq!(session.messages, messages);
+
+        let summaries = store.list().unwrap();
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].message_count, 4);
+    }
+
+    #[test]
+    fn test_load_by_prefix_name_and_latest() {
+        let store = temp_store("lookup");
+        let mut older = SessionInfo::new("m");
+        older.id = "20260101-090000".to_string();
+        older.updated_at = Utc::now() - chrono::Duration::days(1);
+        let mut newer = SessionInfo::new("m");
+        newer.id = "20260102-090000".to_string();
+        newer.name = Some("asset-audit".to_string());
+
+        store.save(&older, &conversation()).unwrap();
+        store.save(&newer, &conversation()).unwrap();
+
+        assert_eq!(store.load("20260101").unwrap().info.id, older.id);
+        assert_eq!(store.load("asset-audit").unwrap().info.id, newer.id);
+        assert!(store.load("2026").is_err());
+        assert!(store.load("missing").is_err());
+        assert_eq!(store.latest().unwrap().unwrap().info.id, newer.id);
+    }
+
+    #[test]
+    fn test_ids_cannot_escape_the_store() {
+        let store = temp_store("escape");
+        let outside = store.dir.with_extension("jsonl");
+        let info = SessionInfo::new("m");
+        fs::write(&outside, format!("{}\n", serde_json::to_string(&Record::Session(info)).unwrap())).unwrap();
+
+        let name = format!("../{}", store.dir.file_name().unwrap().to_string_lossy());
+        assert!(store.load(&name).is_err());
+
+        let mut info = SessionInfo::new("m");
+        info.id = name;
+        assert!(store.save(&info, &conversation()).is_err());
+        fs::remove_file(outside).unwrap();
+    }
+
+    #[test]
+    fn test_new_sessions_get_distinct_ids() {
+        let first = SessionInfo::new("m");
+        let second = SessionInfo::new("m");
+        assert_ne!(first.id, second.id);
+    }
+}
@@ -0,0 +1,157 @@
+//! HTTP stub servers for tests
+//!
+//! Just enough HTTP/1.1 for the clients under test: one request per connection,
+//! bodies sized by `Content-Length`, and responses that close the connection.
+
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::Value;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+/// How long [`serve`] keeps a connection open after answering, so event
+/// streams stay open like on a real server
+const LINGER: Duration = Duration::from_secs(5);
+
+/// `202 Accepted` without a body
+pub const ACCEPTED: &str = "HTTP/1.1 202 Accepted\r\nContent-Length: 0\r\nConnection: close\r\n\r\n";
+
+/// A request received by a stub server
+#[derive(Debug, Clone)]
+pub struct Request {
+    /// Request line and headers
+    pub head: String,
+    pub body: String,
+}
+
+impl Request {
+    /// Request method, e.g. `POST`
+    pub fn method(&self) -> &str {
+        self.head.split(' ').next().unwrap_or_default()
+    }
+
+    /// Value of a header, matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.head.lines().skip(1).find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            key.trim().eq_ignore_ascii_case(name).then(|| value.trim())
+        })
+    }
+
+    /// Body parsed as JSON, or `Value::Null`
+    pub fn json(&self) -> Value {
+        serde_json::from_str(&self.body).unwrap_or(Value::Null)
+    }
+}
+
+impl std::fmt::Display for Request {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}\r\n\r\n{}", self.head, self.body)
+    }
+}
+
+/// Read one request, including its body; `None` if the connection closes first
+pub async fn read_request(socket: &mut TcpStream) -> Option<Request> {
+    let mut request = Vec::new();
+    let mut buf = [0u8; 4096];
+    loop {
+        let n = socket.read(&mut buf).await.ok()?;
+        if n == 0 {
+            return None;
+        }
+        request.extend_from_slice(&buf[..n]);
+        let Some(end) = request.windows(4).position(|w| w == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&request[..end]).to_string();
+        let length = head
+            .lines()
+            .find_map(|line| {
+                let (key, value) = line.split_once(':')?;
+                key.trim().eq_ignore_ascii_case("content-length").then(|| value.trim().parse().ok())?
+            })
+            .unwrap_or(0);
+        if request.len() >= end + 4 + length {
+            let body = String::from_utf8_lossy(&request[end + 4..end + 4 + length]).to_string();
+            return Some(Request { head, body });
+        }
+    }
+}
+
+/// `200 OK` with a body
+pub fn ok(content_type: &str, body: &str) -> String {
+    format!(
+        "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        content_type,
+        body.len(),
+        body
+    )
+}
+
+/// `200 OK` with a JSON body
+pub fn json(body: &Value) -> String {
+    ok("application/json", &body.to_string())
+}
+
+/// `200 OK` event stream with one SSE event per message
+pub fn event_stream(messages: &[Value]) -> String {
+    let mut response =
+        "HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nConnection: close\r\n\r\n".to_string();
+    for message in messages {
+        response.push_str(&format!("event: message\ndata: {}\n\n", message));
+    }
+    response
+}
+
+/// Answer every connection with `handler` until the test ends
+pub async fn serve<F, Fut>(handler: F) -> SocketAddr
+where
+    F: Fn(Request) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = String> + Send + 'static,
+{
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let handler = Arc::new(handler);
+    tokio::spawn(async move {
+        loop {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                let Some(request) = read_request(&mut socket).await else {
+                    return;
+                };
+                let response = handler(request).await;
+                let _ = socket.write_all(response.as_bytes()).await;
+                // Hold the connection until the client closes it
+                let mut buf = [0u8; 1024];
+                let _ = tokio::time::timeout(LINGER, async {
+                    while socket.read(&mut buf).await.is_ok_and(|n| n > 0) {}
+                })
+                .await;
+            });
+        }
+    });
+    addr
+}
+
+/// Answer one connection at a time with `responses`, in order; the handle
+/// returns the requests once every response was sent
+pub async fn serve_in_order(responses: Vec<String>) -> (SocketAddr, JoinHandle<Vec<Request>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for response in responses {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let request = read_request(&mut socket).await.unwrap();
+            socket.write_all(response.as_bytes()).await.unwrap();
+            requests.push(request);
+        }
+        requests
+    });
+    (addr, server)
+}
@@ -0,0 +1,144 @@
+//! Dynamic tool selection for large tool sets
+//!
+//! Tool names and descriptions are embedded with an Ollama embedding model and
+//! kept in an in-memory vector index. For each user message only the `top_k`
+//! closest tools are advertised to the model, which keeps small models from
+//! running out of context when many MCP tools are connected.
+
+use std::sync::Arc;
+
+use anyhow::{Context, Result};
+use rig::{
+    client::EmbeddingsClient,
+    completion::ToolDefinition,
+    embeddings::{Embedding, EmbeddingModel, distance::VectorDistance},
+    providers::ollama,
+    tool::server::{ToolServer, ToolServerError, ToolServerHandle},
+};
+use tokio::sync::Mutex;
+use tracing::debug;
+
+use crate::config::ToolSelectionConfig;
+
+/// A tool in the index, with the text its embedding was made from
+struct IndexedTool {
+    name: String,
+    text: String,
+    embedding: Embedding,
+}
+
+/// In-memory vector index over tool definitions
+///
+/// Clones share the index, so tools are embedded once and reused by later requests.
+#[derive(Clone)]
+pub struct ToolIndex {
+    model: ollama::EmbeddingModel,
+    top_k: usize,
+    tools: Arc<Mutex<Vec<IndexedTool>>>,
+}
+
+impl ToolIndex {
+    /// Create an empty index using the configured embedding model
+    pub fn new(client: &ollama::Client, config: &ToolSelectionConfig) -> Self {
+        Self {
+            model: client.embedding_model(&config.model),
+            top_k: config.top_k.max(1),
+            tools: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Number of tools advertised per message
+    pub fn top_k(&self) -> usize {
+        self.top_k
+    }
+
+    /// Names of the `top_k` tools closest to `query`, best match first
+    ///
+    /// Tools that are new (or whose description changed) are embedded first,
+    /// and tools no longer offered are dropped from the index.
+    pub async fn select(&self, query: &str, tools: &[ToolDefinition]) -> Result<Vec<String>> {
+        let mut index = self.tools.lock().await;
+        index.retain(|entry| tools.iter().any(|tool| tool.name == entry.name && tool_text(tool) == entry.text));
+
+        let missing: Vec<(String, String)> = tools
+            .iter()
+            .filter(|tool| !index.iter().any(|entry| entry.name == tool.name))
+            .map(|tool| (tool.name.clone(), tool_text(tool)))
+            .collect();
+        if !missing.is_empty() {
+            debug!("Embedding {} tool definitions with {}", missing.len(), self.model.model);
+            let embeddings = self
+                .model
+                .embed_texts(missing.iter().map(|(_, text)| text.clone()))
+                .await
+                .with_context(|| format!("Failed to embed tool definitions with {}", self.model.model))?;
+            index.extend(
+                missing
+                    .into_iter()
+                    .zip(embeddings)
+                    .map(|((name, text), embedding)| IndexedTool { name, text, embedding }),
+            );
+        }
+
+        let query = self
+            .model
+            .embed_text(query)
+            .await
+            .with_context(|| format!("Failed to embed the message with {}", self.model.model))?;
+        let mut scored: Vec<(f64, &str)> = index
+            .iter()
+            .map(|entry| (entry.embedding.cosine_similarity(&query, false), entry.name.as_str()))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+        Ok(scored
+            .into_iter()
+            .take(self.top_k)
+            .map(|(_, name)| name.to_string())
+            .collect())
+    }
+}
+
+/// Text embedded for a tool
+fn tool_text(tool: &ToolDefinition) -> String {
+    format!("{}: {}", tool.name, tool.description)
+}
+
+/// Start a tool server that advertises only `tools` and forwards calls to `handle`
+pub fn selected_tool_server(handle: &ToolServerHandle, tools: Vec<ToolDefinition>) -> ToolServerHandle {
+    tools
+        .into_iter()
+        .fold(ToolServer::new(), |server, definition| {
+            server.tool(SelectedTool {
+                definition,
+                handle: handle.clone(),
+            })
+        })
+        .run()
+}
+
+/// A tool advertised for one request, run by the full tool server
+struct SelectedTool {
+    definition: ToolDefinition,
+    handle: ToolServerHandle,
+}
+
+impl rig::tool::Tool for SelectedTool {
+    const NAME: &'static str = "selected_tool";
+    type Error = ToolServerError;
+    type Args = serde_json::Value;
+    type Output = serde_json::Value;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        self.definition.clone()
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        // The output is already serialized, so pass it through unchanged
+        let output = self.handle.call_tool(&self.definition.name, &args.to_string()).await?;
+        Ok(serde_json::from_str(&output).unwrap_or(serde_json::Value::String(output)))
+    }
+
+    fn name(&self) -> String {
+        self.definition.name.clone()
+    }
+}
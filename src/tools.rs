@@ -1,10 +1,17 @@
 //! Built-in tools for the AI agent
 //!
-//! Note: Tool argument structs are defined here for future expansion.
-//! Currently tools are executed directly in the agent module.
+//! Each tool implements [`rig::tool::Tool`] so it can be registered on the agent's
+//! `ToolServer` alongside MCP tools.
+
+use std::process::Stdio;
+
+use anyhow::{Context, Result};
+use rig::{completion::ToolDefinition, tool::Tool, tool::server::ToolServer};
+use serde_json::json;
+
+use crate::approval::{self, ToolApprover};
 
 /// Read a file's contents arguments
-#[allow(dead_code)]
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct ReadFileArgs {
     /// Path to the file to read
@@ -12,7 +19,6 @@ pub struct ReadFileArgs {
 }
 
 /// Write contents to a file arguments
-#[allow(dead_code)]
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct WriteFileArgs {
     /// Path to the file to write
@@ -22,7 +28,6 @@ pub struct WriteFileArgs {
 }
 
 /// List directory contents arguments
-#[allow(dead_code)]
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct ListDirectoryArgs {
     /// Path to the directory to list
@@ -30,23 +35,431 @@ pub struct ListDirectoryArgs {
 }
 
 /// Run a shell command arguments
-#[allow(dead_code)]
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct RunCommandArgs {
     /// Command to run
     pub command: String,
     /// Working directory for the command
+    #[serde(default)]
     pub cwd: Option<String>,
 }
 
 /// Search code with grep arguments
-#[allow(dead_code)]
 #[derive(Debug, serde::Deserialize, serde::Serialize)]
 pub struct SearchCodeArgs {
     /// Pattern to search for
     pub pattern: String,
     /// Directory to search in
+    #[serde(default)]
     pub path: Option<String>,
     /// File pattern to filter (e.g., "*.rs")
+    #[serde(default)]
     pub glob: Option<String>,
 }
+
+/// Error returned by a built-in tool
+#[derive(Debug)]
+pub struct BuiltinToolError(String);
+
+impl std::fmt::Display for BuiltinToolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BuiltinToolError {}
+
+impl From<anyhow::Error> for BuiltinToolError {
+    fn from(e: anyhow::Error) -> Self {
+        // Keep the context chain so the model sees why the tool failed
+        Self(format!("{:#}", e))
+    }
+}
+
+/// Register the built-in tools whose name passes `enabled` on a tool server,
+/// gated by the approver if one is given
+pub fn register_builtin_tools(
+    mut tool_server: ToolServer,
+    approver: Option<&ToolApprover>,
+    enabled: impl Fn(&str) -> bool,
+) -> ToolServer {
+    if enabled(ReadFile::NAME) {
+        tool_server = approval::add_tool(tool_server, ReadFile, approver);
+    }
+    if enabled(WriteFile::NAME) {
+        tool_server = approval::add_tool(tool_server, WriteFile, approver);
+    }
+    if enabled(ListDirectory::NAME) {
+        tool_server = approval::add_tool(tool_server, ListDirectory, approver);
+    }
+    if enabled(RunCommand::NAME) {
+        tool_server = approval::add_tool(tool_server, RunCommand, approver);
+    }
+    if enabled(SearchCode::NAME) {
+        tool_server = approval::add_tool(tool_server, SearchCode, approver);
+    }
+    tool_server
+}
+
+/// Names of all built-in tools
+pub const BUILTIN_TOOL_NAMES: &[&str] = &[
+    ReadFile::NAME,
+    WriteFile::NAME,
+    ListDirectory::NAME,
+    RunCommand::NAME,
+    SearchCode::NAME,
+];
+
+/// Read a file from disk
+pub async fn run_read_file(args: &ReadFileArgs) -> Result<String> {
+    let contents = tokio::fs::read_to_string(&args.path).await.context("Failed to read file")?;
+    Ok(format!("Contents of {}:\n\n{}", args.path, contents))
+}
+
+/// Write a file to disk
+pub async fn run_write_file(args: &WriteFileArgs) -> Result<String> {
+    tokio::fs::write(&args.path, &args.content).await.context("Failed to write file")?;
+    Ok(format!(
+        "Successfully wrote {} bytes to {}",
+        args.content.len(),
+        args.path
+    ))
+}
+
+/// List the entries of a directory
+pub async fn run_list_directory(args: &ListDirectoryArgs) -> Result<String> {
+    let mut entries = tokio::fs::read_dir(&args.path).await.context("Failed to read directory")?;
+
+    let mut result = String::new();
+    while let Some(entry) = entries.next_entry().await.context("Failed to read directory entry")? {
+        let name = entry.file_name();
+        let name_str = name.to_string_lossy();
+        let is_dir = tokio::fs::metadata(entry.path()).await.is_ok_and(|m| m.is_dir());
+        if is_dir {
+            result.push_str(&format!("📁 {}/\n", name_str));
+        } else {
+            result.push_str(&format!("📄 {}\n", name_str));
+        }
+    }
+    Ok(format!("Contents of {}:\n\n{}", args.path, result))
+}
+
+/// Run a shell command with bash
+///
+/// The command gets no stdin and is killed if the request is cancelled.
+pub async fn run_command(args: &RunCommandArgs) -> Result<String> {
+    let mut cmd = tokio::process::Command::new("bash");
+    cmd.arg("-c")
+        .arg(&args.command)
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+    if let Some(cwd) = &args.cwd {
+        cmd.current_dir(cwd);
+    }
+    let output = cmd.output().await.context("Failed to run command")?;
+
+    let mut result = String::new();
+    result.push_str(&format!("Command: {}\n\n", args.command));
+    if !output.stdout.is_empty() {
+        result.push_str("STDOUT:\n");
+        result.push_str(&String::from_utf8_lossy(&output.stdout));
+        result.push('\n');
+    }
+    if !output.stderr.is_empty() {
+        result.push_str("STDERR:\n");
+        result.push_str(&String::from_utf8_lossy(&output.stderr));
+        result.push('\n');
+    }
+    result.push_str(&format!(
+        "Exit code: {}",
+        output.status.code().unwrap_or(-1)
+    ));
+    Ok(result)
+}
+
+/// Search code recursively with grep
+pub async fn run_search_code(args: &SearchCodeArgs) -> Result<String> {
+    let mut cmd = tokio::process::Command::new("grep");
+    cmd.arg("-rn").arg("--color=never");
+    if let Some(glob) = &args.glob {
+        cmd.arg("--include").arg(glob);
+    }
+    // A pattern or path starting with `-` must not be read as an option
+    cmd.arg("-e")
+        .arg(&args.pattern)
+        .arg("--")
+        .arg(args.path.as_deref().unwrap_or("."))
+        .stdin(Stdio::null())
+        .kill_on_drop(true);
+
+    let output = cmd.output().await.context("Failed to run grep")?;
+
+    if output.stdout.is_empty() {
+        Ok("No matches found.".to_string())
+    } else {
+        Ok(format!(
+            "Search results for '{}':\n\n{}",
+            args.pattern,
+            String::from_utf8_lossy(&output.stdout)
+        ))
+    }
+}
+
+/// Built-in tool: read a file
+#[derive(Debug, Clone, Copy)]
+pub struct ReadFile;
+
+impl Tool for ReadFile {
+    const NAME: &'static str = "read_file";
+    type Error = BuiltinToolError;
+    type Args = ReadFileArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Read the contents of a text file on the local machine".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to read"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("🔧 {}(path: {})", Self::NAME, args.path);
+        Ok(run_read_file(&args).await?)
+    }
+}
+
+/// Built-in tool: write a file
+#[derive(Debug, Clone, Copy)]
+pub struct WriteFile;
+
+impl Tool for WriteFile {
+    const NAME: &'static str = "write_file";
+    type Error = BuiltinToolError;
+    type Args = WriteFileArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Write text to a file on the local machine, replacing any existing contents"
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the file to write"
+                    },
+                    "content": {
+                        "type": "string",
+                        "description": "Contents to write to the file"
+                    }
+                },
+                "required": ["path", "content"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("🔧 {}(path: {})", Self::NAME, args.path);
+        Ok(run_write_file(&args).await?)
+    }
+}
+
+/// Built-in tool: list a directory
+#[derive(Debug, Clone, Copy)]
+pub struct ListDirectory;
+
+impl Tool for ListDirectory {
+    const NAME: &'static str = "list_directory";
+    type Error = BuiltinToolError;
+    type Args = ListDirectoryArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "List the files and subdirectories in a directory".to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "path": {
+                        "type": "string",
+                        "description": "Path to the directory to list"
+                    }
+                },
+                "required": ["path"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("🔧 {}(path: {})", Self::NAME, args.path);
+        Ok(run_list_directory(&args).await?)
+    }
+}
+
+/// Built-in tool: run a shell command
+#[derive(Debug, Clone, Copy)]
+pub struct RunCommand;
+
+impl Tool for RunCommand {
+    const NAME: &'static str = "run_command";
+    type Error = BuiltinToolError;
+    type Args = RunCommandArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Run a shell command with bash and return its stdout, stderr and exit code"
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "command": {
+                        "type": "string",
+                        "description": "Command to run"
+                    },
+                    "cwd": {
+                        "type": "string",
+                        "description": "Working directory for the command (defaults to the current directory)"
+                    }
+                },
+                "required": ["command"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("🔧 {}(command: {})", Self::NAME, args.command);
+        Ok(run_command(&args).await?)
+    }
+}
+
+/// Built-in tool: search code with grep
+#[derive(Debug, Clone, Copy)]
+pub struct SearchCode;
+
+impl Tool for SearchCode {
+    const NAME: &'static str = "search_code";
+    type Error = BuiltinToolError;
+    type Args = SearchCodeArgs;
+    type Output = String;
+
+    async fn definition(&self, _prompt: String) -> ToolDefinition {
+        ToolDefinition {
+            name: Self::NAME.to_string(),
+            description: "Search files recursively for a pattern with grep and return matching lines"
+                .to_string(),
+            parameters: json!({
+                "type": "object",
+                "properties": {
+                    "pattern": {
+                        "type": "string",
+                        "description": "Pattern to search for"
+                    },
+                    "path": {
+                        "type": "string",
+                        "description": "Directory to search in (defaults to the current directory)"
+                    },
+                    "glob": {
+                        "type": "string",
+                        "description": "File pattern to filter (e.g., \"*.rs\")"
+                    }
+                },
+                "required": ["pattern"]
+            }),
+        }
+    }
+
+    async fn call(&self, args: Self::Args) -> Result<Self::Output, Self::Error> {
+        tracing::info!("🔧 {}(pattern: {})", Self::NAME, args.pattern);
+        Ok(run_search_code(&args).await?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_builtin_tools_are_registered() {
+        let handle = register_builtin_tools(ToolServer::new(), None, |_| true).run();
+        let defs = handle.get_tool_defs(None).await.unwrap();
+
+        let names: Vec<&str> = defs.iter().map(|d| d.name.as_str()).collect();
+        assert_eq!(names, BUILTIN_TOOL_NAMES);
+        for def in &defs {
+            assert_eq!(def.parameters["type"], "object");
+            assert!(def.parameters["required"].is_array());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_call_through_tool_server() {
+        let dir = std::env::temp_dir().join(format!("pcli2-rig-tools-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("hello.txt");
+        let handle = register_builtin_tools(ToolServer::new(), None, |_| true).run();
+
+        let args = json!({ "path": file, "content": "hello" }).to_string();
+        let written = handle.call_tool(WriteFile::NAME, &args).await.unwrap();
+        assert!(written.contains("wrote 5 bytes"));
+
+        let args = json!({ "path": file }).to_string();
+        let read = handle.call_tool(ReadFile::NAME, &args).await.unwrap();
+        assert!(read.contains("hello"));
+
+        let args = json!({ "path": dir }).to_string();
+        let listing = handle.call_tool(ListDirectory::NAME, &args).await.unwrap();
+        assert!(listing.contains("hello.txt"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_run_command_honors_cwd() {
+        let cwd = std::env::temp_dir();
+        let result = run_command(&RunCommandArgs {
+            command: "pwd".to_string(),
+            cwd: Some(cwd.display().to_string()),
+        })
+        .await
+        .unwrap();
+
+        let canonical = cwd.canonicalize().unwrap();
+        assert!(result.contains(&canonical.display().to_string()));
+        assert!(result.contains("Exit code: 0"));
+    }
+
+    #[tokio::test]
+    async fn test_search_code_pattern_starting_with_dash() {
+        let dir = std::env::temp_dir().join(format!("pcli2-rig-search-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("notes.txt"), "run with --verbose\n").unwrap();
+        std::fs::write(dir.join("other.md"), "--verbose\n").unwrap();
+
+        let result = run_search_code(&SearchCodeArgs {
+            pattern: "--verbose".to_string(),
+            path: Some(dir.display().to_string()),
+            glob: Some("*.txt".to_string()),
+        })
+        .await
+        .unwrap();
+        assert!(result.contains("notes.txt:1:run with --verbose"));
+        assert!(!result.contains("other.md"));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
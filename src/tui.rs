@@ -11,6 +11,8 @@ use std::io;
 use tokio::sync::mpsc;
 use tracing::debug;
 
+use crate::images::ImageRenderer;
+
 /// Terminal event stream
 pub type EventStream = mpsc::UnboundedReceiver<Result<Event>>;
 
@@ -91,6 +93,12 @@ impl Tui {
         Ok(())
     }
 
+    /// Draw the inline images placed while rendering the last frame
+    pub fn paint_images(&mut self, images: &mut ImageRenderer) -> Result<()> {
+        let area = self.area();
+        images.paint(self.terminal.backend_mut(), (area.width, area.height))
+    }
+
     /// Get the terminal area
     pub fn area(&self) -> ratatui::layout::Rect {
         let size = self.terminal.size().unwrap_or_default();
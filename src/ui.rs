@@ -10,7 +10,11 @@ use ratatui::{
 use tui_markdown::from_str;
 use ansi_to_tui::IntoText;
 
-use crate::app::App;
+use std::rc::Rc;
+
+use crate::app::{App, ModelPicker};
+use crate::images::RenderedImage;
+use crate::mcp::{HealthState, ToolProgress};
 
 /// Colors for the dark theme (warm color palette)
 mod colors {
@@ -60,12 +64,25 @@ pub fn render(frame: &mut Frame, app: &mut App) {
         render_help_modal(frame, app, area);
     }
 
+    // Render model picker if open
+    if let Some(picker) = app.model_picker() {
+        render_model_picker(frame, picker, app.agent().model_name(), area);
+    }
+
+    // Render YOLO confirmation if needed
+    if app.confirming_yolo() {
+        render_yolo_confirmation(frame, app, area);
+    }
+
     // Render tool confirmation dialog if needed
     if app.has_pending_tool_call() {
         render_tool_confirmation(frame, app, area);
     }
 }
 
+/// Number of lines of tool output shown in the chat pane
+const TOOL_RESULT_PREVIEW_LINES: usize = 8;
+
 /// Render the chat history
 fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
     let border_color = if is_focused {
@@ -91,6 +108,9 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
     // Build all lines with background colors
     let mut all_lines: Vec<(Line, Option<ratatui::style::Color>)> = Vec::new();
     let total_messages = history.len();
+    // Inline images, by the index of their first line
+    let mut images: Vec<(usize, Rc<RenderedImage>)> = Vec::new();
+    let mut image_number = 0;
 
     // Add ASCII banner if terminal is wide enough (64+ chars) and tall enough (10+ lines)
     if area.width >= 64 && area.height >= 10 {
@@ -114,7 +134,7 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
                 };
 
                 // Interpolate RGB values from warm orange to golden yellow
-                let r = (255.0 + (255.0 - 255.0) * t) as u8;
+                let r = 255u8;
                 let g = (130.0 + (200.0 - 130.0) * t) as u8;
                 let b = (60.0 + (80.0 - 60.0) * t) as u8;
 
@@ -134,7 +154,7 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
     for (idx, msg) in history.iter().enumerate() {
         let bg_color = match msg.role {
             crate::agent::MessageRole::User => Some(colors::USER_BG),
-            crate::agent::MessageRole::Assistant => Some(colors::ASSISTANT_BG),
+            crate::agent::MessageRole::Assistant | crate::agent::MessageRole::Notice => Some(colors::ASSISTANT_BG),
             crate::agent::MessageRole::System => Some(colors::ASSISTANT_BG),
             crate::agent::MessageRole::ToolResult => Some(colors::USER_BG),
         };
@@ -146,7 +166,7 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
                     .fg(colors::ACCENT_WARM_ORANGE)
                     .add_modifier(Modifier::BOLD),
             ),
-            crate::agent::MessageRole::Assistant => (
+            crate::agent::MessageRole::Assistant | crate::agent::MessageRole::Notice => (
                 "🤖 Assistant:",
                 Style::default()
                     .fg(colors::ACCENT_WARM_ORANGE)
@@ -170,11 +190,40 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
         all_lines.push((Line::from(Span::styled(prefix, style)), bg_color));
 
         // Render content - use markdown for assistant messages
-        if msg.role == crate::agent::MessageRole::Assistant {
+        if matches!(msg.role, crate::agent::MessageRole::Assistant | crate::agent::MessageRole::Notice) {
             let markdown_text = from_str(&msg.content);
             for line in markdown_text.lines {
                 all_lines.push((line, bg_color));
             }
+            for call in &msg.tool_calls {
+                all_lines.push((
+                    Line::from(Span::styled(
+                        format!("→ {}({})", call.tool_name, call.arguments),
+                        Style::default().fg(colors::ACCENT_PURPLE),
+                    )),
+                    bg_color,
+                ));
+            }
+        } else if msg.role == crate::agent::MessageRole::ToolResult {
+            // Tool output can be long; show only the start of it
+            let max_width = area.width.saturating_sub(20) as usize;
+            let content = format_msg_content(&msg.content, max_width.max(40));
+            let total = content.lines().count();
+            for line in content.lines().take(TOOL_RESULT_PREVIEW_LINES) {
+                all_lines.push((
+                    Line::from(Span::styled(line.to_string(), Style::default().fg(colors::DIM))),
+                    bg_color,
+                ));
+            }
+            if total > TOOL_RESULT_PREVIEW_LINES {
+                all_lines.push((
+                    Line::from(Span::styled(
+                        format!("… {} more lines", total - TOOL_RESULT_PREVIEW_LINES),
+                        Style::default().fg(colors::DIM).add_modifier(Modifier::ITALIC),
+                    )),
+                    bg_color,
+                ));
+            }
         } else {
             // Calculate dynamic max width based on terminal width (subtract prefix and padding)
             let max_width = area.width.saturating_sub(20) as usize;
@@ -190,6 +239,29 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
             }
         }
 
+
+        // Images attached by the user or returned by a tool
+        for attachment in &msg.attachments {
+            let label = if attachment.is_image() {
+                image_number += 1;
+                format!("🖼 [{}] {} · /save-image {}", image_number, attachment.label(), image_number)
+            } else {
+                format!("📎 {}", attachment.label())
+            };
+            all_lines.push((
+                Line::from(Span::styled(label, Style::default().fg(colors::ACCENT_PURPLE))),
+                bg_color,
+            ));
+            if attachment.is_image()
+                && let Some(image) = app.images().render(attachment, area.width.saturating_sub(2))
+            {
+                images.push((all_lines.len(), image.clone()));
+                for line in &image.lines {
+                    all_lines.push((line.clone(), bg_color));
+                }
+            }
+        }
+
         // Add single spacing line between messages (not after the last one)
         if idx < total_messages - 1 {
             all_lines.push((Line::from(""), bg_color));
@@ -258,6 +330,28 @@ fn render_chat(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
     let chat = List::new(items).block(block);
 
     frame.render_widget(chat, area);
+
+    // Draw fully visible images with the terminal's graphics protocol, unless a
+    // dialog covers the chat; partly visible ones keep their half-block rendering
+    if app.has_dialog() {
+        return;
+    }
+    for (start, image) in images {
+        if start < scroll_start || start + usize::from(image.height) > scroll_start + visible_height {
+            continue;
+        }
+        let x = area.x + 1;
+        let y = area.y + 1 + (start - scroll_start) as u16;
+        app.images().place(&image, x, y);
+        // Keep ratatui from drawing over the image
+        for row in y..y + image.height {
+            for col in x..x + image.width {
+                if let Some(cell) = frame.buffer_mut().cell_mut((col, row)) {
+                    cell.set_skip(true);
+                }
+            }
+        }
+    }
 }
 
 /// Render the input area with visible blinking cursor
@@ -322,11 +416,35 @@ fn render_input(frame: &mut Frame, app: &App, area: Rect, is_focused: bool) {
                 .title({
                     let model = app.agent().model_name();
                     let mcp_count = app.agent().mcp_server_count();
-                    if mcp_count > 0 {
-                        format!(" Input │ {} │ 🔌{} ", model, mcp_count)
+                    let mut title = vec![Span::raw(if mcp_count > 0 {
+                        format!(" Input │ {} │ 🔌{}", model, mcp_count)
                     } else {
                         format!(" Input │ {} ", model)
+                    })];
+                    if mcp_count > 0 {
+                        // One dot per MCP server, colored by its health
+                        title.push(Span::raw(" "));
+                        for client in app.agent().mcp_clients() {
+                            let color = match client.health().state {
+                                HealthState::Up => colors::ACCENT_GREEN,
+                                HealthState::Degraded => colors::ACCENT_YELLOW,
+                                HealthState::Down => colors::ERROR_RED,
+                            };
+                            title.push(Span::styled("●", Style::default().fg(color)));
+                        }
+                        title.push(Span::raw(" "));
+                    }
+                    if app.is_yolo() {
+                        title.push(Span::styled(
+                            " YOLO ",
+                            Style::default()
+                                .fg(colors::BACKGROUND)
+                                .bg(colors::ERROR_RED)
+                                .add_modifier(Modifier::BOLD),
+                        ));
+                        title.push(Span::raw(" "));
                     }
+                    Line::from(title)
                 })
                 .title_style(Style::default().fg(border_color))
                 .borders(Borders::ALL)
@@ -409,6 +527,15 @@ fn render_status(frame: &mut Frame, app: &App, area: Rect) {
         format!(" {} ", app.status())
     };
 
+    // Show progress reported by MCP servers for running tool calls
+    if app.is_thinking() {
+        for client in app.agent().mcp_clients() {
+            for call in client.tool_progress() {
+                status_text = format!("{}  {}", status_text, progress_text(client.name(), &call));
+            }
+        }
+    }
+
     // Add CPU sparkline when thinking
     if app.is_thinking() {
         let cpu_history = app.cpu_history();
@@ -435,6 +562,27 @@ fn render_status(frame: &mut Frame, app: &App, area: Rect) {
     frame.render_widget(status, area);
 }
 
+/// Progress of a tool call: a bar when the total is known, else the count so far
+fn progress_text(server: &str, call: &ToolProgress) -> String {
+    const WIDTH: usize = 10;
+    let progress = match call.fraction() {
+        Some(fraction) => {
+            let filled = (fraction * WIDTH as f64).round() as usize;
+            format!(
+                "{}{} {:.0}%",
+                "█".repeat(filled),
+                "░".repeat(WIDTH - filled),
+                fraction * 100.0
+            )
+        }
+        None => format!("{}", call.progress.unwrap_or_default()),
+    };
+    match &call.message {
+        Some(message) => format!("⏳ {}/{} {} {}", server, call.tool, progress, message),
+        None => format!("⏳ {}/{} {}", server, call.tool, progress),
+    }
+}
+
 /// Render tool confirmation dialog
 fn render_tool_confirmation(frame: &mut Frame, app: &App, area: Rect) {
     // Create centered dialog
@@ -464,7 +612,7 @@ fn render_tool_confirmation(frame: &mut Frame, app: &App, area: Rect) {
             Line::from(format!("Arguments: {}", pending.arguments)),
             Line::from(""),
             Line::from(Span::styled(
-                "Execute this tool? (Y/n)",
+                "[Y] once  [S] session  [A] always  [N] decline",
                 Style::default().fg(colors::FOREGROUND),
             )),
             Line::from(""),
@@ -483,6 +631,115 @@ fn render_tool_confirmation(frame: &mut Frame, app: &App, area: Rect) {
     }
 }
 
+/// Render the "enable YOLO mode?" confirmation
+fn render_yolo_confirmation(frame: &mut Frame, app: &App, area: Rect) {
+    let dialog_width = 60.min(area.width.saturating_sub(4));
+    let dialog_height = 9.min(area.height.saturating_sub(4));
+    let dialog_area = Rect::new(
+        (area.width - dialog_width) / 2,
+        (area.height - dialog_height) / 2,
+        dialog_width,
+        dialog_height,
+    );
+
+    frame.render_widget(ratatui::widgets::Clear, dialog_area);
+
+    let mcp_count = app.agent().mcp_server_count();
+    let mut lines = vec![
+        Line::from(""),
+        Line::from("Tool calls will run without asking, including ones"),
+        Line::from("that write files or run shell commands."),
+    ];
+    if mcp_count > 0 {
+        lines.push(Line::from(Span::styled(
+            format!("{} MCP server(s) connected can modify data too.", mcp_count),
+            Style::default().fg(colors::ACCENT_YELLOW),
+        )));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "[Y] enable YOLO mode  [any other key] cancel",
+        Style::default().fg(colors::FOREGROUND),
+    )));
+
+    let dialog = Paragraph::new(lines).block(
+        Block::default()
+            .title(" Enable YOLO mode? ")
+            .title_style(
+                Style::default()
+                    .fg(colors::ERROR_RED)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(colors::ERROR_RED))
+            .style(Style::default().bg(colors::BACKGROUND)),
+    );
+
+    frame.render_widget(dialog, dialog_area);
+}
+
+/// Render the `/model` picker
+fn render_model_picker(frame: &mut Frame, picker: &ModelPicker, current: &str, area: Rect) {
+    let dialog_width = 76.min(area.width.saturating_sub(4));
+    let dialog_height = (picker.models.len() as u16 + 5).min(area.height.saturating_sub(4));
+    let dialog_area = Rect::new(
+        (area.width - dialog_width) / 2,
+        (area.height - dialog_height) / 2,
+        dialog_width,
+        dialog_height,
+    );
+
+    frame.render_widget(ratatui::widgets::Clear, dialog_area);
+
+    // Keep the highlighted model in view
+    let visible = dialog_height.saturating_sub(5) as usize;
+    let first = picker.selected.saturating_sub(visible.saturating_sub(1));
+    let name_width = picker.models.iter().map(|m| m.name.len()).max().unwrap_or(0);
+
+    let mut lines = vec![Line::from("")];
+    for (i, model) in picker.models.iter().enumerate().skip(first).take(visible) {
+        let tools = match model.supports_tools() {
+            Some(true) => "🔧 tools",
+            Some(false) => "no tools",
+            None => "",
+        };
+        let text = format!(
+            "{} {:<name_width$}  {:>8}  {:>6}  {:<7}  {}",
+            if model.name == current { "●" } else { " " },
+            model.name,
+            model.size_label(),
+            model.parameter_size.as_deref().unwrap_or(""),
+            model.quantization.as_deref().unwrap_or(""),
+            tools,
+        );
+        let style = if i == picker.selected {
+            Style::default()
+                .fg(colors::BACKGROUND)
+                .bg(colors::ACCENT_WARM_ORANGE)
+                .add_modifier(Modifier::BOLD)
+        } else {
+            Style::default().fg(colors::FOREGROUND)
+        };
+        lines.push(Line::from(Span::styled(text, style)));
+    }
+    lines.push(Line::from(""));
+    lines.push(Line::from(Span::styled(
+        "[↑/↓] select  [Enter] switch  [Esc] cancel",
+        Style::default().fg(colors::DIM),
+    )));
+
+    let dialog = Paragraph::new(lines).block(
+        Block::default()
+            .title(" Models ")
+            .title_style(Style::default().fg(colors::ACCENT_WARM_ORANGE))
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(colors::ACCENT_WARM_ORANGE))
+            .style(Style::default().bg(colors::BACKGROUND)),
+    );
+
+    frame.render_widget(dialog, dialog_area);
+}
+
 /// Render help modal dialog with scrollable text
 fn render_help_modal(frame: &mut Frame, app: &App, area: Rect) {
     // Create centered dialog (80% width, 90% height)
@@ -559,6 +816,8 @@ fn render_help_modal(frame: &mut Frame, app: &App, area: Rect) {
             || line.trim().starts_with("Home")
             || line.trim().starts_with("End")
             || line.trim().starts_with("Y/")
+            || line.trim().starts_with("S  ")
+            || line.trim().starts_with("A  ")
             || line.trim().starts_with("N/")
         {
             styled_lines.push(Line::from(Span::styled(